@@ -14,6 +14,11 @@ use std::io::Write;
 use std::path::PathBuf;
 
 fn main() {
+    // Host builds (the `std` feature) link with the platform's own linker scripts
+    if env::var_os("CARGO_FEATURE_RP2040").is_none() {
+        return;
+    }
+
     // Put `memory.x` in our output directory and ensure it's
     // on the linker search path.
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
@@ -1,11 +1,50 @@
+use async_io::Async;
 use embassy_executor::Spawner;
 use embassy_time::Timer;
+use embedded_io_adapters::futures_03::FromFutures;
+use embedded_io_async::{Read, Write};
 use log::*;
+use nix::sys::termios::BaudRate;
+
+mod serial_config;
+mod serial_port;
+
+use serial_config::SerialConfig;
+use serial_port::SerialPort;
+
+type Port = FromFutures<Async<SerialPort>>;
+
+/// Open a serial port at `baud` for use from async tasks
+fn open_port(path: &str, baud: BaudRate) -> std::io::Result<Port> {
+    let port = SerialPort::new(path, baud)?;
+    Ok(FromFutures::new(Async::new(port)?))
+}
+
+/// Log what the host sends on the input port
+#[embassy_executor::task]
+async fn serial_input(mut port: Port) {
+    let mut buf = [0u8; 256];
+    loop {
+        match port.read(&mut buf).await {
+            Ok(n) => info!(
+                "Received: {}",
+                String::from_utf8_lossy(&buf[..n]).trim_end()
+            ),
+            Err(err) => {
+                error!("Serial read failed: {:?}", err);
+                Timer::after_secs(1).await;
+            }
+        }
+    }
+}
 
 #[embassy_executor::task]
-async fn run() {
+async fn run(mut port: Port) {
     loop {
         info!("tick");
+        if let Err(err) = port.write_all(b"tick\n").await {
+            error!("Serial write failed: {:?}", err);
+        }
         Timer::after_secs(1).await;
     }
 }
@@ -17,5 +56,33 @@ async fn main(spawner: Spawner) {
         .format_timestamp_nanos()
         .init();
 
-    spawner.spawn(run().unwrap());
+    let config = match SerialConfig::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Unsupported baud rate: {}", err.0);
+            return;
+        }
+    };
+    info!(
+        "Serial in: {}, out: {}, baud: {:?}",
+        config.input_path, config.output_path, config.baud
+    );
+
+    let (input, output) = match (
+        open_port(&config.input_path, config.baud),
+        open_port(&config.output_path, config.baud),
+    ) {
+        (Ok(input), Ok(output)) => (input, output),
+        (Err(err), _) => {
+            error!("Failed to open {}: {}", config.input_path, err);
+            return;
+        }
+        (_, Err(err)) => {
+            error!("Failed to open {}: {}", config.output_path, err);
+            return;
+        }
+    };
+
+    spawner.spawn(serial_input(input).unwrap());
+    spawner.spawn(run(output).unwrap());
 }
@@ -0,0 +1,76 @@
+use nix::sys::termios::BaudRate;
+
+pub const DEFAULT_SERIAL_IN: &str = "/tmp/ttyV0";
+pub const DEFAULT_SERIAL_OUT: &str = "/tmp/ttyV1";
+pub const DEFAULT_BAUD: BaudRate = BaudRate::B115200;
+
+/// Serial port settings for the simulator, read from the environment
+#[derive(Debug)]
+pub struct SerialConfig {
+    pub input_path: String,
+    pub output_path: String,
+    pub baud: BaudRate,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnsupportedBaudRate(pub String);
+
+impl SerialConfig {
+    /// Read `REFLOW_SERIAL_IN`, `REFLOW_SERIAL_OUT` and `REFLOW_BAUD`, falling
+    /// back to the defaults for any that are unset.
+    pub fn from_env() -> Result<Self, UnsupportedBaudRate> {
+        let input_path =
+            std::env::var("REFLOW_SERIAL_IN").unwrap_or_else(|_| DEFAULT_SERIAL_IN.to_string());
+        let output_path =
+            std::env::var("REFLOW_SERIAL_OUT").unwrap_or_else(|_| DEFAULT_SERIAL_OUT.to_string());
+        let baud = match std::env::var("REFLOW_BAUD") {
+            Ok(baud) => parse_baud_rate(&baud)?,
+            Err(_) => DEFAULT_BAUD,
+        };
+
+        Ok(Self {
+            input_path,
+            output_path,
+            baud,
+        })
+    }
+}
+
+/// Map a baud rate string such as "115200" to the termios constant
+pub fn parse_baud_rate(baud: &str) -> Result<BaudRate, UnsupportedBaudRate> {
+    match baud.trim() {
+        "9600" => Ok(BaudRate::B9600),
+        "19200" => Ok(BaudRate::B19200),
+        "38400" => Ok(BaudRate::B38400),
+        "57600" => Ok(BaudRate::B57600),
+        "115200" => Ok(BaudRate::B115200),
+        "230400" => Ok(BaudRate::B230400),
+        "460800" => Ok(BaudRate::B460800),
+        "921600" => Ok(BaudRate::B921600),
+        other => Err(UnsupportedBaudRate(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_supported_baud_rates() {
+        assert_eq!(parse_baud_rate("9600"), Ok(BaudRate::B9600));
+        assert_eq!(parse_baud_rate("115200"), Ok(BaudRate::B115200));
+        assert_eq!(parse_baud_rate(" 921600\n"), Ok(BaudRate::B921600));
+    }
+
+    #[test]
+    fn rejects_unsupported_baud_rates() {
+        assert_eq!(
+            parse_baud_rate("12345"),
+            Err(UnsupportedBaudRate("12345".to_string()))
+        );
+        assert_eq!(
+            parse_baud_rate("fast"),
+            Err(UnsupportedBaudRate("fast".to_string()))
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use core::fmt::Write;
+
+#[cfg(feature = "rp2040")]
+use {
+    crate::{state_receiver, DisplayResources, INIT_PROGRESS},
+    core::cell::RefCell,
+    defmt::{info, warn, Debug2Format},
+    display_interface_spi::SPIInterface,
+    embassy_embedded_hal::shared_bus::blocking::spi::SpiDevice,
+    embassy_futures::select::{select3, Either3},
+    embassy_rp::gpio::{Level, Output},
+    embassy_rp::spi::{self, Spi},
+    embassy_sync::blocking_mutex::raw::NoopRawMutex,
+    embassy_sync::blocking_mutex::Mutex,
+    embassy_time::Delay,
+    mipidsi::models::ST7789,
+    mipidsi::options::{ColorInversion, Orientation, Rotation},
+    mipidsi::Builder,
+};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoTextStyle,
+    },
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::Text,
+};
+use heapless::String;
+
+use crate::{ReflowControllerState, Status};
+
+/// Panel resolution of the ST7789
+pub const DISPLAY_WIDTH: u16 = 240;
+pub const DISPLAY_HEIGHT: u16 = 240;
+
+#[cfg(feature = "rp2040")]
+const DISPLAY_SPI_FREQUENCY: u32 = 32_000_000;
+
+const LINE_HEIGHT: i32 = 24;
+const MARGIN: i32 = 8;
+
+/// Buttons next to the display used to switch pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ButtonPress {
+    Previous,
+    Next,
+}
+
+pub static DISPLAY_BUTTON_CHANNEL: Channel<CriticalSectionRawMutex, ButtonPress, 2> =
+    Channel::new();
+
+/// Pages the user can cycle through with the display buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Page {
+    Status,
+    Profile,
+    System,
+}
+
+impl Page {
+    const ALL: [Page; 3] = [Page::Status, Page::Profile, Page::System];
+
+    pub fn navigate(self, button: ButtonPress) -> Page {
+        let index = Page::ALL.iter().position(|page| *page == self).unwrap_or(0);
+        let count = Page::ALL.len();
+        let next = match button {
+            ButtonPress::Next => (index + 1) % count,
+            ButtonPress::Previous => (index + count - 1) % count,
+        };
+        Page::ALL[next]
+    }
+}
+
+fn status_text(status: &Status) -> &'static str {
+    match status {
+        Status::Initializing => "Initializing",
+        Status::Idle => "Idle",
+        Status::Running => "Running",
+        Status::CoolingDown => "Cooling Down",
+        Status::Baking => "Baking",
+        Status::Finished => "Finished",
+        Status::Error => "Error",
+    }
+}
+
+fn status_color(status: &Status) -> Rgb565 {
+    match status {
+        Status::Running | Status::Baking => Rgb565::RED,
+        Status::CoolingDown => Rgb565::CYAN,
+        Status::Finished => Rgb565::GREEN,
+        Status::Error => Rgb565::YELLOW,
+        Status::Initializing | Status::Idle => Rgb565::WHITE,
+    }
+}
+
+fn draw_line<D>(display: &mut D, row: i32, text: &str, color: Rgb565) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    let style = MonoTextStyle::new(&FONT_10X20, color);
+    Text::new(text, Point::new(MARGIN, LINE_HEIGHT * (row + 1)), style).draw(display)?;
+    Ok(())
+}
+
+fn draw_progress_bar<D>(display: &mut D, row: i32, progress_pct: u8) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    let width = DISPLAY_WIDTH as i32 - 2 * MARGIN;
+    let top_left = Point::new(MARGIN, LINE_HEIGHT * row + 6);
+    Rectangle::new(top_left, Size::new(width as u32, 12))
+        .into_styled(PrimitiveStyle::with_stroke(Rgb565::WHITE, 1))
+        .draw(display)?;
+    let filled = width * progress_pct.min(100) as i32 / 100;
+    Rectangle::new(top_left, Size::new(filled as u32, 12))
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::GREEN))
+        .draw(display)?;
+    Ok(())
+}
+
+/// Boot screen shown until the controller publishes its first state
+pub fn draw_splash<D>(display: &mut D, progress_pct: u8) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    display.clear(Rgb565::BLACK)?;
+    draw_line(display, 2, "Reflow Controller", Rgb565::WHITE)?;
+    let style = MonoTextStyle::new(&FONT_6X10, Rgb565::WHITE);
+    Text::new(crate::VERSION, Point::new(MARGIN, LINE_HEIGHT * 4), style).draw(display)?;
+    draw_progress_bar(display, 6, progress_pct)
+}
+
+/// Render one page of the controller state
+pub fn draw_page<D>(
+    display: &mut D,
+    page: Page,
+    state: &ReflowControllerState,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    display.clear(Rgb565::BLACK)?;
+    draw_line(display, 0, status_text(&state.status), status_color(&state.status))?;
+
+    let mut line: String<32> = String::new();
+    match page {
+        Page::Status => {
+            let _ = write!(
+                line,
+                "Temp   {:.1}C {:+.1}/s",
+                state.current_temperature, state.temperature_rate
+            );
+            draw_line(display, 2, &line, Rgb565::WHITE)?;
+            line.clear();
+            let _ = write!(line, "Target {:.1}C", state.target_temperature);
+            draw_line(display, 3, &line, Rgb565::WHITE)?;
+            line.clear();
+            let _ = write!(line, "Heater {}%", state.heater_power);
+            draw_line(display, 4, &line, Rgb565::WHITE)?;
+            line.clear();
+            let _ = write!(line, "Fan    {}%", state.fan_speed);
+            draw_line(display, 5, &line, Rgb565::WHITE)?;
+            if state.status == Status::Running {
+                line.clear();
+                let _ = write!(line, "{} {}s", state.current_step, state.step_time_remaining);
+                draw_line(display, 6, &line, Rgb565::WHITE)?;
+                draw_progress_bar(display, 7, state.total_progress_pct)?;
+                line.clear();
+                let _ = write!(line, "{:+.1}C to go", state.step_temp_delta);
+                draw_line(display, 8, &line, Rgb565::WHITE)?;
+            }
+        }
+        Page::Profile => {
+            draw_line(display, 2, &state.current_profile, Rgb565::WHITE)?;
+            let _ = write!(
+                line,
+                "Step {}/{}",
+                state.current_step_index + 1,
+                state.total_steps
+            );
+            draw_line(display, 3, &line, Rgb565::WHITE)?;
+            line.clear();
+            let _ = write!(line, "Length {}s", state.profile_duration_s);
+            draw_line(display, 4, &line, Rgb565::WHITE)?;
+            draw_line(display, 5, state.current_step, Rgb565::WHITE)?;
+        }
+        Page::System => {
+            let _ = write!(line, "Kp {:.2}", state.kp);
+            draw_line(display, 2, &line, Rgb565::WHITE)?;
+            line.clear();
+            let _ = write!(line, "Ki {:.2}", state.ki);
+            draw_line(display, 3, &line, Rgb565::WHITE)?;
+            line.clear();
+            let _ = write!(line, "Kd {:.2}", state.kd);
+            draw_line(display, 4, &line, Rgb565::WHITE)?;
+            draw_line(display, 6, &state.notice, Rgb565::YELLOW)?;
+        }
+    }
+
+    if state.status == Status::Error {
+        let style = MonoTextStyle::new(&FONT_6X10, Rgb565::YELLOW);
+        Text::new(
+            &state.error_message,
+            Point::new(MARGIN, DISPLAY_HEIGHT as i32 - MARGIN),
+            style,
+        )
+        .draw(display)?;
+    }
+    Ok(())
+}
+
+/// Drive the ST7789 TFT: show the splash screen during init, then redraw the
+/// selected page whenever the controller state changes or a page button is pressed.
+#[cfg(feature = "rp2040")]
+#[embassy_executor::task]
+pub async fn display_task(r: DisplayResources) {
+    let mut config = spi::Config::default();
+    config.frequency = DISPLAY_SPI_FREQUENCY;
+    let spi = Spi::new_blocking_txonly(r.spi, r.clk, r.mosi, config);
+    let spi_bus: Mutex<NoopRawMutex, _> = Mutex::new(RefCell::new(spi));
+    let display_spi = SpiDevice::new(&spi_bus, Output::new(r.cs, Level::High));
+
+    let dc = Output::new(r.dc, Level::Low);
+    let rst = Output::new(r.rst, Level::Low);
+    let _backlight = Output::new(r.backlight, Level::High);
+
+    let interface = SPIInterface::new(display_spi, dc);
+    let mut display = match Builder::new(ST7789, interface)
+        .display_size(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        .reset_pin(rst)
+        .invert_colors(ColorInversion::Inverted)
+        .orientation(Orientation::new().rotate(Rotation::Deg0))
+        .init(&mut Delay)
+    {
+        Ok(display) => display,
+        Err(err) => {
+            warn!("Display init failed: {}", Debug2Format(&err));
+            return;
+        }
+    };
+    info!("Display initialized");
+
+    let Some(mut state_receiver) = state_receiver("display_task") else {
+        return;
+    };
+    let Some(mut progress_receiver) = INIT_PROGRESS.receiver() else {
+        warn!("No INIT_PROGRESS receiver left for display_task");
+        return;
+    };
+
+    let mut state: Option<ReflowControllerState> = None;
+    let mut init_progress = 0;
+    let mut page = Page::Status;
+    loop {
+        let result = match &state {
+            Some(state) => draw_page(&mut display, page, state),
+            None => draw_splash(&mut display, init_progress),
+        };
+        if let Err(err) = result {
+            warn!("Display draw failed: {}", Debug2Format(&err));
+        }
+
+        match select3(
+            state_receiver.changed(),
+            DISPLAY_BUTTON_CHANNEL.receive(),
+            progress_receiver.changed(),
+        )
+        .await
+        {
+            Either3::First(new_state) => state = Some(new_state),
+            Either3::Second(button) => page = page.navigate(button),
+            Either3::Third(progress) => init_progress = progress,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temperature_sensor::ControlSensor;
+    use embedded_graphics::mock_display::MockDisplay;
+
+    const STATUSES: [Status; 7] = [
+        Status::Initializing,
+        Status::Idle,
+        Status::Running,
+        Status::CoolingDown,
+        Status::Baking,
+        Status::Finished,
+        Status::Error,
+    ];
+
+    fn state(status: Status) -> ReflowControllerState {
+        let mut error_message = heapless::String::new();
+        let _ = error_message.push_str("Overtemperature");
+        let mut notice = heapless::String::new();
+        let _ = notice.push_str("No SD card");
+        ReflowControllerState {
+            schema_version: crate::SCHEMA_VERSION,
+            status,
+            target_temperature: 150.0,
+            current_temperature: 142.5,
+            board_temperature: 0.0,
+            control_sensor: ControlSensor::Air,
+            temperature_rate: 1.5,
+            rate_warning: false,
+            door_closed: true,
+            fan: false,
+            fan_speed: 0,
+            light: false,
+            heater_power: 80,
+            pid_output_raw: 80,
+            timer: 100,
+            current_step: "Preheat",
+            current_step_index: 0,
+            total_steps: 6,
+            step_temp_reached: false,
+            step_time_met: false,
+            step_time_remaining: 20,
+            step_temp_delta: 7.5,
+            total_progress_pct: 40,
+            profile_duration_s: 330,
+            bake_remaining_s: 0,
+            loop_time_ms: 100,
+            uptime_s: 10,
+            i2c_errors: 0,
+            kp: 2.0,
+            ki: 0.1,
+            kd: 0.5,
+            cycles_completed: 3,
+            finish_reason: None,
+            current_profile: heapless::String::try_from("Lead Free").unwrap(),
+            active_profile_checksum: 0,
+            error_message,
+            fault_latched: false,
+            safe_to_open: false,
+            notice,
+        }
+    }
+
+    /// A 64x64 mock that tolerates the rest of the 240x240 frame being drawn
+    fn display() -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        display.set_allow_out_of_bounds_drawing(true);
+        display.set_allow_overdraw(true);
+        display
+    }
+
+    fn lit_pixels(display: &MockDisplay<Rgb565>) -> usize {
+        display
+            .bounding_box()
+            .points()
+            .filter(|&point| {
+                display
+                    .get_pixel(point)
+                    .is_some_and(|color| color != Rgb565::BLACK)
+            })
+            .count()
+    }
+
+    #[test]
+    fn every_page_draws_for_every_status() {
+        for status in STATUSES {
+            for page in Page::ALL {
+                let mut display = display();
+                draw_page(&mut display, page, &state(status.clone())).unwrap();
+                // At least the status line lands in the mock's corner
+                assert!(lit_pixels(&display) > 0, "{:?} page drew nothing", page);
+            }
+        }
+    }
+
+    #[test]
+    fn splash_draws() {
+        for progress_pct in [0, 50, 100, 255] {
+            let mut display = display();
+            draw_splash(&mut display, progress_pct).unwrap();
+            assert!(lit_pixels(&display) > 0);
+        }
+    }
+
+    #[test]
+    fn next_wraps_around_the_pages() {
+        assert_eq!(Page::Status.navigate(ButtonPress::Next), Page::Profile);
+        assert_eq!(Page::Profile.navigate(ButtonPress::Next), Page::System);
+        assert_eq!(Page::System.navigate(ButtonPress::Next), Page::Status);
+    }
+
+    #[test]
+    fn previous_wraps_around_the_pages() {
+        assert_eq!(Page::Status.navigate(ButtonPress::Previous), Page::System);
+        assert_eq!(Page::System.navigate(ButtonPress::Previous), Page::Profile);
+        assert_eq!(Page::Profile.navigate(ButtonPress::Previous), Page::Status);
+    }
+}
@@ -1,8 +1,18 @@
-use crate::{relay::RelayController, I2c0Bus, HEATER_POWER, SYSTEM_TICK_MILLIS};
+#[cfg(feature = "rp2040")]
+use {
+    crate::relay::RELAY_BOARD_DEFAULT_ADDR, crate::I2c0Bus,
+    embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice,
+};
+use crate::{relay::RelayController, HEATER_POWER, SYSTEM_TICK_MILLIS};
+use crate::{BusDevice, I2cErrorReport, I2C_ERROR_CHANNEL};
 use defmt::{error, info, warn, Debug2Format};
-use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_futures::select::{select, Either};
 use embassy_time::Timer;
 use embedded_hal_async::i2c::I2c;
+use serde::Serialize;
 
 async fn set_heater_relays<I2C, E>(
     relay_controller: &mut RelayController<I2C, E>,
@@ -34,6 +44,74 @@ where
     Ok(())
 }
 
+/// Set for active-low relay boards, see `RelayController::with_options`
+#[cfg(feature = "rp2040")]
+const RELAY_BOARD_INVERTED: bool = false;
+
+/// Relay driving the cooling fan
+const FAN_RELAY: u8 = 1;
+
+/// Relay channel driving the solid state relay for `HeaterCommand::SetPwmPower`
+const SSR_RELAY: u8 = 2;
+
+/// Map a fan speed or power of 0-100 onto the relay board's 0-255 PWM range
+fn percent_to_pwm(percent: u8) -> u8 {
+    (percent.min(100) as u16 * 255 / 100) as u8
+}
+
+/// Power the heater may actually apply. While the cooling interlock is engaged
+/// the heat relays are forced off regardless of the commanded power.
+fn interlocked_power(power: u8, cooling_interlock: bool) -> u8 {
+    if cooling_interlock {
+        0
+    } else {
+        power
+    }
+}
+
+/// Publish the relay duty counters after this many time slots (one minute)
+const DUTY_REPORT_SLOTS: u32 = 600;
+
+/// Accumulated on-time of each heater relay, counted in 100ms time slots
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RelayDutyCounters {
+    pub relay_2_slots: u32,
+    pub relay_3_slots: u32,
+    pub relay_4_slots: u32,
+    pub total_slots: u32,
+}
+
+impl RelayDutyCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one time slot with the given relay states
+    pub fn add_slot(&mut self, relay_2: bool, relay_3: bool, relay_4: bool) {
+        self.relay_2_slots = self.relay_2_slots.wrapping_add(relay_2 as u32);
+        self.relay_3_slots = self.relay_3_slots.wrapping_add(relay_3 as u32);
+        self.relay_4_slots = self.relay_4_slots.wrapping_add(relay_4 as u32);
+        self.total_slots = self.total_slots.wrapping_add(1);
+    }
+}
+
+/// Whether the relay board answered a `HeaterCommand::SelfTest`
+pub static RELAY_SELF_TEST_RESULT: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+
+pub static RELAY_DUTY_CHANNEL: Channel<CriticalSectionRawMutex, RelayDutyCounters, 1> =
+    Channel::new();
+
+/// Switch the heating elements off now, ahead of anything queued on `HEATER_POWER`.
+/// Checked while waiting between slots, so a power cycle in progress is cut short.
+pub static HEATER_FORCE_OFF: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Shortest time, in 100ms slots, a heater relay may be switched on for. Shorter
+/// on-times are carried over until enough power has accumulated.
+const MIN_RELAY_ON_SLOTS: u8 = 3;
+/// Shortest time, in slots, a relay may be switched off between two on-periods.
+/// Shorter gaps are filled in and the extra power is taken from the next cycles.
+const MIN_RELAY_OFF_SLOTS: u8 = 3;
+
 #[derive(Clone, Copy)]
 struct RelaySchedule {
     relay_2: [bool; 10],
@@ -50,21 +128,38 @@ impl RelaySchedule {
         }
     }
 
-    fn calculate_for_power(power: u8, rotation: u8) -> Self {
+    /// Build the schedule for one cycle. `remainder` carries the fractional
+    /// relay-time unit that didn't fit in previous cycles, so that over several
+    /// cycles the delivered power averages out to the commanded power.
+    fn calculate_for_power(power: u8, rotation: u8, remainder: &mut f32) -> Self {
         let mut schedule = Self::new();
 
         if power == 0 {
+            *remainder = 0.0;
             return schedule;
         }
 
         // Convert power (0-100) to total relay-time units needed
         // Each relay represents 33.33% power, so 3 relays = 100%
         // We have 10 time slots of 100ms each
-        let total_relay_time = (power as f32 / 100.0) * 30.0; // 30 = 3 relays * 10 time slots
+        // 30 = 3 relays * 10 time slots
+        let total_relay_time = (power as f32 / 100.0) * 30.0 + *remainder;
+        let relay_units = (total_relay_time as u8).min(30);
+        *remainder = total_relay_time - relay_units as f32;
 
         // Calculate how many full relays (10 slots each) and partial relay time
-        let full_relays = (total_relay_time as u8) / 10;
-        let partial_slots = (total_relay_time as u8) % 10;
+        let full_relays = relay_units / 10;
+        let mut partial_slots = relay_units % 10;
+
+        // Don't cycle the active relay faster than it is rated for. The difference
+        // goes into the remainder so the average power still matches.
+        if partial_slots > 0 && partial_slots < MIN_RELAY_ON_SLOTS {
+            *remainder += partial_slots as f32;
+            partial_slots = 0;
+        } else if partial_slots > 10 - MIN_RELAY_OFF_SLOTS {
+            *remainder -= (10 - partial_slots) as f32;
+            partial_slots = 10;
+        }
 
         // Determine which relay is the "active" (cycling) relay based on rotation
         let active_relay = (rotation % 3) + 2; // Cycles through relays 2, 3, 4
@@ -153,34 +248,193 @@ impl RelaySchedule {
         schedule
     }
 
+    /// Whether the cycle can be cut short after `slot` without switching any relay
+    /// sooner than its minimum on- or off-time. Only runs within this cycle are
+    /// counted, so a relay that kept its state from the previous cycle is treated
+    /// as if it had just switched.
+    fn can_stop_after(&self, slot: usize) -> bool {
+        [&self.relay_2, &self.relay_3, &self.relay_4].iter().all(|relay| {
+            let state = relay[slot];
+            let run = relay[..=slot].iter().rev().take_while(|&&on| on == state).count();
+            let minimum = if state { MIN_RELAY_ON_SLOTS } else { MIN_RELAY_OFF_SLOTS };
+            run >= minimum as usize
+        })
+    }
+}
+
+/// Switch every heater relay on with a single command, leaving the fan as it was.
+/// The board's all-on command includes the fan relay, so the fan is restored after it.
+async fn heater_full_on<I2C, E>(
+    relay_controller: &mut RelayController<I2C, E>,
+    fan_speed: u8,
+) -> Result<(), crate::relay::Error<E>>
+where
+    I2C: I2c<Error = E>,
+{
+    relay_controller.all_on().await?;
+    restore_fan(relay_controller, fan_speed).await
+}
+
+/// Switch every heater relay off with a single command, leaving the fan as it was.
+async fn heater_all_off<I2C, E>(
+    relay_controller: &mut RelayController<I2C, E>,
+    fan_speed: u8,
+) -> Result<(), crate::relay::Error<E>>
+where
+    I2C: I2c<Error = E>,
+{
+    relay_controller.all_off().await?;
+    restore_fan(relay_controller, fan_speed).await
+}
+
+/// Put the fan relay back after a bulk command switched it along with the heaters:
+/// its on/off state, and the PWM speed set by `HeaterCommand::SetFanSpeed`.
+async fn restore_fan<I2C, E>(
+    relay_controller: &mut RelayController<I2C, E>,
+    fan_speed: u8,
+) -> Result<(), crate::relay::Error<E>>
+where
+    I2C: I2c<Error = E>,
+{
+    if fan_speed == 0 {
+        return relay_controller.relay_off(FAN_RELAY).await;
+    }
+    relay_controller.relay_on(FAN_RELAY).await?;
+    relay_controller
+        .set_pwm(FAN_RELAY, percent_to_pwm(fan_speed))
+        .await
+}
+
+/// A power cycle is cut short when the commanded power moves this far from the power
+/// its schedule was built for
+const POWER_CHANGE_RECOMPUTE: u8 = 10;
+
+/// Heater task state that commands change. Shared between the main loop and the
+/// slot loop of a power cycle, which both handle incoming commands.
+struct HeaterState {
+    current_power: u8,
+    rotation_counter: u8,
+    remainder: f32,
+    // Fan speed 0-100 to restore after an all-on/all-off command
+    fan_speed: u8,
+    // Power level (0 or 100) whose single all-off/all-on command has been applied
+    applied_endpoint: Option<u8>,
+    cooling_interlock: bool,
+    // Commanded SSR power and the PWM byte last written for it
+    pwm_power: u8,
+    applied_pwm: Option<u8>,
+}
+
+impl HeaterState {
+    fn new() -> Self {
+        Self {
+            current_power: 0,
+            rotation_counter: 0,
+            remainder: 0.0,
+            fan_speed: 0,
+            applied_endpoint: Some(0),
+            cooling_interlock: false,
+            pwm_power: 0,
+            applied_pwm: None,
+        }
+    }
+
+    /// Power the heat relays should deliver, after the cooling interlock
+    fn applied_power(&self) -> u8 {
+        interlocked_power(self.current_power, self.cooling_interlock)
+    }
 }
 
+/// Run one 10-slot power cycle. Commands are handled between slots, and the cycle
+/// ends early if the power drops to zero or changes by `POWER_CHANGE_RECOMPUTE` so
+/// the caller can build a new schedule straight away. Such a cut waits for a slot
+/// where every relay has been in its state for the minimum time; only
+/// `HEATER_FORCE_OFF` switches off immediately.
 async fn run_power_cycle<I2C, E>(
     relay_controller: &mut RelayController<I2C, E>,
     schedule: RelaySchedule,
+    power: u8,
+    state: &mut HeaterState,
+    duty: &mut RelayDutyCounters,
 ) -> Result<(), crate::relay::Error<E>>
 where
     I2C: I2c<Error = E>,
     E: core::fmt::Debug,
 {
+    let receiver = HEATER_POWER.receiver();
     for slot in 0..10 {
         // Set relay states for this 100ms slot
-        let result = set_heater_relays(
+        let result = set_heater_relays_with_retry(
             relay_controller,
             schedule.relay_2[slot],
             schedule.relay_3[slot],
             schedule.relay_4[slot],
+            2,
         ).await;
 
         result?;
+        duty.add_slot(
+            schedule.relay_2[slot],
+            schedule.relay_3[slot],
+            schedule.relay_4[slot],
+        );
 
-        // Wait for 100ms before next slot
-        Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await;
+        // Wait for 100ms before next slot, unless told to switch off
+        let slot_timer = Timer::after_millis(SYSTEM_TICK_MILLIS.into());
+        if let Either::Second(()) = select(slot_timer, HEATER_FORCE_OFF.wait()).await {
+            force_off(relay_controller, state).await;
+            return Ok(());
+        }
+
+        while let Ok(command) = receiver.try_receive() {
+            handle_command(relay_controller, state, command).await;
+        }
+        let applied_power = state.applied_power();
+        let power_changed =
+            applied_power == 0 || applied_power.abs_diff(power) >= POWER_CHANGE_RECOMPUTE;
+        if power_changed && schedule.can_stop_after(slot) {
+            // The carried-over power belonged to the abandoned schedule
+            state.remainder = 0.0;
+            return Ok(());
+        }
     }
 
     Ok(())
 }
 
+async fn set_heater_relays_with_retry<I2C, E>(
+    relay_controller: &mut RelayController<I2C, E>,
+    relay_2: bool,
+    relay_3: bool,
+    relay_4: bool,
+    max_retries: usize,
+) -> Result<(), crate::relay::Error<E>>
+where
+    I2C: I2c<Error = E>,
+    E: core::fmt::Debug,
+{
+    let mut attempts = 0;
+    loop {
+        match set_heater_relays(relay_controller, relay_2, relay_3, relay_4).await {
+            Ok(()) => return Ok(()),
+            // A bad relay number is a programming error, retrying won't help
+            Err(crate::relay::Error::InvalidRelayNumber) => {
+                return Err(crate::relay::Error::InvalidRelayNumber)
+            }
+            Err(err) if attempts < max_retries => {
+                attempts += 1;
+                warn!(
+                    "Setting heater relays failed (attempt {}/{}) with error {}; retrying...",
+                    attempts,
+                    max_retries + 1,
+                    Debug2Format(&err)
+                );
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
 async fn turn_all_off_with_retry<I2C, E>(
     relay_controller: &mut RelayController<I2C, E>,
     max_retries: usize,
@@ -220,9 +474,9 @@ where
     let mut attempts = 0;
     loop {
         let result = if on {
-            relay_controller.relay_on(1).await
+            relay_controller.relay_on(FAN_RELAY).await
         } else {
-            relay_controller.relay_off(1).await
+            relay_controller.relay_off(FAN_RELAY).await
         };
 
         match result {
@@ -242,12 +496,188 @@ where
     }
 }
 
-#[cfg(not(feature = "mock_temperature_sensor"))]
+/// Drive the SSR channel with the commanded PWM power, bypassing the time-sliced
+/// relay schedule. Only written when the value changes.
+async fn apply_pwm_power<I2C, E>(
+    relay_controller: &mut RelayController<I2C, E>,
+    state: &mut HeaterState,
+) where
+    I2C: I2c<Error = E>,
+    E: core::fmt::Debug,
+{
+    let pwm = percent_to_pwm(interlocked_power(state.pwm_power, state.cooling_interlock));
+    if state.applied_pwm == Some(pwm) {
+        return;
+    }
+    match relay_controller.set_pwm(SSR_RELAY, pwm).await {
+        Ok(()) => state.applied_pwm = Some(pwm),
+        Err(e) => error!("Failed to set SSR PWM to {}: {}", pwm, Debug2Format(&e)),
+    }
+}
+
+/// Handle a `HEATER_FORCE_OFF`: apply whatever is already queued, so a stale power
+/// command can't switch the elements back on, then turn every heating element off.
+async fn force_off<I2C, E>(relay_controller: &mut RelayController<I2C, E>, state: &mut HeaterState)
+where
+    I2C: I2c<Error = E>,
+    E: core::fmt::Debug,
+{
+    warn!("Forcing heater off");
+    while let Ok(command) = HEATER_POWER.receiver().try_receive() {
+        handle_command(relay_controller, state, command).await;
+    }
+    state.current_power = 0;
+    state.remainder = 0.0;
+    state.pwm_power = 0;
+    state.applied_pwm = None;
+    match heater_all_off(relay_controller, state.fan_speed).await {
+        Ok(()) => state.applied_endpoint = Some(0),
+        Err(e) => {
+            error!("Failed to force heater relays off: {}", Debug2Format(&e));
+            state.applied_endpoint = None;
+        }
+    }
+    apply_pwm_power(relay_controller, state).await;
+}
+
+/// Apply one command from the controller to the relays and the heater state
+async fn handle_command<I2C, E>(
+    relay_controller: &mut RelayController<I2C, E>,
+    state: &mut HeaterState,
+    command: crate::HeaterCommand,
+) where
+    I2C: I2c<Error = E>,
+    E: core::fmt::Debug,
+{
+    match command {
+        crate::HeaterCommand::SetPower(power) => {
+            if power > 100 {
+                warn!("Invalid heater power level: {}", power);
+            } else if power != state.current_power {
+                state.current_power = power;
+                state.rotation_counter = state.rotation_counter.wrapping_add(1);
+            }
+        }
+        crate::HeaterCommand::SetPwmPower(power) => {
+            if power > 100 {
+                warn!("Invalid heater power level: {}", power);
+            } else {
+                state.pwm_power = power;
+                apply_pwm_power(relay_controller, state).await;
+            }
+        }
+        crate::HeaterCommand::SetFan(on) => {
+            info!("Setting fan to {}", on);
+            // On/off replaces any speed set by SetFanSpeed, so the PWM follows it
+            let speed = if on { 100 } else { 0 };
+            let result = match set_fan_with_retry(relay_controller, on, 2).await {
+                Ok(()) => relay_controller.set_pwm(FAN_RELAY, percent_to_pwm(speed)).await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                error!("Failed to set fan to {}: {}", on, Debug2Format(&e));
+            } else {
+                state.fan_speed = speed;
+            }
+
+            // Cooling with the fan: make sure no heat relay was left energized
+            if on && state.applied_power() == 0 {
+                let result =
+                    set_heater_relays_with_retry(relay_controller, false, false, false, 2)
+                        .await;
+                if let Err(e) = result {
+                    error!("Failed to confirm heater relays off: {}", Debug2Format(&e));
+                }
+            }
+        }
+        crate::HeaterCommand::SetFanSpeed(speed) => {
+            if speed > 100 {
+                warn!("Invalid fan speed: {}", speed);
+            } else {
+                let result = relay_controller
+                    .set_pwm(FAN_RELAY, percent_to_pwm(speed))
+                    .await;
+
+                if let Err(e) = result {
+                    error!("Failed to set fan speed to {}: {}", speed, Debug2Format(&e));
+                } else {
+                    state.fan_speed = speed;
+                }
+            }
+        }
+        crate::HeaterCommand::SimulationReset => {
+            info!("Resetting heater simulation state");
+            state.current_power = 0;
+            state.rotation_counter = 0;
+            state.remainder = 0.0;
+            state.applied_endpoint = None;
+            // Turn off all relays
+            let result = set_heater_relays(relay_controller, false, false, false).await;
+            if let Err(e) = result {
+                error!("Failed to turn off heater relays during reset: {}", Debug2Format(&e));
+            }
+        }
+        crate::HeaterCommand::UpdatePidParameters { kp, ki, kd } => {
+            info!("PID parameters updated: Kp={}, Ki={}, Kd={}", kp, ki, kd);
+            // Note: Actual PID controller is updated in reflow_controller.rs
+            // This is just for logging at the heater task level
+        }
+        crate::HeaterCommand::SetCoolingInterlock(engaged) => {
+            info!("Cooling interlock {}", if engaged { "engaged" } else { "released" });
+            state.cooling_interlock = engaged;
+            state.applied_endpoint = None;
+            state.applied_pwm = None;
+            if state.pwm_power > 0 {
+                apply_pwm_power(relay_controller, state).await;
+            }
+        }
+        crate::HeaterCommand::SelfTest => {
+            let result = relay_controller.all_status().await;
+            match result {
+                Ok(statuses) => info!("Relay self-test passed: {}", statuses),
+                Err(ref e) => error!("Relay self-test failed: {}", Debug2Format(e)),
+            }
+            RELAY_SELF_TEST_RESULT.signal(result.is_ok());
+        }
+        crate::HeaterCommand::SetThermalParams { .. } => {
+            // Only used by the mock temperature sensor simulation
+        }
+        crate::HeaterCommand::TestRelay { relay, on } => {
+            // The power cycle would fight over the heat relays
+            if state.applied_power() > 0 || state.pwm_power > 0 {
+                warn!("Ignoring relay test while the heater is on");
+                return;
+            }
+            info!("Relay test: relay {} on: {}", relay, on);
+            let result = if on {
+                relay_controller.relay_on(relay).await
+            } else {
+                relay_controller.relay_off(relay).await
+            };
+            if let Err(e) = result {
+                error!("Relay test on relay {} failed: {}", relay, Debug2Format(&e));
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "rp2040", not(feature = "mock_temperature_sensor")))]
 #[embassy_executor::task]
 pub async fn heater_task(i2c_bus: &'static I2c0Bus) {
     let i2c_dev = I2cDevice::new(i2c_bus);
-    let mut relay_controller = RelayController::new(i2c_dev);
+    let relay_controller =
+        RelayController::with_options(i2c_dev, RELAY_BOARD_DEFAULT_ADDR, RELAY_BOARD_INVERTED);
+    run_heater(relay_controller).await;
+}
 
+/// Drive the heater relays from `HEATER_POWER` commands until the relay board fails
+/// to initialize. Takes any I2C bus so the control loop doesn't depend on the RP2040.
+pub async fn run_heater<I2C, E>(mut relay_controller: RelayController<I2C, E>)
+where
+    I2C: I2c<Error = E>,
+    E: core::fmt::Debug,
+{
     if let Err(e) = relay_controller.all_off().await {
         error!("Failed to initialize heater relays: {}", Debug2Format(&e));
         return;
@@ -255,59 +685,71 @@ pub async fn heater_task(i2c_bus: &'static I2c0Bus) {
 
     let receiver = HEATER_POWER.receiver();
 
-    let mut current_power = 0u8;
-    let mut rotation_counter = 0u8;
-    let mut last_schedule = RelaySchedule::new();
+    let mut state = HeaterState::new();
+    let mut duty = RelayDutyCounters::new();
+    let mut last_reported_slots = 0u32;
+    let mut reported_i2c_errors = 0u32;
 
     loop {
+        if HEATER_FORCE_OFF.try_take().is_some() {
+            force_off(&mut relay_controller, &mut state).await;
+        }
+
         // Check for new power commands (non-blocking)
-        match receiver.try_receive() {
-            Ok(command) => match command {
-                crate::HeaterCommand::SetPower(power) => {
-                    if power > 100 {
-                        warn!("Invalid heater power level: {}", power);
-                    } else if power != current_power {
-                        current_power = power;
-                        rotation_counter = rotation_counter.wrapping_add(1);
-                        last_schedule = RelaySchedule::calculate_for_power(power, rotation_counter);
-                    }
-                }
-                crate::HeaterCommand::SetFan(on) => {
-                    info!("Setting fan to {}", on);
-                    let result = set_fan_with_retry(&mut relay_controller, on, 2).await;
+        if let Ok(command) = receiver.try_receive() {
+            handle_command(&mut relay_controller, &mut state, command).await;
+        }
 
-                    if let Err(e) = result {
-                        error!("Failed to set fan to {}: {}", on, Debug2Format(&e));
-                    }
-                }
-                crate::HeaterCommand::SimulationReset => {
-                    info!("Resetting heater simulation state");
-                    current_power = 0;
-                    rotation_counter = 0;
-                    last_schedule = RelaySchedule::new();
-                    // Turn off all relays
-                    let result = set_heater_relays(&mut relay_controller, false, false, false).await;
-                    if let Err(e) = result {
-                        error!("Failed to turn off heater relays during reset: {}", Debug2Format(&e));
+        let applied_power = state.applied_power();
+
+        // Full power and zero power each need only one command
+        if applied_power == 100 {
+            if state.applied_endpoint != Some(100) {
+                match heater_full_on(&mut relay_controller, state.fan_speed).await {
+                    Ok(()) => state.applied_endpoint = Some(100),
+                    Err(e) => {
+                        error!("Failed to switch heater fully on: {}", Debug2Format(&e));
+
+                        let retry_result = turn_all_off_with_retry(&mut relay_controller, 2).await;
+                        if let Err(retry_e) = retry_result {
+                            error!(
+                                "Failed to turn off heater relays after error: {}",
+                                Debug2Format(&retry_e)
+                            );
+                        }
+
+                        // Reset to 0 power after error
+                        state.current_power = 0;
+                        state.remainder = 0.0;
+                        state.applied_endpoint = None;
                     }
                 }
-                crate::HeaterCommand::UpdatePidParameters { kp, ki, kd } => {
-                    info!("PID parameters updated: Kp={}, Ki={}, Kd={}", kp, ki, kd);
-                    // Note: Actual PID controller is updated in reflow_controller.rs
-                    // This is just for logging at the heater task level
-                }
-            },
-            Err(_) => {} // No new command, continue with current power level
-        }
-
-        // Run the power cycle for current power level
-        if current_power > 0 {
-            let result = run_power_cycle(&mut relay_controller, last_schedule).await;
+            }
+            if state.applied_endpoint == Some(100) {
+                duty.add_slot(true, true, true);
+            }
+            Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await;
+        } else if applied_power > 0 {
+            // Run the power cycle for current power level
+            state.applied_endpoint = None;
+            let schedule = RelaySchedule::calculate_for_power(
+                applied_power,
+                state.rotation_counter,
+                &mut state.remainder,
+            );
+            let result = run_power_cycle(
+                &mut relay_controller,
+                schedule,
+                applied_power,
+                &mut state,
+                &mut duty,
+            )
+            .await;
 
             if let Err(e) = result {
                 error!(
                     "Failed to run power cycle at {}%: {}",
-                    current_power,
+                    applied_power,
                     Debug2Format(&e)
                 );
 
@@ -323,23 +765,340 @@ pub async fn heater_task(i2c_bus: &'static I2c0Bus) {
                 }
 
                 // Reset to 0 power after error
-                current_power = 0;
-                last_schedule = RelaySchedule::new();
+                state.current_power = 0;
+                state.remainder = 0.0;
             }
         } else {
             // Power is 0, ensure all relays are off and wait
-            let result = set_heater_relays(&mut relay_controller, false, false, false).await;
-            if let Err(e) = result {
-                error!("Failed to turn off heater relays: {}", Debug2Format(&e));
+            if state.applied_endpoint != Some(0) {
+                match heater_all_off(&mut relay_controller, state.fan_speed).await {
+                    Ok(()) => state.applied_endpoint = Some(0),
+                    Err(e) => error!("Failed to turn off heater relays: {}", Debug2Format(&e)),
+                }
             }
+            duty.add_slot(false, false, false);
             Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await;
         }
+
+        if duty.total_slots.wrapping_sub(last_reported_slots) >= DUTY_REPORT_SLOTS {
+            last_reported_slots = duty.total_slots;
+            // Drop the report if the previous one hasn't been sent yet
+            let _ = RELAY_DUTY_CHANNEL.try_send(duty);
+        }
+
+        let i2c_errors = relay_controller.i2c_errors();
+        if i2c_errors != reported_i2c_errors {
+            let report = I2cErrorReport {
+                device: BusDevice::Relays,
+                count: i2c_errors,
+            };
+            if I2C_ERROR_CHANNEL.try_send(report).is_ok() {
+                reported_i2c_errors = i2c_errors;
+            }
+        }
     }
 }
 
-#[cfg(feature = "mock_temperature_sensor")]
+#[cfg(all(feature = "rp2040", feature = "mock_temperature_sensor"))]
 #[embassy_executor::task]
 pub async fn heater_task(i2c_bus: &'static I2c0Bus) {
     Timer::after_millis((SYSTEM_TICK_MILLIS* 10).into()).await;
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// On-slots of each heat relay in one cycle
+    fn relay_on_slots(schedule: &RelaySchedule) -> [u32; 3] {
+        let count = |slots: &[bool; 10]| slots.iter().filter(|&&on| on).count() as u32;
+        [
+            count(&schedule.relay_2),
+            count(&schedule.relay_3),
+            count(&schedule.relay_4),
+        ]
+    }
+
+    /// Total relay on-slots over `cycles` cycles, carrying the remainder like the task
+    fn total_on_slots(power: u8, cycles: u8) -> u32 {
+        let mut remainder = 0.0;
+        let mut total = 0;
+        for rotation in 0..cycles {
+            let schedule = RelaySchedule::calculate_for_power(power, rotation, &mut remainder);
+            let slots = relay_on_slots(&schedule);
+            for on in slots {
+                // A relay is off, fully on, or on and off for at least the minimum
+                let min_on = MIN_RELAY_ON_SLOTS as u32;
+                let max_on = 10 - MIN_RELAY_OFF_SLOTS as u32;
+                assert!(on == 0 || on == 10 || (min_on..=max_on).contains(&on));
+            }
+            total += slots.iter().sum::<u32>();
+        }
+        total
+    }
+
+    #[test]
+    fn zero_power_switches_every_relay_off() {
+        let mut remainder = 1.5;
+        let schedule = RelaySchedule::calculate_for_power(0, 0, &mut remainder);
+        assert_eq!(relay_on_slots(&schedule), [0, 0, 0]);
+        assert_eq!(remainder, 0.0);
+    }
+
+    #[test]
+    fn full_power_keeps_every_relay_on() {
+        let mut remainder = 0.0;
+        let schedule = RelaySchedule::calculate_for_power(100, 0, &mut remainder);
+        assert_eq!(relay_on_slots(&schedule), [10, 10, 10]);
+        assert_eq!(total_on_slots(100, 10), 300);
+    }
+
+    #[test]
+    fn half_power_is_half_the_relay_time() {
+        let mut remainder = 0.0;
+        let schedule = RelaySchedule::calculate_for_power(50, 0, &mut remainder);
+        assert_eq!(relay_on_slots(&schedule).iter().sum::<u32>(), 15);
+        assert_eq!(total_on_slots(50, 10), 150);
+    }
+
+    #[test]
+    fn remainder_carries_short_pulses_into_later_cycles() {
+        // 5% is 1.5 slots a cycle, too short to switch a relay on for by itself
+        assert!(total_on_slots(5, 10).abs_diff(15) <= 1);
+        // 95% would leave a 1.5 slot gap, too short to switch a relay off for
+        assert!(total_on_slots(95, 10).abs_diff(285) <= 1);
+        // 55% asks for 16.5 slots a cycle
+        assert!(total_on_slots(55, 10).abs_diff(165) <= 1);
+    }
+
+    #[test]
+    fn short_runs_block_an_early_stop() {
+        let mut remainder = 0.0;
+        // 10% is 3 slots on relay 2, then 7 slots off
+        let schedule = RelaySchedule::calculate_for_power(10, 0, &mut remainder);
+        assert!(!schedule.can_stop_after(0));
+        assert!(!schedule.can_stop_after(1));
+        assert!(schedule.can_stop_after(2));
+        assert!(!schedule.can_stop_after(3));
+        assert!(schedule.can_stop_after(5));
+    }
+
+    #[test]
+    fn power_change_waits_for_the_minimum_on_time() {
+        let _statics = crate::lock_statics();
+        reset_heater_statics();
+        let mut board = crate::relay::mock::MockRelayBoard::default();
+        let mut relays = RelayController::new(&mut board);
+        let mut remainder = 0.0;
+        let schedule = RelaySchedule::calculate_for_power(10, 0, &mut remainder);
+        let mut state = HeaterState::new();
+        state.current_power = 10;
+        let mut duty = RelayDutyCounters::default();
+
+        // Switched off right after the first slot turned relay 2 on
+        HEATER_POWER.try_send(crate::HeaterCommand::SetPower(0)).unwrap();
+        embassy_futures::block_on(run_power_cycle(
+            &mut relays,
+            schedule,
+            10,
+            &mut state,
+            &mut duty,
+        ))
+        .unwrap();
+
+        assert_eq!(state.current_power, 0);
+        assert_eq!(duty.total_slots, MIN_RELAY_ON_SLOTS as u32);
+        assert_eq!(duty.relay_2_slots, MIN_RELAY_ON_SLOTS as u32);
+    }
+
+    #[test]
+    fn fan_on_runs_at_full_speed() {
+        let mut board = crate::relay::mock::MockRelayBoard::default();
+        let mut relays = RelayController::new(&mut board);
+        let mut state = HeaterState::new();
+        embassy_futures::block_on(async {
+            handle_command(&mut relays, &mut state, crate::HeaterCommand::SetFanSpeed(40)).await;
+            handle_command(&mut relays, &mut state, crate::HeaterCommand::SetFan(true)).await;
+        });
+        assert_eq!(state.fan_speed, 100);
+        assert!(board.on[FAN_RELAY as usize - 1]);
+        assert_eq!(board.pwm[FAN_RELAY as usize - 1], percent_to_pwm(100));
+
+        let mut relays = RelayController::new(&mut board);
+        embassy_futures::block_on(handle_command(
+            &mut relays,
+            &mut state,
+            crate::HeaterCommand::SetFan(false),
+        ));
+        assert_eq!(state.fan_speed, 0);
+        assert_eq!(board.pwm[FAN_RELAY as usize - 1], 0);
+    }
+
+    /// Start from empty heater channels, whatever earlier tests left behind
+    fn reset_heater_statics() {
+        HEATER_POWER.clear();
+        HEATER_FORCE_OFF.reset();
+        RELAY_DUTY_CHANNEL.clear();
+    }
+
+    /// Run the heater task against `board` while `commands` plays out
+    fn run_heater_with(
+        board: &mut crate::relay::mock::MockRelayBoard,
+        commands: impl core::future::Future<Output = ()>,
+    ) {
+        embassy_futures::block_on(select(run_heater(RelayController::new(board)), commands));
+    }
+
+    fn heat_relays_on(board: &crate::relay::mock::MockRelayBoard) -> [bool; 3] {
+        [board.on[1], board.on[2], board.on[3]]
+    }
+
+    #[test]
+    fn full_and_zero_power_use_the_bulk_commands() {
+        let _statics = crate::lock_statics();
+        reset_heater_statics();
+        let mut board = crate::relay::mock::MockRelayBoard::default();
+        run_heater_with(&mut board, async {
+            HEATER_POWER.send(crate::HeaterCommand::SetPower(100)).await;
+            Timer::after_millis(300).await;
+            HEATER_POWER.send(crate::HeaterCommand::SetPower(0)).await;
+            Timer::after_millis(300).await;
+        });
+
+        let written = board.written();
+        // All-off at start-up and at zero power, all-on once for full power
+        assert_eq!(written.iter().filter(|bytes| **bytes == [0x0B]).count(), 1);
+        assert_eq!(written.iter().filter(|bytes| **bytes == [0x0A]).count(), 2);
+        // No heat relay was toggled on its own
+        assert!(!written.iter().any(|bytes| matches!(bytes[..], [0x02..=0x04])));
+        assert_eq!(heat_relays_on(&board), [false; 3]);
+        assert!(!board.on[FAN_RELAY as usize - 1]);
+    }
+
+    #[test]
+    fn duty_counters_follow_the_schedule() {
+        let _statics = crate::lock_statics();
+        reset_heater_statics();
+        let mut board = crate::relay::mock::MockRelayBoard::default();
+        let mut relays = RelayController::new(&mut board);
+        let mut remainder = 0.0;
+        let schedule = RelaySchedule::calculate_for_power(50, 1, &mut remainder);
+        let mut state = HeaterState::new();
+        state.current_power = 50;
+        let mut duty = RelayDutyCounters::new();
+        embassy_futures::block_on(run_power_cycle(
+            &mut relays,
+            schedule,
+            50,
+            &mut state,
+            &mut duty,
+        ))
+        .unwrap();
+
+        let [relay_2, relay_3, relay_4] = relay_on_slots(&schedule);
+        assert_eq!(duty.relay_2_slots, relay_2);
+        assert_eq!(duty.relay_3_slots, relay_3);
+        assert_eq!(duty.relay_4_slots, relay_4);
+        assert_eq!(duty.total_slots, 10);
+    }
+
+    #[test]
+    fn cooling_interlock_blocks_heating() {
+        let _statics = crate::lock_statics();
+        reset_heater_statics();
+        let mut board = crate::relay::mock::MockRelayBoard::default();
+        run_heater_with(&mut board, async {
+            HEATER_POWER.send(crate::HeaterCommand::SetCoolingInterlock(true)).await;
+            HEATER_POWER.send(crate::HeaterCommand::SetPower(100)).await;
+            HEATER_POWER.send(crate::HeaterCommand::SetPwmPower(50)).await;
+            Timer::after_millis(400).await;
+        });
+
+        assert!(!board.written().contains(&std::vec![0x0B]));
+        assert_eq!(heat_relays_on(&board), [false; 3]);
+        assert_eq!(board.pwm[SSR_RELAY as usize - 1], 0);
+    }
+
+    #[test]
+    fn power_change_mid_cycle_is_picked_up() {
+        let _statics = crate::lock_statics();
+        reset_heater_statics();
+        let mut board = crate::relay::mock::MockRelayBoard::default();
+        let mut relays = RelayController::new(&mut board);
+        let mut remainder = 0.0;
+        // 20% is 6 slots on relay 2
+        let schedule = RelaySchedule::calculate_for_power(20, 0, &mut remainder);
+        let mut state = HeaterState::new();
+        state.current_power = 20;
+        let mut duty = RelayDutyCounters::new();
+
+        HEATER_POWER.try_send(crate::HeaterCommand::SetPower(80)).unwrap();
+        embassy_futures::block_on(run_power_cycle(
+            &mut relays,
+            schedule,
+            20,
+            &mut state,
+            &mut duty,
+        ))
+        .unwrap();
+
+        // Cut short as soon as relay 2 had been on for the minimum
+        assert_eq!(state.current_power, 80);
+        assert_eq!(duty.total_slots, MIN_RELAY_ON_SLOTS as u32);
+        assert_eq!(state.remainder, 0.0);
+    }
+
+    #[test]
+    fn pwm_power_writes_the_ssr_byte() {
+        assert_eq!(percent_to_pwm(0), 0);
+        assert_eq!(percent_to_pwm(50), 127);
+        assert_eq!(percent_to_pwm(100), 255);
+        assert_eq!(percent_to_pwm(150), 255);
+
+        let mut board = crate::relay::mock::MockRelayBoard::default();
+        let mut relays = RelayController::new(&mut board);
+        let mut state = HeaterState::new();
+        embassy_futures::block_on(async {
+            handle_command(&mut relays, &mut state, crate::HeaterCommand::SetPwmPower(50)).await;
+            // Unchanged power isn't written again
+            handle_command(&mut relays, &mut state, crate::HeaterCommand::SetPwmPower(50)).await;
+            // Out of range power is ignored
+            handle_command(&mut relays, &mut state, crate::HeaterCommand::SetPwmPower(101)).await;
+        });
+        assert_eq!(board.written(), [std::vec![0x10 + SSR_RELAY - 1, 127]]);
+        assert_eq!(state.pwm_power, 50);
+    }
+
+    #[test]
+    fn force_off_interrupts_a_cycle() {
+        let _statics = crate::lock_statics();
+        reset_heater_statics();
+        let mut board = crate::relay::mock::MockRelayBoard::default();
+        let mut relays = RelayController::new(&mut board);
+        let mut remainder = 0.0;
+        let schedule = RelaySchedule::calculate_for_power(50, 0, &mut remainder);
+        let mut state = HeaterState::new();
+        state.current_power = 50;
+        state.pwm_power = 40;
+        let mut duty = RelayDutyCounters::new();
+
+        HEATER_FORCE_OFF.signal(());
+        embassy_futures::block_on(run_power_cycle(
+            &mut relays,
+            schedule,
+            50,
+            &mut state,
+            &mut duty,
+        ))
+        .unwrap();
+
+        // Switched off after the first slot, without waiting for the minimum run
+        assert_eq!(duty.total_slots, 1);
+        assert_eq!(state.current_power, 0);
+        assert_eq!(state.pwm_power, 0);
+        assert_eq!(state.applied_endpoint, Some(0));
+        assert_eq!(heat_relays_on(&board), [false; 3]);
+        assert_eq!(board.pwm[SSR_RELAY as usize - 1], 0);
+    }
+}
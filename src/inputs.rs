@@ -1,14 +1,88 @@
-use defmt::*;
-use embassy_executor::Spawner;
-use embassy_rp::{
-    gpio::{Input, Level, Pull},
-    peripherals::{PIN_12, PIN_13, PIN_14, PIN_15, PIN_4, PIN_5},
-    Peri,
+#[cfg(feature = "rp2040")]
+use {
+    crate::display::{ButtonPress, DISPLAY_BUTTON_CHANNEL},
+    crate::{Event, InputResources, INPUT_EVENT_CHANNEL},
+    defmt::*,
+    embassy_executor::Spawner,
+    embassy_rp::{
+        gpio::{Input, Level, Pull},
+        peripherals::{PIN_12, PIN_13, PIN_14, PIN_15, PIN_4, PIN_5},
+        Peri,
+    },
+    embassy_time::Timer,
 };
-use embassy_time::Timer;
 
-use crate::{Event, InputResources, INPUT_EVENT_CHANNEL, SYSTEM_TICK_MILLIS};
+/// Number of consecutive matching samples before a level is accepted
+#[cfg(feature = "rp2040")]
+const DEBOUNCE_SAMPLES: u8 = 5;
+/// Time between debounce samples
+#[cfg(feature = "rp2040")]
+const DEBOUNCE_SAMPLE_MILLIS: u64 = 10;
 
+/// Tracks consecutive samples of an input and reports the level once it is stable
+pub struct Debouncer<T> {
+    candidate: T,
+    count: u8,
+    required: u8,
+}
+
+impl<T: Copy + PartialEq> Debouncer<T> {
+    pub fn new(initial: T, required: u8) -> Self {
+        Self {
+            candidate: initial,
+            count: 0,
+            required,
+        }
+    }
+
+    /// Feed a sample. Returns the level once it has been seen `required` times in a row.
+    pub fn update(&mut self, sample: T) -> Option<T> {
+        if sample == self.candidate {
+            self.count = self.count.saturating_add(1);
+        } else {
+            self.candidate = sample;
+            self.count = 1;
+        }
+
+        if self.count >= self.required {
+            Some(self.candidate)
+        } else {
+            None
+        }
+    }
+}
+
+/// Sample the input until its level has been stable for `DEBOUNCE_SAMPLES` reads
+#[cfg(feature = "rp2040")]
+pub async fn wait_for_stable_level(input: &Input<'_>) -> Level {
+    let mut debouncer = Debouncer::new(input.get_level(), DEBOUNCE_SAMPLES);
+    loop {
+        if let Some(level) = debouncer.update(input.get_level()) {
+            return level;
+        }
+        Timer::after_millis(DEBOUNCE_SAMPLE_MILLIS).await;
+    }
+}
+
+/// Wait for a clean press of an active-low button: a falling edge followed by the
+/// input settling low. Bounces, including those on release, are ignored.
+#[cfg(feature = "rp2040")]
+pub async fn debounced_press(input: &mut Input<'_>) {
+    loop {
+        input.wait_for_falling_edge().await;
+        if wait_for_stable_level(input).await == Level::Low {
+            return;
+        }
+    }
+}
+
+#[cfg(feature = "rp2040")]
+fn door_event(level: Level) -> Event {
+    // The switch pulls the input low when the door is closed
+    Event::DoorStateChanged(level == Level::Low)
+}
+
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 pub async fn interface_task(spawner: Spawner, r: InputResources) {
     spawner.spawn(unwrap!(button_a_task(r.button_a)));
@@ -19,94 +93,130 @@ pub async fn interface_task(spawner: Spawner, r: InputResources) {
     spawner.spawn(unwrap!(start_button_task(r.start_button)));
 }
 
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 async fn button_a_task(pin: Peri<'static, PIN_12>) -> ! {
     let mut button = Input::new(pin, Pull::Up);
     loop {
-        button.wait_for_falling_edge().await;
+        debounced_press(&mut button).await;
         defmt::info!("Button A Pressed");
-        // Handle button one press
-        Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await; // Debounce delay
+        // Dropped if the display isn't keeping up
+        let _ = DISPLAY_BUTTON_CHANNEL.try_send(ButtonPress::Previous);
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 async fn button_b_task(pin: Peri<'static, PIN_13>) -> ! {
     let mut button = Input::new(pin, Pull::Up);
     loop {
-        button.wait_for_falling_edge().await;
+        debounced_press(&mut button).await;
         defmt::info!("Button B Pressed");
-        // Handle button one press
-        Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await; // Debounce delay
+        let _ = DISPLAY_BUTTON_CHANNEL.try_send(ButtonPress::Next);
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 async fn button_x_task(pin: Peri<'static, PIN_14>) -> ! {
     let mut button = Input::new(pin, Pull::Up);
     loop {
-        button.wait_for_falling_edge().await;
-
-        Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await; // Debounce delay
+        debounced_press(&mut button).await;
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 async fn button_y_task(pin: Peri<'static, PIN_15>) -> ! {
     let mut button = Input::new(pin, Pull::Up);
     loop {
-        button.wait_for_falling_edge().await;
+        debounced_press(&mut button).await;
         let sender = INPUT_EVENT_CHANNEL.sender();
         defmt::info!("Button Y Pressed");
         sender.send(Event::ResetCommand).await;
-        Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await; // Debounce delay
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 async fn start_button_task(pin: Peri<'static, PIN_5>) -> ! {
     let mut button = Input::new(pin, Pull::Up);
     loop {
-        button.wait_for_falling_edge().await;
+        debounced_press(&mut button).await;
         defmt::info!("Start Button Pressed");
         INPUT_EVENT_CHANNEL.sender().send(Event::StartCommand).await;
-        Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await; // Debounce delay
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 async fn door_switch_task(pin: Peri<'static, PIN_4>) -> ! {
     let mut door_switch = Input::new(pin, Pull::Up);
-    {
-        let current_state = door_switch.get_level();
-
-        let sender = INPUT_EVENT_CHANNEL.sender();
-        match current_state {
-            Level::Low => {
-                sender.send(Event::DoorStateChanged(true)).await;
-            }
-            Level::High => {
-                sender.send(Event::DoorStateChanged(false)).await;
-            }
-        }
-    }
+    let mut reported_level = wait_for_stable_level(&door_switch).await;
+    INPUT_EVENT_CHANNEL
+        .sender()
+        .send(door_event(reported_level))
+        .await;
 
     loop {
         // Wait for a change in the door switch state
         door_switch.wait_for_any_edge().await;
-        defmt::info!("Door switch state changed");
-        Timer::after_millis((SYSTEM_TICK_MILLIS * 5).into()).await; // Debounce delay (500ms equivalent)
 
-        let new_state = door_switch.get_level();
-
-        let sender = INPUT_EVENT_CHANNEL.sender();
-        match new_state {
-            Level::Low => {
-                sender.send(Event::DoorStateChanged(true)).await;
-            }
-            Level::High => {
-                sender.send(Event::DoorStateChanged(false)).await;
-            }
+        // Only report once the switch has settled, and only if it actually changed
+        let new_level = wait_for_stable_level(&door_switch).await;
+        if new_level != reported_level {
+            defmt::info!("Door switch state changed");
+            reported_level = new_level;
+            INPUT_EVENT_CHANNEL
+                .sender()
+                .send(door_event(new_level))
+                .await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `samples` and collect what the debouncer reports after each one
+    fn run(debouncer: &mut Debouncer<bool>, samples: &[bool]) -> std::vec::Vec<Option<bool>> {
+        samples
+            .iter()
+            .map(|&sample| debouncer.update(sample))
+            .collect()
+    }
+
+    #[test]
+    fn bounces_are_ignored_until_the_level_settles() {
+        let mut debouncer = Debouncer::new(true, 3);
+        let reports = run(
+            &mut debouncer,
+            &[false, true, false, false, true, false, false, false],
+        );
+        assert_eq!(
+            reports,
+            [None, None, None, None, None, None, None, Some(false)]
+        );
+        // A stable level keeps being reported
+        assert_eq!(debouncer.update(false), Some(false));
+    }
+
+    #[test]
+    fn a_single_glitch_restarts_the_count() {
+        let mut debouncer = Debouncer::new(false, 3);
+        assert_eq!(run(&mut debouncer, &[false, false]), [None, None]);
+        assert_eq!(debouncer.update(true), None);
+        assert_eq!(
+            run(&mut debouncer, &[false, false, false]),
+            [None, None, Some(false)]
+        );
+    }
+
+    #[test]
+    fn one_required_sample_reports_at_once() {
+        let mut debouncer = Debouncer::new(0u8, 1);
+        assert_eq!(debouncer.update(7), Some(7));
+        assert_eq!(debouncer.update(3), Some(3));
+    }
+}
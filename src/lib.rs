@@ -1,37 +1,82 @@
 #![no_std]
 
+#[cfg(test)]
+extern crate std;
+
+#[cfg(all(feature = "rp2040", feature = "std"))]
+compile_error!(
+    "`rp2040` and `std` each bring a time driver, build the host tests with \
+     `--no-default-features --features std`"
+);
+
+pub mod display;
 pub mod heater;
 pub mod inputs;
 pub mod mcp9600;
+#[cfg(feature = "rp2040")]
 pub mod outputs;
 pub mod pid;
 pub mod profile;
+pub mod profile_source;
+pub mod protocol;
 pub mod reflow_controller;
 pub mod relay;
 pub mod sd_profile_reader;
+pub mod settings;
 use defmt::Format;
 
 pub mod temperature_sensor;
+pub mod temperature_trace;
 pub mod usb_interface;
 pub static VERSION: &str = "v0.1";
 pub static SYSTEM_TICK_MILLIS: u32 = 100;
 
-use assign_resources::assign_resources;
-use embassy_rp::i2c::I2c;
-use embassy_rp::i2c::{self};
-use embassy_rp::peripherals;
-use embassy_rp::peripherals::I2C0;
-use embassy_rp::Peri;
-use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// How many times faster than real time the simulation runs. Set by the
+/// `fast_simulation` feature so a full reflow simulates in seconds.
+#[cfg(feature = "fast_simulation")]
+pub const TIME_SCALE: u32 = 10;
+#[cfg(not(feature = "fast_simulation"))]
+pub const TIME_SCALE: u32 = 1;
+
+/// Sleep for `millis` of simulated time
+pub async fn sleep_scaled(millis: u32) {
+    embassy_time::Timer::after_millis((millis / TIME_SCALE).max(1).into()).await;
+}
+
+/// Simulated time since `since`, which is wall-clock time unless `TIME_SCALE` > 1
+pub fn scaled_elapsed(since: embassy_time::Instant) -> embassy_time::Duration {
+    since.elapsed() * TIME_SCALE
+}
+
+/// Version of the JSON shape sent over USB, bump whenever a field is added or changed
+pub const SCHEMA_VERSION: u8 = 25;
+
+#[cfg(feature = "rp2040")]
+use {
+    assign_resources::assign_resources,
+    embassy_rp::i2c::{self, I2c},
+    embassy_rp::peripherals,
+    embassy_rp::peripherals::I2C0,
+    embassy_rp::Peri,
+    embassy_sync::blocking_mutex::raw::NoopRawMutex,
+    embassy_sync::mutex::Mutex,
+};
 use embassy_sync::channel::Channel;
-use embassy_sync::watch::Watch;
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use embassy_sync::watch::{self, Watch};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use serde::{Deserialize, Serialize};
+use temperature_sensor::ControlSensor;
 
+#[cfg(feature = "rp2040")]
 pub type I2c0Bus = Mutex<NoopRawMutex, I2c<'static, I2C0, i2c::Async>>;
 
-
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// Events handled by the controller. The serde form is part of the serial protocol:
+/// externally tagged, so unit variants are plain strings (`"StartCommand"`), tuple
+/// variants wrap their value (`{"LoadProfile":"leaded.txt"}`,
+/// `{"DoorStateChanged":true}`) and struct variants wrap an object
+/// (`{"UpdatePidParameters":{"kp":3.0,"ki":0.5,"kd":0.0}}`).
+/// Don't rename variants without bumping `SCHEMA_VERSION`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Event {
     StartCommand,
     StopCommand,
@@ -39,8 +84,28 @@ pub enum Event {
     DoorStateChanged(bool), // true = closed, false = opened
     LoadProfile(heapless::String<64>), // filename to load from SD card
     ListProfilesRequest,
+    GetProfile(heapless::String<64>), // filename to read without making it active
+    PreviewProfile(u32), // sample interval in seconds
     SimulationReset,
     UpdatePidParameters { kp: f32, ki: f32, kd: f32 },
+    SetThermalParams { max_rate: f32, thermal_mass: f32, loss: f32 },
+    SetIdleTarget(f32),
+    StartBake { target: f32, duration_s: u32 }, // hold target for duration_s, then finish
+    ClearError,
+    ThermocoupleFault(bool), // true = open circuit, false = short circuit
+    ResetCycleCount,
+    SetControlSensor(ControlSensor),
+    FullReset, // rebuild the controller state, keeping the persisted settings
+    SetLight(bool), // manual oven light, ignored while heating
+    AcknowledgeFault, // clear a latched overtemperature fault once the oven is cool
+    DumpEvents,       // write out the controller's recent event log
+    TestRelay { relay: u8, on: bool }, // drive one relay directly, idle only
+    SetOvenType(OvenType), // relay bank or SSR, not while heating
+    SetOvershootGuard { band: f32, max_power: u8 }, // power cap within band of the peak
+    SetFinishedAutoReset(Option<u32>), // seconds after finishing, None waits for a reset
+    SetFinishedHoldTarget(Option<f32>), // held after a run, None lets the oven cool
+    SetSafeToOpen { temperature: f32, samples: u8 }, // readings below temperature in a row
+    SetControlPeriod(u32), // milliseconds between control loop iterations
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
@@ -55,49 +120,204 @@ pub enum OutputCommand {
     SetFan(bool),
     SetLight(bool),
     SetBuzzer(bool),
+    SetExhaust(bool), // exhaust fan or door vent used while cooling
     SetStartButtonLight(LedState),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Format)]
 pub enum HeaterCommand {
     SetPower(u8),
+    SetPwmPower(u8), // value between 0 and 100, PWM on the single SSR channel
     SetFan(bool),
+    SetFanSpeed(u8), // value between 0 and 100
     SimulationReset,
     UpdatePidParameters { kp: f32, ki: f32, kd: f32 },
+    SetThermalParams { max_rate: f32, thermal_mass: f32, loss: f32 },
+    SetCoolingInterlock(bool), // true forces the heat relays off
+    SelfTest,                  // result reported on heater::RELAY_SELF_TEST_RESULT
+    TestRelay { relay: u8, on: bool }, // bench test, ignored while the heater is on
+}
+
+/// How the heating element is switched, which decides the heater power command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum OvenType {
+    /// Three mechanical relays time-sliced by the heater task (`SetPower`)
+    #[default]
+    RelayBank,
+    /// One solid state relay driven with PWM (`SetPwmPower`)
+    Ssr,
+}
+
+impl OvenType {
+    /// Heater command that applies `power` (0-100) to this kind of oven
+    pub fn power_command(self, power: u8) -> HeaterCommand {
+        match self {
+            OvenType::RelayBank => HeaterCommand::SetPower(power),
+            OvenType::Ssr => HeaterCommand::SetPwmPower(power),
+        }
+    }
 }
 
 pub static INPUT_EVENT_CHANNEL: Channel<CriticalSectionRawMutex, Event, 3> = Channel::new();
 pub static OUTPUT_COMMAND_CHANNEL: Channel<CriticalSectionRawMutex, OutputCommand, 3> =
     Channel::new();
 pub static HEATER_POWER: Channel<CriticalSectionRawMutex, HeaterCommand, 2> = Channel::new();
-pub static CURRENT_STATE: Watch<CriticalSectionRawMutex, ReflowControllerState, 3> = Watch::new();
+/// Receiver slots on CURRENT_STATE. Current subscribers: the USB state writer
+/// (`usb_interface::usb_task`) and the TFT (`display::display_task`). The rest are
+/// spare, bump this when adding another subscriber.
+pub const CURRENT_STATE_RECEIVERS: usize = 4;
+pub static CURRENT_STATE: Watch<
+    CriticalSectionRawMutex,
+    ReflowControllerState,
+    CURRENT_STATE_RECEIVERS,
+> = Watch::new();
+
+pub type StateReceiver = watch::Receiver<
+    'static,
+    CriticalSectionRawMutex,
+    ReflowControllerState,
+    CURRENT_STATE_RECEIVERS,
+>;
+
+/// Register a CURRENT_STATE receiver for `subscriber`. Returns `None`, logging which
+/// subscriber missed out, once every slot in `CURRENT_STATE_RECEIVERS` is taken.
+pub fn state_receiver(subscriber: &str) -> Option<StateReceiver> {
+    let receiver = CURRENT_STATE.receiver();
+    if receiver.is_none() {
+        defmt::error!(
+            "No CURRENT_STATE receiver left for {} (capacity {})",
+            subscriber,
+            CURRENT_STATE_RECEIVERS
+        );
+    }
+    receiver
+}
 pub static PROFILE_LIST_CHANNEL: Channel<CriticalSectionRawMutex, heapless::Vec<heapless::String<64>, 16>, 1> = Channel::new();
 pub static ACTIVE_PROFILE_CHANNEL: Channel<CriticalSectionRawMutex, profile::Profile, 1> = Channel::new();
+pub static PROFILE_PREVIEW_CHANNEL: Channel<CriticalSectionRawMutex, profile::Profile, 1> = Channel::new();
+/// Profile of a run that has just started, written out with its planned setpoints
+pub static RUN_SCHEDULE_CHANNEL: Channel<CriticalSectionRawMutex, profile::Profile, 1> = Channel::new();
+pub static EVENT_LOG_CHANNEL: Channel<CriticalSectionRawMutex, heapless::Vec<reflow_controller::EventLogEntry, { reflow_controller::EVENT_LOG_CAPACITY }>, 1> = Channel::new();
+pub static SETPOINT_PREVIEW_CHANNEL: Channel<CriticalSectionRawMutex, heapless::Vec<(u32, f32), { profile::PREVIEW_MAX_POINTS }>, 1> = Channel::new();
+
+/// Startup progress (0-100) published by the controller as each init stage completes
+pub static INIT_PROGRESS: Watch<CriticalSectionRawMutex, u8, 2> = Watch::new();
+
+/// One-shot notifications written to the serial output alongside the periodic state
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SystemEvent {
+    Finished {
+        peak: f32,
+        duration_s: u32,
+        reason: FinishReason,
+        // Temperature over the run, peak is the maximum
+        min: f32,
+        mean: f32,
+        std_dev: f32,
+    },
+    /// The oven has stayed below the safe temperature long enough after a run
+    SafeToOpen,
+}
+
+/// How a run reached `Status::Finished`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum FinishReason {
+    /// Every step of the profile, or the whole bake, ran to the end
+    Completed,
+    /// Stopped by the user during the final cooling step
+    StoppedEarly,
+    /// The door was opened during the final cooling step
+    DoorOpened,
+}
+
+/// Reply to a command that reports whether it was accepted (ACK) or rejected (NAK)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CommandAck {
+    pub command: &'static str,
+    pub accepted: bool,
+    pub reason: &'static str, // empty when accepted
+}
+
+pub static COMMAND_ACK_CHANNEL: Channel<CriticalSectionRawMutex, CommandAck, 2> = Channel::new();
+
+pub static SYSTEM_EVENT_CHANNEL: Channel<CriticalSectionRawMutex, SystemEvent, 2> = Channel::new();
+
+/// Devices on the shared I2C bus whose failed transfers are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum BusDevice {
+    Relays,
+    AirSensor,
+    BoardSensor,
+}
+
+/// Running total of failed I2C transfers to one device. A report that doesn't fit
+/// the channel is dropped, the next one carries the newer total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct I2cErrorReport {
+    pub device: BusDevice,
+    pub count: u32,
+}
+
+pub static I2C_ERROR_CHANNEL: Channel<CriticalSectionRawMutex, I2cErrorReport, 4> =
+    Channel::new();
 
 #[derive(Debug, Clone, PartialEq, Format, Serialize, Deserialize)]
 pub enum Status {
     Initializing,
     Idle,
     Running,
+    CoolingDown,
+    Baking,
     Finished,
     Error,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReflowControllerState {
+    #[serde(default)]
+    pub schema_version: u8,
     pub status: Status,
     pub target_temperature: f32,
     pub current_temperature: f32,
+    pub board_temperature: f32, // second thermocouple, 0 without the board_sensor feature
+    pub control_sensor: ControlSensor,
+    pub temperature_rate: f32, // smoothed, degrees per second
+    pub rate_warning: bool,    // heating faster than the current step's max_rate
     pub door_closed: bool,
     pub fan: bool,
+    pub fan_speed: u8, // commanded fan PWM between 0 and 100, 0 when the fan is off
     pub light: bool,
     pub heater_power: u8, // value between 0 and 100
+    pub pid_output_raw: u8, // PID output before the overshoot guard and rate limit
     pub timer: u32,
     pub current_step: &'static str,
+    pub current_step_index: u8,
+    pub total_steps: u8,
+    pub step_temp_reached: bool,
+    pub step_time_met: bool,
+    pub step_time_remaining: u32, // zero outside of a running step
+    pub step_temp_delta: f32, // step temperature minus current, zero outside of a running step
+    pub total_progress_pct: u8,
+    pub profile_duration_s: u32, // expected runtime of the active profile
+    pub bake_remaining_s: u32,
+    pub loop_time_ms: u32, // time between the starts of the last two control loop ticks
+    pub uptime_s: u32,
+    pub i2c_errors: u32, // failed transfers on the shared bus, relays and sensors together
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    pub cycles_completed: u32, // runs that reached Finished, persisted in flash
+    pub finish_reason: Option<FinishReason>, // how the last run finished, if one has
     pub current_profile: heapless::String<32>,
+    pub active_profile_checksum: u32,
     pub error_message: heapless::String<256>,
+    pub fault_latched: bool, // overtemperature, cleared only by ACK_FAULT
+    pub safe_to_open: bool, // cooled down after a run, threshold set with SAFE_TO_OPEN
+    pub notice: heapless::String<64>, // non-fatal condition, e.g. no SD card
 }
 
+#[cfg(feature = "rp2040")]
 assign_resources! {
     inputs: InputResources {
         button_a: PIN_12,
@@ -112,6 +332,7 @@ assign_resources! {
         light: PIN_18,
         buzzer: PIN_19,
         start_button_light: PIN_3,
+        exhaust: PIN_22,
     },
     usb: USBResources {
         usb: USB,
@@ -121,6 +342,21 @@ assign_resources! {
         sda: PIN_20,
         scl: PIN_21,
     },
+    flash: FlashResources {
+        flash: FLASH,
+    },
+    watchdog: WatchdogResources {
+        watchdog: WATCHDOG,
+    },
+    display: DisplayResources {
+        spi: SPI1,
+        clk: PIN_10,
+        mosi: PIN_11,
+        cs: PIN_9,
+        dc: PIN_8,
+        rst: PIN_7,
+        backlight: PIN_6,
+    },
     // SD card resources - will be added when hardware integration is ready
     // sd_card: SdCardResources {
     //     spi: SPI0,
@@ -130,3 +366,73 @@ assign_resources! {
     //     cs: PIN_17,
     // },
 }
+
+/// Every task talks through the statics above, so tests that send or receive on them
+/// take turns instead of running in parallel
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn lock_statics() -> std::sync::MutexGuard<'static, ()> {
+    static STATICS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    STATICS_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// defmt needs a global logger, a timestamp and a panic handler to link. There's no
+/// probe to log to on the host, so `std` builds discard its output.
+#[cfg(feature = "std")]
+mod host_defmt {
+    defmt::timestamp!("");
+
+    #[defmt::global_logger]
+    struct DiscardLogger;
+
+    unsafe impl defmt::Logger for DiscardLogger {
+        fn acquire() {}
+        unsafe fn flush() {}
+        unsafe fn release() {}
+        unsafe fn write(_bytes: &[u8]) {}
+    }
+
+    #[defmt::panic_handler]
+    fn panic() -> ! {
+        core::panic!("defmt panic")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssr_oven_is_driven_with_pwm() {
+        assert_eq!(OvenType::Ssr.power_command(40), HeaterCommand::SetPwmPower(40));
+        assert_eq!(OvenType::RelayBank.power_command(40), HeaterCommand::SetPower(40));
+    }
+
+    #[test]
+    fn oven_type_selected_over_serial() {
+        assert_eq!(
+            protocol::parse_command("OVEN_TYPE SSR"),
+            Some(Event::SetOvenType(OvenType::Ssr))
+        );
+        assert_eq!(
+            protocol::parse_command("{\"SetOvenType\":\"RelayBank\"}"),
+            Some(Event::SetOvenType(OvenType::RelayBank))
+        );
+        assert_eq!(protocol::parse_command("OVEN_TYPE GAS"), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn state_receivers_run_out_at_capacity() {
+        let _statics = lock_statics();
+        let receivers: std::vec::Vec<_> = (0..CURRENT_STATE_RECEIVERS)
+            .map(|_| state_receiver("test").unwrap())
+            .collect();
+        assert!(state_receiver("one too many").is_none());
+
+        // Dropping a receiver frees its slot
+        drop(receivers);
+        assert!(state_receiver("test").is_some());
+    }
+}
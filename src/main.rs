@@ -7,18 +7,21 @@ use embassy_rp::bind_interrupts;
 use embassy_rp::i2c::{Config, I2c, InterruptHandler};
 use embassy_rp::peripherals::I2C0;
 use embassy_sync::mutex::Mutex;
+use reflow_controller::display::display_task;
 use reflow_controller::heater::heater_task;
 
 use reflow_controller::inputs::interface_task;
 use reflow_controller::outputs::output_task;
 use reflow_controller::{temperature_sensor::run_temperature_sensor, usb_interface::usb_task};
+use reflow_controller::settings::settings_task;
 use reflow_controller::{I2c0Bus, USBResources};
 use static_cell::StaticCell;
 use {defmt_rtt as _, panic_probe as _};
 
 use reflow_controller::reflow_controller::controller_task;
 use reflow_controller::{
-    split_resources, AssignedResources, I2CResources, InputResources, OutputResources,
+    split_resources, AssignedResources, DisplayResources, FlashResources, I2CResources,
+    InputResources, OutputResources, WatchdogResources,
 };
 
 #[embassy_executor::main]
@@ -37,10 +40,16 @@ async fn main(spawner: Spawner) {
 
     spawner.spawn(unwrap!(heater_task(i2c_bus)));
     spawner.spawn(unwrap!(run_temperature_sensor(i2c_bus)));
+    #[cfg(all(feature = "board_sensor", not(feature = "mock_temperature_sensor")))]
+    spawner.spawn(unwrap!(
+        reflow_controller::temperature_sensor::run_board_temperature_sensor(i2c_bus)
+    ));
 
     spawner.spawn(unwrap!(interface_task(spawner, r.inputs)));
     spawner.spawn(unwrap!(output_task(spawner, r.outputs)));
 
     spawner.spawn(unwrap!(usb_task(spawner, r.usb)));
-    spawner.spawn(unwrap!(controller_task()));
+    spawner.spawn(unwrap!(display_task(r.display)));
+    spawner.spawn(unwrap!(settings_task(r.flash)));
+    spawner.spawn(unwrap!(controller_task(r.watchdog)));
 }
@@ -18,6 +18,12 @@ mod reg {
 /// MCP9600 Device ID and revision
 const DEVICE_ID: u8 = 0x40;
 
+/// Sensor CONFIG bits 2..0 hold the digital filter coefficient (0 = off, 7 = max)
+const FILTER_COEFFICIENT_MASK: u8 = 0b0000_0111;
+pub const MAX_FILTER_COEFFICIENT: u8 = 7;
+/// Filter coefficient the driver has always used
+pub const DEFAULT_FILTER_COEFFICIENT: u8 = 1;
+
 /// Scaling factor for temperature registers (°C/LSB)
 const TEMP_SCALE: f32 = 0.0625;
 
@@ -33,12 +39,36 @@ bitflags::bitflags! {
     ///
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct SensorFault: u8 {
-        const INPUT_RANGE = 0b0000_0001;
-        const ALERT1      = 0b0000_0010;
-        const ALERT2      = 0b0000_0100;
-        const ALERT3      = 0b0000_1000;
-        const ALERT4      = 0b0001_0000;
-        // MCP9601: open/short circuit bits could be added here
+        const ALERT1        = 0b0000_0001;
+        const ALERT2        = 0b0000_0010;
+        const ALERT3        = 0b0000_0100;
+        const ALERT4        = 0b0000_1000;
+        const INPUT_RANGE   = 0b0001_0000;
+        /// MCP9601 only: thermocouple open circuit (shares the input range bit)
+        const OPEN_CIRCUIT  = 0b0001_0000;
+        /// MCP9601 only: thermocouple shorted to VDD or GND
+        const SHORT_CIRCUIT = 0b0010_0000;
+    }
+}
+
+/// A thermocouple wiring fault reported by the MCP9601
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThermocoupleFault {
+    Open,
+    Short,
+}
+
+impl SensorFault {
+    /// Decode the MCP9601 open/short circuit bits. A short takes precedence since an
+    /// open input can also read as out of range.
+    pub fn thermocouple_fault(&self) -> Option<ThermocoupleFault> {
+        if self.contains(SensorFault::SHORT_CIRCUIT) {
+            Some(ThermocoupleFault::Short)
+        } else if self.contains(SensorFault::OPEN_CIRCUIT) {
+            Some(ThermocoupleFault::Open)
+        } else {
+            None
+        }
     }
 }
 
@@ -49,6 +79,7 @@ pub enum Error<I2cE> {
     BadDeviceId,
     SensorFault(SensorFault),
     DataFormat,
+    InvalidFilterCoefficient(u8),
 }
 
 impl<I2cE: fmt::Debug> fmt::Display for Error<I2cE> {
@@ -58,6 +89,9 @@ impl<I2cE: fmt::Debug> fmt::Display for Error<I2cE> {
             Error::BadDeviceId => write!(f, "Bad device ID"),
             Error::SensorFault(flags) => write!(f, "Sensor fault: {:?}", flags),
             Error::DataFormat => write!(f, "Data format error"),
+            Error::InvalidFilterCoefficient(coeff) => {
+                write!(f, "Invalid filter coefficient: {}", coeff)
+            }
         }
     }
 }
@@ -68,6 +102,8 @@ where
 {
     addr: u8,
     i2c: I2C,
+    config: u8,
+    i2c_errors: u32,
 }
 
 impl<I2C, E> Mcp9600<I2C, E>
@@ -76,23 +112,52 @@ where
 {
     /// Create a new MCP9600 driver instance
     pub fn new(i2c_device: I2C) -> Self {
+        Self::with_address(i2c_device, MCP9600_I2C_BASE_ADDR)
+    }
+
+    /// Create a driver for a sensor strapped to a different address (ADDR pin)
+    pub fn with_address(i2c_device: I2C, addr: u8) -> Self {
         Self {
-            addr: MCP9600_I2C_BASE_ADDR,
+            addr,
             i2c: i2c_device,
+            config: 0,
+            i2c_errors: 0,
         }
     }
 
-    /// Initialize the sensor: verify ID, set K-type, continuous mode, defaults
-    pub async fn init(&mut self) -> Result<(), Error<E>> {
+    /// Failed I2C transfers since the driver was created
+    pub fn i2c_errors(&self) -> u32 {
+        self.i2c_errors
+    }
+
+    fn i2c_error(&mut self, err: E) -> Error<E> {
+        self.i2c_errors = self.i2c_errors.wrapping_add(1);
+        Error::I2c(err)
+    }
+
+    /// Initialize the sensor: verify ID, set K-type, continuous mode and the given
+    /// filter coefficient (see `set_filter_coefficient`)
+    pub async fn init(&mut self, filter_coefficient: u8) -> Result<(), Error<E>> {
 
         // read the device ID and revision number
         let (_id, _rev) = self.read_id_revision().await?;
-        let config = 0x01;
+        // K-type is thermocouple type 0 in bits 6..4
+        self.config = 0x00;
+        self.set_filter_coefficient(filter_coefficient).await
+    }
+
+    /// Set the hardware digital filter, 0 (off) to 7 (heaviest smoothing). Higher
+    /// values reduce noise at the cost of a slower response to temperature changes.
+    pub async fn set_filter_coefficient(&mut self, coeff: u8) -> Result<(), Error<E>> {
+        if coeff > MAX_FILTER_COEFFICIENT {
+            return Err(Error::InvalidFilterCoefficient(coeff));
+        }
+        let config = (self.config & !FILTER_COEFFICIENT_MASK) | coeff;
         self.i2c
             .write(self.addr, &[reg::CONFIG, config])
             .await
-            .map_err(Error::I2c)?;
-
+            .map_err(|e| self.i2c_error(e))?;
+        self.config = config;
         Ok(())
     }
 
@@ -101,7 +166,7 @@ where
         self.i2c
             .write_read(self.addr, &[reg::DEVICE_ID], &mut buf)
             .await
-            .map_err(Error::I2c)?;
+            .map_err(|e| self.i2c_error(e))?;
         if (buf[0]) != DEVICE_ID {
             return Err(Error::BadDeviceId);
         }
@@ -133,7 +198,7 @@ where
         self.i2c
             .write_read(self.addr, &[reg::TH], &mut buf)
             .await
-            .map_err(Error::I2c)?;
+            .map_err(|e| self.i2c_error(e))?;
         let th = Self::parse_temp16(&buf[0..2]).ok_or(Error::DataFormat)?;
         let td = Self::parse_temp16(&buf[2..4]).ok_or(Error::DataFormat)?;
         let tc = Self::parse_temp16(&buf[4..6]).ok_or(Error::DataFormat)?;
@@ -150,17 +215,27 @@ where
         self.i2c
             .write_read(self.addr, &[reg::STATUS], &mut buf)
             .await
-            .map_err(Error::I2c)?;
+            .map_err(|e| self.i2c_error(e))?;
         Ok(SensorFault::from_bits_truncate(buf[0]))
     }
 
+    /// Check the MCP9601 open/short circuit detection. A disconnected probe otherwise
+    /// reads as a plausible cold temperature.
+    pub async fn check_thermocouple(&mut self) -> Result<(), Error<E>> {
+        let status = self.read_status().await?;
+        match status.thermocouple_fault() {
+            Some(_) => Err(Error::SensorFault(status)),
+            None => Ok(()),
+        }
+    }
+
     /// Read and parse a 16-bit signed temperature register
     async fn read_temp16(&mut self, reg: u8) -> Result<i16, Error<E>> {
         let mut buf = [0u8; 2];
         self.i2c
             .write_read(self.addr, &[reg], &mut buf)
             .await
-            .map_err(Error::I2c)?;
+            .map_err(|e| self.i2c_error(e))?;
         Self::parse_temp16(&buf).ok_or(Error::DataFormat)
     }
 
@@ -174,25 +249,112 @@ where
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-
-//     #[test]
-//     fn parse_temp16_positive() {
-//         let bytes = [0x00, 0x80]; // 128 * 0.0625 = 8.0°C
-//         assert_eq!(Mcp9600::parse_temp16(&bytes), Some(128));
-//     }
-
-//     #[test]
-//     fn parse_temp16_negative() {
-//         let bytes = [0xFF, 0x80]; // -128 * 0.0625 = -8.0°C
-//         assert_eq!(Mcp9600::parse_temp16(&bytes), Some(-128));
-//     }
-
-//     #[test]
-//     fn parse_temp16_zero() {
-//         let bytes = [0x00, 0x00];
-//         assert_eq!(Mcp9600::parse_temp16(&bytes), Some(0));
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_hal_async::i2c::{ErrorKind, ErrorType, Operation};
+    use std::vec::Vec;
+
+    #[derive(Debug)]
+    struct MockI2cError;
+
+    impl embedded_hal_async::i2c::Error for MockI2cError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    /// Records writes and answers every read with `reply`
+    #[derive(Default)]
+    struct MockSensor {
+        writes: Vec<Vec<u8>>,
+        reply: u8,
+    }
+
+    impl ErrorType for MockSensor {
+        type Error = MockI2cError;
+    }
+
+    impl I2c for MockSensor {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => self.writes.push(bytes.to_vec()),
+                    Operation::Read(buffer) => buffer.fill(self.reply),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    type Driver<'a> = Mcp9600<&'a mut MockSensor, MockI2cError>;
+
+    #[test]
+    fn parse_temp16_positive() {
+        let bytes = [0x00, 0x80]; // 128 * 0.0625 = 8.0°C
+        assert_eq!(Driver::parse_temp16(&bytes), Some(128));
+    }
+
+    #[test]
+    fn parse_temp16_negative() {
+        let bytes = [0xFF, 0x80]; // -128 * 0.0625 = -8.0°C
+        assert_eq!(Driver::parse_temp16(&bytes), Some(-128));
+    }
+
+    #[test]
+    fn parse_temp16_zero() {
+        let bytes = [0x00, 0x00];
+        assert_eq!(Driver::parse_temp16(&bytes), Some(0));
+    }
+
+    #[test]
+    fn thermocouple_fault_decoding() {
+        assert_eq!(SensorFault::empty().thermocouple_fault(), None);
+        assert_eq!(SensorFault::ALERT1.thermocouple_fault(), None);
+        assert_eq!(
+            SensorFault::OPEN_CIRCUIT.thermocouple_fault(),
+            Some(ThermocoupleFault::Open)
+        );
+        assert_eq!(
+            SensorFault::SHORT_CIRCUIT.thermocouple_fault(),
+            Some(ThermocoupleFault::Short)
+        );
+        // A short also trips the open/input range bit, and wins
+        let both = SensorFault::OPEN_CIRCUIT | SensorFault::SHORT_CIRCUIT;
+        assert_eq!(both.thermocouple_fault(), Some(ThermocoupleFault::Short));
+    }
+
+    #[test]
+    fn check_thermocouple_reports_the_status() {
+        let mut sensor = MockSensor {
+            reply: 0b0010_0000,
+            ..MockSensor::default()
+        };
+        let mut driver = Driver::new(&mut sensor);
+        let result = block_on(driver.check_thermocouple());
+        assert!(matches!(
+            result,
+            Err(Error::SensorFault(flags)) if flags == SensorFault::SHORT_CIRCUIT
+        ));
+        assert_eq!(sensor.writes, [[reg::STATUS]]);
+    }
+
+    #[test]
+    fn filter_coefficient_is_written_to_config() {
+        let mut sensor = MockSensor::default();
+        let mut driver = Driver::new(&mut sensor);
+        block_on(driver.set_filter_coefficient(3)).unwrap();
+        block_on(driver.set_filter_coefficient(MAX_FILTER_COEFFICIENT)).unwrap();
+        assert!(matches!(
+            block_on(driver.set_filter_coefficient(8)),
+            Err(Error::InvalidFilterCoefficient(8))
+        ));
+        // The rejected value wasn't sent
+        assert_eq!(sensor.writes, [[reg::CONFIG, 3], [reg::CONFIG, 7]]);
+    }
+}
@@ -14,6 +14,7 @@ pub async fn output_task(spawner: Spawner, r: OutputResources) {
     let mut fan = Output::new(r.fan, Level::Low);
     let mut light = Output::new(r.light, Level::Low);
     let mut buzzer = Output::new(r.buzzer, Level::Low);
+    let mut exhaust = Output::new(r.exhaust, Level::Low);
     let start_button_light = Output::new(r.start_button_light, Level::Low);
 
     let receiver = OUTPUT_COMMAND_CHANNEL.receiver();
@@ -31,6 +32,9 @@ pub async fn output_task(spawner: Spawner, r: OutputResources) {
             OutputCommand::SetBuzzer(state) => {
                 buzzer.set_level(if state { Level::High } else { Level::Low })
             }
+            OutputCommand::SetExhaust(state) => {
+                exhaust.set_level(if state { Level::High } else { Level::Low })
+            }
             OutputCommand::SetStartButtonLight(state) => LED_STATE.sender().send(state),
         }
     }
@@ -38,7 +42,10 @@ pub async fn output_task(spawner: Spawner, r: OutputResources) {
 
 #[embassy_executor::task]
 pub async fn start_button_light_task(mut start_button_light: Output<'static>) {
-    let mut receiver = LED_STATE.receiver().unwrap();
+    let Some(mut receiver) = LED_STATE.receiver() else {
+        error!("No LED_STATE receiver left for start_button_light_task");
+        return;
+    };
 
     loop {
         let state = receiver.changed().await;
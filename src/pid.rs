@@ -1,3 +1,18 @@
+/// Largest gain accepted by `update_parameters`, anything above is a typo rather than tuning
+pub const MAX_GAIN: f32 = 100.0;
+
+/// Check a set of gains before applying them: each must be finite and within
+/// `0..=MAX_GAIN`. Returns the reason they were rejected.
+pub fn validate_gains(kp: f32, ki: f32, kd: f32) -> Result<(), &'static str> {
+    if !kp.is_finite() || !ki.is_finite() || !kd.is_finite() {
+        return Err("gains must be finite");
+    }
+    if [kp, ki, kd].iter().any(|gain| !(0.0..=MAX_GAIN).contains(gain)) {
+        return Err("gains must be between 0 and 100");
+    }
+    Ok(())
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct PidController {
     kp: f32,
@@ -5,6 +20,7 @@ pub struct PidController {
     kd: f32,
     integral: f32,
     previous_error: f32,
+    last_output: u8,
     out_min: f32,
     out_max: f32,
 }
@@ -17,39 +33,56 @@ impl PidController {
             kd,
             integral: 0.0,
             previous_error: 0.0,
+            last_output: 0,
             out_min: 0.0,
             out_max: 100.0,
         }
     }
 
     /// Compute the new output given setpoint and measured temperature.
-    /// Returns a duty cycle in [out_min, out_max].
-    pub fn update(&mut self, setpoint: f32, measurement: f32) -> u8 {
+    /// `dt` is the time since the previous update in seconds.
+    /// Returns a duty cycle in [out_min, out_max]. A NaN or infinite input is
+    /// rejected and the previous output held, so it can't poison the integral.
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt: f32) -> u8 {
+        if !setpoint.is_finite() || !measurement.is_finite() || !dt.is_finite() {
+            return self.last_output;
+        }
         let error = setpoint - measurement;
 
         // Proportional term
         let proportional = self.kp * error;
 
         // Integral term
-        self.integral += error;
+        self.integral += error * dt;
         let integral = self.ki * self.integral;
 
         // Derivative term
-        let derivative = self.kd * (error - self.previous_error);
+        let derivative = if dt > 0.0 {
+            self.kd * (error - self.previous_error) / dt
+        } else {
+            0.0
+        };
         self.previous_error = error;
 
         // Calculate output
         let output = proportional + integral + derivative;
+        if !output.is_finite() {
+            // Only reachable with huge gains; drop the state rather than keep it
+            self.integral = 0.0;
+            self.previous_error = 0.0;
+            return self.last_output;
+        }
 
         // Clamp to output range
         let clamped_output = output.max(self.out_min).min(self.out_max);
 
         // Apply integral windup protection
         if output != clamped_output {
-            self.integral -= error;
+            self.integral -= error * dt;
         }
 
-        clamped_output as u8
+        self.last_output = clamped_output as u8;
+        self.last_output
     }
 
     /// Reset the integral term to prevent windup when changing setpoints.
@@ -60,7 +93,11 @@ impl PidController {
 
     /// Update PID parameters during runtime for tuning.
     /// Optionally resets integral term to prevent windup with new parameters.
-    pub fn update_parameters(&mut self, kp: f32, ki: f32, kd: f32, reset_integral: bool) {
+    /// Gains rejected by `validate_gains` are ignored; returns whether they were applied.
+    pub fn update_parameters(&mut self, kp: f32, ki: f32, kd: f32, reset_integral: bool) -> bool {
+        if validate_gains(kp, ki, kd).is_err() {
+            return false;
+        }
         self.kp = kp;
         self.ki = ki;
         self.kd = kd;
@@ -68,6 +105,7 @@ impl PidController {
         if reset_integral {
             self.integral = 0.0;
         }
+        true
     }
 
     /// Get current PID parameters for monitoring/logging.
@@ -75,3 +113,63 @@ impl PidController {
         (self.kp, self.ki, self.kd)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integral_grows_with_dt() {
+        // Integral only, 10 degrees below the setpoint
+        let mut short = PidController::new(0.0, 1.0, 0.0);
+        let mut long = PidController::new(0.0, 1.0, 0.0);
+        assert_eq!(short.update(100.0, 90.0, 0.5), 5);
+        assert_eq!(long.update(100.0, 90.0, 2.0), 20);
+        assert_eq!(short.update(100.0, 90.0, 0.5), 10);
+        assert_eq!(long.update(100.0, 90.0, 2.0), 40);
+    }
+
+    #[test]
+    fn derivative_is_divided_by_dt() {
+        let mut short = PidController::new(0.0, 0.0, 1.0);
+        let mut long = PidController::new(0.0, 0.0, 1.0);
+        short.update(100.0, 100.0, 1.0);
+        long.update(100.0, 100.0, 1.0);
+        // The error grows by 5 degrees in each
+        assert_eq!(short.update(100.0, 95.0, 0.5), 10);
+        assert_eq!(long.update(100.0, 95.0, 2.0), 2);
+        // No time has passed, so no derivative
+        assert_eq!(short.update(100.0, 90.0, 0.0), 0);
+    }
+
+    #[test]
+    fn nan_input_holds_the_output_and_spares_the_integral() {
+        let mut pid = PidController::new(1.0, 1.0, 0.0);
+        let mut reference = PidController::new(1.0, 1.0, 0.0);
+        let output = pid.update(100.0, 90.0, 1.0);
+        reference.update(100.0, 90.0, 1.0);
+
+        assert_eq!(pid.update(100.0, f32::NAN, 1.0), output);
+        assert_eq!(pid.update(f32::NAN, 90.0, 1.0), output);
+        assert_eq!(pid.update(100.0, 90.0, f32::INFINITY), output);
+
+        // The next good reading gives the same output as if the bad ones never came
+        assert_eq!(pid.update(100.0, 95.0, 1.0), reference.update(100.0, 95.0, 1.0));
+    }
+
+    #[test]
+    fn validate_gains_rejects_negative_and_non_finite_gains() {
+        assert_eq!(validate_gains(3.0, 0.5, 0.0), Ok(()));
+        assert_eq!(validate_gains(0.0, 0.0, MAX_GAIN), Ok(()));
+        assert!(validate_gains(-1.0, 0.5, 0.0).is_err());
+        assert!(validate_gains(3.0, -0.1, 0.0).is_err());
+        assert!(validate_gains(3.0, 0.5, MAX_GAIN + 1.0).is_err());
+        assert!(validate_gains(f32::NAN, 0.5, 0.0).is_err());
+        assert!(validate_gains(3.0, f32::INFINITY, 0.0).is_err());
+        assert!(validate_gains(3.0, 0.5, f32::NEG_INFINITY).is_err());
+
+        let mut pid = PidController::new(3.0, 0.5, 0.0);
+        assert!(!pid.update_parameters(-3.0, 0.5, 0.0, true));
+        assert_eq!(pid.get_parameters(), (3.0, 0.5, 0.0));
+    }
+}
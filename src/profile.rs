@@ -1,9 +1,13 @@
+use heapless::Vec;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Step {
     pub is_cooling: bool,
     pub has_fan: bool,
+    /// Fan speed 0-100 for this step, overriding `has_fan` (which means 100)
+    #[serde(default)]
+    pub fan_speed: Option<u8>,
     pub step_name: StepName,
     pub set_temperature: f32,
     pub target_time: u32,
@@ -15,6 +19,9 @@ pub struct Step {
 pub struct Profile {
     pub name: heapless::String<32>,
     pub steps: [Step; 6],
+    /// Seconds to hold at the first step's temperature before the profile starts
+    #[serde(default)]
+    pub initial_dwell_s: u32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -27,6 +34,25 @@ pub enum StepName {
     Cooling,
 }
 
+impl Step {
+    /// Display name of the step
+    pub fn name(&self) -> &'static str {
+        self.step_name.to_str()
+    }
+
+    /// How long the step runs for once started (its step_time)
+    pub fn time(&self) -> u32 {
+        self.step_time
+    }
+
+    /// Fan speed for the step: `fan_speed` if given, otherwise 100 or 0 from `has_fan`
+    pub fn fan_speed(&self) -> u8 {
+        self.fan_speed
+            .unwrap_or(if self.has_fan { 100 } else { 0 })
+            .min(100)
+    }
+}
+
 // implement to_str for StepName
 impl StepName {
     pub fn to_str(&self) -> &'static str {
@@ -41,6 +67,320 @@ impl StepName {
     }
 }
 
+/// Maximum number of samples returned by `Profile::preview`
+pub const PREVIEW_MAX_POINTS: usize = 64;
+
+/// Oven temperature assumed at the start of a run when previewing
+const PREVIEW_START_TEMPERATURE: f32 = 25.0;
+
+/// Highest set temperature a profile may request
+pub const MAX_PROFILE_TEMPERATURE: f32 = 300.0;
+
+/// Controller ticks in `seconds`
+pub fn seconds_to_ticks(seconds: u32) -> u32 {
+    seconds.saturating_mul(1000 / crate::SYSTEM_TICK_MILLIS)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ProfileError {
+    InvalidTemperature,
+    InvalidStepTime,
+    InvalidRate,
+    /// A step's target_time is not after the previous step's
+    NonIncreasingTargetTime,
+    /// A step's target_time differs from the sum of step_times up to and including it
+    InconsistentTargetTime,
+    /// A profile needs exactly `PROFILE_STEPS` steps
+    WrongStepCount,
+    /// Steps must run Preheat, Soak, Ramp, ReflowRamp, ReflowCool, Cooling
+    StepOrder,
+    /// The profile name is longer than 32 bytes
+    NameTooLong,
+}
+
+impl Profile {
+    /// FNV-1a hash over the profile name and every step field, in order.
+    /// Lets a host confirm the controller is running the exact profile it expects.
+    pub fn checksum(&self) -> u32 {
+        const FNV_OFFSET_BASIS: u32 = 0x811C_9DC5;
+        const FNV_PRIME: u32 = 0x0100_0193;
+
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut add = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash ^= byte as u32;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        };
+
+        add(self.name.as_bytes());
+        for step in self.steps.iter() {
+            add(&[step.step_name as u8, step.is_cooling as u8, step.has_fan as u8]);
+            add(&step.set_temperature.to_bits().to_le_bytes());
+            add(&step.target_time.to_le_bytes());
+            add(&step.step_time.to_le_bytes());
+            add(&step.max_rate.to_bits().to_le_bytes());
+        }
+        // Only hashed when set so profiles without them keep their checksum. The step
+        // index goes in with the speed, so moving it to another step changes the hash.
+        for (index, step) in self.steps.iter().enumerate() {
+            if let Some(fan_speed) = step.fan_speed {
+                add(&[index as u8, fan_speed]);
+            }
+        }
+        if self.initial_dwell_s > 0 {
+            add(&self.initial_dwell_s.to_le_bytes());
+        }
+        hash
+    }
+
+    /// Length of a run in controller ticks: every step's step_time, which the controller
+    /// counts in ticks of `SYSTEM_TICK_MILLIS`, plus the initial dwell.
+    pub fn total_duration_ticks(&self) -> u32 {
+        self.steps.iter().fold(
+            seconds_to_ticks(self.initial_dwell_s),
+            |total, step| total.saturating_add(step.step_time),
+        )
+    }
+
+    /// Completion of a run `elapsed_ticks` into it, the initial dwell included
+    pub fn progress_pct(&self, elapsed_ticks: u32) -> u8 {
+        let total = self.total_duration_ticks();
+        if total == 0 {
+            return 100;
+        }
+        (elapsed_ticks.saturating_mul(100) / total).min(100) as u8
+    }
+
+    /// Expected length of a run in seconds, from `total_duration_ticks`. Rounded down.
+    pub fn total_duration_s(&self) -> u32 {
+        (self.total_duration_ticks() as u64 * crate::SYSTEM_TICK_MILLIS as u64 / 1000) as u32
+    }
+
+    /// Step and target temperature `elapsed_s` seconds into the run, using each
+    /// step's cumulative `target_time`. With the `ramp_setpoint` feature the target
+    /// ramps linearly from the previous step's temperature, matching the controller.
+    pub fn setpoint_at(&self, elapsed_s: u32) -> (StepName, f32) {
+        let mut step_start_time = 0;
+        let mut step_start_temperature = PREVIEW_START_TEMPERATURE;
+
+        for step in self.steps.iter() {
+            if elapsed_s < step.target_time {
+                #[cfg(feature = "ramp_setpoint")]
+                {
+                    let duration = step.target_time.saturating_sub(step_start_time);
+                    let fraction = if duration > 0 {
+                        elapsed_s.saturating_sub(step_start_time) as f32 / duration as f32
+                    } else {
+                        1.0
+                    };
+                    let temperature = step_start_temperature
+                        + (step.set_temperature - step_start_temperature) * fraction;
+                    return (step.step_name, temperature);
+                }
+
+                #[cfg(not(feature = "ramp_setpoint"))]
+                {
+                    let _ = (step_start_time, step_start_temperature);
+                    return (step.step_name, step.set_temperature);
+                }
+            }
+            step_start_time = step.target_time;
+            step_start_temperature = step.set_temperature;
+        }
+
+        let last = &self.steps[self.steps.len() - 1];
+        (last.step_name, last.set_temperature)
+    }
+
+    /// Sample the setpoint curve every `interval_s` seconds until the last step's
+    /// target time, returning `(elapsed_s, temperature)` pairs.
+    pub fn preview(&self, interval_s: u32) -> Vec<(u32, f32), PREVIEW_MAX_POINTS> {
+        let mut samples = Vec::new();
+        let end = self.steps[self.steps.len() - 1].target_time;
+        let interval_s = interval_s.max(1);
+
+        let mut elapsed_s = 0;
+        while elapsed_s <= end {
+            if samples.push((elapsed_s, self.setpoint_at(elapsed_s).1)).is_err() {
+                break;
+            }
+            elapsed_s += interval_s;
+        }
+        samples
+    }
+
+    /// Check that every step has values the controller can safely run.
+    ///
+    /// `target_time` is the cumulative time from the start of the profile to the end
+    /// of a step (used for the setpoint ramp), while `step_time` is that step's own
+    /// duration (used to complete the step). Each `target_time` must therefore be the
+    /// running sum of the `step_time`s so both views agree.
+    pub fn validate(&self) -> Result<(), ProfileError> {
+        let mut previous_target_time = 0;
+        let mut cumulative_step_time: u32 = 0;
+        for (index, step) in self.steps.iter().enumerate() {
+            if !step.set_temperature.is_finite()
+                || step.set_temperature < 0.0
+                || step.set_temperature > MAX_PROFILE_TEMPERATURE
+            {
+                return Err(ProfileError::InvalidTemperature);
+            }
+            if step.step_time == 0 {
+                return Err(ProfileError::InvalidStepTime);
+            }
+            if !step.max_rate.is_finite() || step.max_rate <= 0.0 {
+                return Err(ProfileError::InvalidRate);
+            }
+            if index > 0 && step.target_time <= previous_target_time {
+                return Err(ProfileError::NonIncreasingTargetTime);
+            }
+            cumulative_step_time = cumulative_step_time.saturating_add(step.step_time);
+            if step.target_time != cumulative_step_time {
+                return Err(ProfileError::InconsistentTargetTime);
+            }
+            previous_target_time = step.target_time;
+        }
+        Ok(())
+    }
+}
+
+/// Number of steps in every profile
+pub const PROFILE_STEPS: usize = 6;
+
+/// Order the steps of a profile must appear in
+const STEP_ORDER: [StepName; PROFILE_STEPS] = [
+    StepName::Preheat,
+    StepName::Soak,
+    StepName::Ramp,
+    StepName::ReflowRamp,
+    StepName::ReflowCool,
+    StepName::Cooling,
+];
+
+/// Builds a profile step by step, filling in each step's cumulative target_time.
+///
+/// ```ignore
+/// let profile = ProfileBuilder::new()
+///     .name("Custom")
+///     .add_step(StepName::Preheat, 150.0, 90, 2.0, false, false)
+///     // ... the remaining five steps
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ProfileBuilder {
+    name: heapless::String<32>,
+    name_too_long: bool,
+    steps: Vec<Step, PROFILE_STEPS>,
+    too_many_steps: bool,
+    initial_dwell_s: u32,
+}
+
+impl ProfileBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name.clear();
+        self.name_too_long = self.name.push_str(name).is_err();
+        self
+    }
+
+    pub fn initial_dwell(mut self, initial_dwell_s: u32) -> Self {
+        self.initial_dwell_s = initial_dwell_s;
+        self
+    }
+
+    pub fn add_step(
+        mut self,
+        step_name: StepName,
+        set_temperature: f32,
+        step_time: u32,
+        max_rate: f32,
+        is_cooling: bool,
+        has_fan: bool,
+    ) -> Self {
+        let previous_target_time = self.steps.last().map_or(0, |step| step.target_time);
+        let step = Step {
+            is_cooling,
+            has_fan,
+            fan_speed: None,
+            step_name,
+            set_temperature,
+            target_time: previous_target_time.saturating_add(step_time),
+            step_time,
+            max_rate,
+        };
+        if self.steps.push(step).is_err() {
+            self.too_many_steps = true;
+        }
+        self
+    }
+
+    /// Check the step count and order, then validate the finished profile
+    pub fn build(self) -> Result<Profile, ProfileError> {
+        if self.name_too_long {
+            return Err(ProfileError::NameTooLong);
+        }
+        if self.too_many_steps || self.steps.len() != PROFILE_STEPS {
+            return Err(ProfileError::WrongStepCount);
+        }
+        if self
+            .steps
+            .iter()
+            .zip(STEP_ORDER.iter())
+            .any(|(step, expected)| step.step_name != *expected)
+        {
+            return Err(ProfileError::StepOrder);
+        }
+
+        let steps = self
+            .steps
+            .into_array()
+            .map_err(|_| ProfileError::WrongStepCount)?;
+        let profile = Profile {
+            name: self.name,
+            steps,
+            initial_dwell_s: self.initial_dwell_s,
+        };
+        profile.validate()?;
+        Ok(profile)
+    }
+}
+
+/// A profile compiled into the firmware, usable without an SD card
+pub struct BuiltinProfile {
+    pub name: &'static str,
+    create: fn() -> Profile,
+}
+
+impl BuiltinProfile {
+    pub fn profile(&self) -> Profile {
+        (self.create)()
+    }
+}
+
+/// Built-in profiles, indexed by the display's profile selection
+pub static PROFILES: [BuiltinProfile; 4] = [
+    BuiltinProfile {
+        name: "Default Profile",
+        create: create_default_profile,
+    },
+    BuiltinProfile {
+        name: "Lead Free",
+        create: create_lead_free_profile,
+    },
+    BuiltinProfile {
+        name: "Leaded",
+        create: create_leaded_profile,
+    },
+    BuiltinProfile {
+        name: "Low Temperature",
+        create: create_low_temp_profile,
+    },
+];
+
 pub fn create_default_profile() -> Profile {
     let mut name = heapless::String::new();
     let _ = name.push_str("Default Profile");
@@ -56,6 +396,7 @@ pub fn create_default_profile() -> Profile {
                 max_rate: 2.0,
                 is_cooling: false,
                 has_fan: false,
+                fan_speed: None,
             },
             Step {
                 step_name: StepName::Soak,
@@ -65,6 +406,7 @@ pub fn create_default_profile() -> Profile {
                 max_rate: 2.0,
                 is_cooling: false,
                 has_fan: false,
+                fan_speed: None,
             },
             Step {
                 step_name: StepName::Ramp,
@@ -74,6 +416,7 @@ pub fn create_default_profile() -> Profile {
                 max_rate: 3.0,
                 is_cooling: false,
                 has_fan: false,
+                fan_speed: None,
             },
             Step {
                 step_name: StepName::ReflowRamp,
@@ -83,6 +426,79 @@ pub fn create_default_profile() -> Profile {
                 max_rate: 2.0,
                 is_cooling: false,
                 has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::ReflowCool,
+                set_temperature: 217.0,
+                target_time: 270,
+                step_time: 30,
+                max_rate: 2.0,
+                is_cooling: true,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Cooling,
+                set_temperature: 50.0,
+                target_time: 330,
+                step_time: 60,
+                max_rate: 5.0,
+                is_cooling: true,
+                has_fan: true,
+                fan_speed: None,
+            },
+        ],
+        initial_dwell_s: 0,
+    }
+}
+
+pub fn create_lead_free_profile() -> Profile {
+    let mut name = heapless::String::new();
+    let _ = name.push_str("Lead Free");
+
+    Profile {
+        name,
+        steps: [
+            Step {
+                step_name: StepName::Preheat,
+                set_temperature: 150.0,
+                target_time: 90,
+                step_time: 90,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Soak,
+                set_temperature: 180.0,
+                target_time: 180,
+                step_time: 90,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Ramp,
+                set_temperature: 217.0,
+                target_time: 210,
+                step_time: 30,
+                max_rate: 3.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::ReflowRamp,
+                set_temperature: 245.0,
+                target_time: 240,
+                step_time: 30,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
             },
             Step {
                 step_name: StepName::ReflowCool,
@@ -92,6 +508,7 @@ pub fn create_default_profile() -> Profile {
                 max_rate: 2.0,
                 is_cooling: true,
                 has_fan: false,
+                fan_speed: None,
             },
             Step {
                 step_name: StepName::Cooling,
@@ -101,7 +518,358 @@ pub fn create_default_profile() -> Profile {
                 max_rate: 5.0,
                 is_cooling: true,
                 has_fan: true,
+                fan_speed: None,
+            },
+        ],
+        initial_dwell_s: 0,
+    }
+}
+
+pub fn create_leaded_profile() -> Profile {
+    let mut name = heapless::String::new();
+    let _ = name.push_str("Leaded");
+
+    Profile {
+        name,
+        steps: [
+            Step {
+                step_name: StepName::Preheat,
+                set_temperature: 100.0,
+                target_time: 180,
+                step_time: 180,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Soak,
+                set_temperature: 150.0,
+                target_time: 270,
+                step_time: 90,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Ramp,
+                set_temperature: 183.0,
+                target_time: 300,
+                step_time: 30,
+                max_rate: 3.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::ReflowRamp,
+                set_temperature: 215.0,
+                target_time: 330,
+                step_time: 30,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::ReflowCool,
+                set_temperature: 183.0,
+                target_time: 360,
+                step_time: 30,
+                max_rate: 2.0,
+                is_cooling: true,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Cooling,
+                set_temperature: 50.0,
+                target_time: 420,
+                step_time: 60,
+                max_rate: 5.0,
+                is_cooling: true,
+                has_fan: true,
+                fan_speed: None,
+            },
+        ],
+        initial_dwell_s: 0,
+    }
+}
+
+pub fn create_low_temp_profile() -> Profile {
+    let mut name = heapless::String::new();
+    let _ = name.push_str("Low Temperature");
+
+    Profile {
+        name,
+        steps: [
+            Step {
+                step_name: StepName::Preheat,
+                set_temperature: 80.0,
+                target_time: 45,
+                step_time: 45,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Soak,
+                set_temperature: 120.0,
+                target_time: 105,
+                step_time: 60,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Ramp,
+                set_temperature: 150.0,
+                target_time: 135,
+                step_time: 30,
+                max_rate: 3.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::ReflowRamp,
+                set_temperature: 180.0,
+                target_time: 165,
+                step_time: 30,
+                max_rate: 2.0,
+                is_cooling: false,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::ReflowCool,
+                set_temperature: 150.0,
+                target_time: 195,
+                step_time: 30,
+                max_rate: 2.0,
+                is_cooling: true,
+                has_fan: false,
+                fan_speed: None,
+            },
+            Step {
+                step_name: StepName::Cooling,
+                set_temperature: 50.0,
+                target_time: 255,
+                step_time: 60,
+                max_rate: 5.0,
+                is_cooling: true,
+                has_fan: true,
+                fan_speed: None,
             },
         ],
+        initial_dwell_s: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_is_half_way_at_half_the_step_time() {
+        let profile = create_lead_free_profile();
+        let total = profile.total_duration_ticks();
+        assert_eq!(total, 330);
+        assert_eq!(profile.progress_pct(0), 0);
+        assert_eq!(profile.progress_pct(total / 2), 50);
+        assert_eq!(profile.progress_pct(total), 100);
+        assert_eq!(profile.progress_pct(total * 2), 100);
+    }
+
+    #[test]
+    fn initial_dwell_is_converted_to_ticks() {
+        let mut profile = create_lead_free_profile();
+        profile.initial_dwell_s = 30;
+        let dwell_ticks = seconds_to_ticks(30);
+        assert_eq!(profile.total_duration_ticks(), 330 + dwell_ticks);
+        assert_eq!(profile.progress_pct((330 + dwell_ticks) / 2), 50);
+    }
+
+    #[test]
+    fn fan_speed_is_optional_in_a_step() {
+        let without = concat!(
+            r#"{"is_cooling":true,"has_fan":true,"step_name":"Cooling","#,
+            r#""set_temperature":50.0,"target_time":330,"step_time":60,"max_rate":5.0}"#
+        );
+        let (step, _) = serde_json_core::from_str::<Step>(without).unwrap();
+        assert_eq!(step.fan_speed, None);
+        assert_eq!(step.fan_speed(), 100);
+
+        let with = concat!(
+            r#"{"is_cooling":true,"has_fan":true,"fan_speed":40,"step_name":"Cooling","#,
+            r#""set_temperature":50.0,"target_time":330,"step_time":60,"max_rate":5.0}"#
+        );
+        let (step, _) = serde_json_core::from_str::<Step>(with).unwrap();
+        assert_eq!(step.fan_speed, Some(40));
+        assert_eq!(step.fan_speed(), 40);
+    }
+
+    #[test]
+    fn fan_speed_on_a_different_step_changes_the_checksum() {
+        let plain = create_default_profile();
+        let mut on_reflow_cool = create_default_profile();
+        on_reflow_cool.steps[4].fan_speed = Some(50);
+        let mut on_cooling = create_default_profile();
+        on_cooling.steps[5].fan_speed = Some(50);
+
+        assert_ne!(plain.checksum(), on_reflow_cool.checksum());
+        assert_ne!(on_reflow_cool.checksum(), on_cooling.checksum());
+    }
+
+    #[test]
+    fn total_duration_is_counted_in_seconds() {
+        // step_time is in ticks of SYSTEM_TICK_MILLIS, 330 ticks of 100ms
+        assert_eq!(create_default_profile().total_duration_s(), 33);
+        assert_eq!(create_lead_free_profile().total_duration_s(), 33);
+        assert_eq!(create_leaded_profile().total_duration_s(), 42);
+        // 255 ticks, rounded down
+        assert_eq!(create_low_temp_profile().total_duration_s(), 25);
+
+        let mut profile = create_default_profile();
+        profile.initial_dwell_s = 30;
+        assert_eq!(profile.total_duration_s(), 63);
+    }
+
+    #[test]
+    fn checksum_is_stable() {
+        let profile = create_lead_free_profile();
+        assert_eq!(profile.checksum(), create_lead_free_profile().checksum());
+        assert_eq!(profile.checksum(), profile.clone().checksum());
+        assert_ne!(profile.checksum(), create_leaded_profile().checksum());
+    }
+
+    #[test]
+    fn checksum_changes_with_every_field() {
+        let base = create_default_profile();
+        let changes: [fn(&mut Profile); 10] = [
+            |profile| profile.name.push('!').unwrap(),
+            |profile| profile.steps[1].step_name = StepName::Ramp,
+            |profile| profile.steps[1].set_temperature += 0.5,
+            |profile| profile.steps[1].target_time += 1,
+            |profile| profile.steps[1].step_time += 1,
+            |profile| profile.steps[1].max_rate += 0.5,
+            |profile| profile.steps[1].is_cooling = true,
+            |profile| profile.steps[1].has_fan = true,
+            |profile| profile.steps[1].fan_speed = Some(100),
+            |profile| profile.initial_dwell_s = 10,
+        ];
+        for (index, change) in changes.iter().enumerate() {
+            let mut profile = base.clone();
+            change(&mut profile);
+            assert_ne!(profile.checksum(), base.checksum(), "change {}", index);
+        }
+    }
+
+    #[test]
+    fn preview_samples_the_setpoint_at_each_interval() {
+        let profile = create_default_profile();
+        let samples = profile.preview(30);
+        // 0 to the last step's target_time of 330, inclusive
+        assert_eq!(samples.len(), 12);
+        for (index, &(elapsed_s, temperature)) in samples.iter().enumerate() {
+            assert_eq!(elapsed_s, index as u32 * 30);
+            assert_eq!(temperature, profile.setpoint_at(elapsed_s).1);
+        }
+        assert_eq!(samples.last().unwrap().0, 330);
+    }
+
+    #[test]
+    fn preview_stops_at_the_point_limit() {
+        let profile = create_default_profile();
+        let samples = profile.preview(1);
+        assert_eq!(samples.len(), PREVIEW_MAX_POINTS);
+        assert_eq!(samples.last().unwrap().0, PREVIEW_MAX_POINTS as u32 - 1);
+        // A zero interval is treated as one second instead of looping forever
+        assert_eq!(profile.preview(0), samples);
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_target_times() {
+        assert_eq!(create_default_profile().validate(), Ok(()));
+
+        let mut profile = create_default_profile();
+        profile.steps[2].target_time += 5;
+        assert_eq!(
+            profile.validate(),
+            Err(ProfileError::InconsistentTargetTime)
+        );
+
+        let mut profile = create_default_profile();
+        profile.steps[2].target_time = profile.steps[1].target_time;
+        assert_eq!(
+            profile.validate(),
+            Err(ProfileError::NonIncreasingTargetTime)
+        );
+
+        let mut profile = create_default_profile();
+        profile.steps[0].step_time = 0;
+        assert_eq!(profile.validate(), Err(ProfileError::InvalidStepTime));
+    }
+
+    #[test]
+    fn builtin_profiles_are_indexed_by_name() {
+        for builtin in PROFILES.iter() {
+            let profile = builtin.profile();
+            assert_eq!(profile.name.as_str(), builtin.name);
+            assert_eq!(profile.validate(), Ok(()));
+        }
+        assert_eq!(PROFILES[1].name, "Lead Free");
+    }
+
+    fn six_steps() -> ProfileBuilder {
+        ProfileBuilder::new()
+            .name("Custom")
+            .add_step(StepName::Preheat, 150.0, 90, 2.0, false, false)
+            .add_step(StepName::Soak, 180.0, 90, 2.0, false, false)
+            .add_step(StepName::Ramp, 217.0, 30, 3.0, false, false)
+            .add_step(StepName::ReflowRamp, 245.0, 30, 2.0, false, false)
+            .add_step(StepName::ReflowCool, 217.0, 30, 2.0, true, false)
+            .add_step(StepName::Cooling, 50.0, 60, 5.0, true, true)
+    }
+
+    #[test]
+    fn builder_fills_in_the_target_times() {
+        let profile = six_steps().initial_dwell(20).build().unwrap();
+        assert_eq!(profile.name.as_str(), "Custom");
+        assert_eq!(profile.initial_dwell_s, 20);
+        let target_times = profile.steps.clone().map(|step| step.target_time);
+        assert_eq!(target_times, [90, 180, 210, 240, 270, 330]);
+    }
+
+    #[test]
+    fn builder_rejects_a_seventh_step() {
+        let builder = six_steps().add_step(StepName::Cooling, 40.0, 60, 5.0, true, true);
+        assert_eq!(builder.build().unwrap_err(), ProfileError::WrongStepCount);
+    }
+
+    #[test]
+    fn builder_rejects_missing_steps_order_and_long_names() {
+        let five = ProfileBuilder::new()
+            .add_step(StepName::Preheat, 150.0, 90, 2.0, false, false)
+            .add_step(StepName::Soak, 180.0, 90, 2.0, false, false)
+            .add_step(StepName::Ramp, 217.0, 30, 3.0, false, false)
+            .add_step(StepName::ReflowRamp, 245.0, 30, 2.0, false, false)
+            .add_step(StepName::ReflowCool, 217.0, 30, 2.0, true, false);
+        assert_eq!(five.build().unwrap_err(), ProfileError::WrongStepCount);
+
+        let swapped = ProfileBuilder::new()
+            .add_step(StepName::Soak, 180.0, 90, 2.0, false, false)
+            .add_step(StepName::Preheat, 150.0, 90, 2.0, false, false)
+            .add_step(StepName::Ramp, 217.0, 30, 3.0, false, false)
+            .add_step(StepName::ReflowRamp, 245.0, 30, 2.0, false, false)
+            .add_step(StepName::ReflowCool, 217.0, 30, 2.0, true, false)
+            .add_step(StepName::Cooling, 50.0, 60, 5.0, true, true);
+        assert_eq!(swapped.build().unwrap_err(), ProfileError::StepOrder);
+
+        let long_name = six_steps().name("A profile name well over thirty-two bytes");
+        assert_eq!(long_name.build().unwrap_err(), ProfileError::NameTooLong);
     }
 }
@@ -0,0 +1,113 @@
+//! Where the controller gets its profiles from.
+//!
+//! The controller only talks to a `ProfileSource`, so the SD card reader can be
+//! swapped for profiles bundled into the firmware on boards without an SD slot.
+//! Enable the `builtin_profiles` feature to use `BuiltinProfileReader`.
+
+use defmt::{error, info};
+use heapless::{String, Vec};
+
+use crate::profile::Profile;
+use crate::sd_profile_reader::{parse_profile_content, SdProfileError};
+
+/// A store of profile files the controller can list and load by filename
+#[allow(async_fn_in_trait)]
+pub trait ProfileSource {
+    /// Prepare the source before it is first read from
+    async fn init(&mut self) -> Result<(), SdProfileError>;
+
+    /// Filenames of the available profiles
+    async fn list_profiles(&self) -> Result<Vec<String<64>, 16>, SdProfileError>;
+
+    /// Read and parse one profile
+    async fn read_profile(&self, filename: &str) -> Result<Profile, SdProfileError>;
+}
+
+/// The profile source used by the controller
+#[cfg(feature = "builtin_profiles")]
+pub type ProfileReader = BuiltinProfileReader;
+#[cfg(not(feature = "builtin_profiles"))]
+pub type ProfileReader = crate::sd_profile_reader::SdProfileReader;
+
+/// Profile files compiled into the firmware, in the same text format as on the SD card
+const BUILTIN_PROFILES: [(&str, &str); 3] = [
+    ("lead_free.txt", include_str!("../profiles/lead_free.txt")),
+    ("leaded.txt", include_str!("../profiles/leaded.txt")),
+    ("low_temp.txt", include_str!("../profiles/low_temp.txt")),
+];
+
+/// Reads the profiles bundled from `profiles/` at build time
+pub struct BuiltinProfileReader;
+
+impl BuiltinProfileReader {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ProfileSource for BuiltinProfileReader {
+    async fn init(&mut self) -> Result<(), SdProfileError> {
+        info!("Using {} built-in profiles", BUILTIN_PROFILES.len());
+        Ok(())
+    }
+
+    async fn list_profiles(&self) -> Result<Vec<String<64>, 16>, SdProfileError> {
+        let mut profiles = Vec::new();
+        for (filename, _) in BUILTIN_PROFILES {
+            let mut name = String::new();
+            let _ = name.push_str(filename);
+            profiles
+                .push(name)
+                .map_err(|_| SdProfileError::TooManyProfiles)?;
+        }
+        Ok(profiles)
+    }
+
+    async fn read_profile(&self, filename: &str) -> Result<Profile, SdProfileError> {
+        info!("Reading built-in profile: {}", filename);
+        match BUILTIN_PROFILES.iter().find(|(name, _)| *name == filename) {
+            Some((name, content)) => parse_profile_content(content, name),
+            None => {
+                error!("Built-in profile not found: {}", filename);
+                Err(SdProfileError::FileNotFound)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+
+    #[test]
+    fn builtin_profiles_are_listed() {
+        let reader = BuiltinProfileReader::new();
+        let profiles = block_on(reader.list_profiles()).unwrap();
+        assert_eq!(profiles, ["lead_free.txt", "leaded.txt", "low_temp.txt"]);
+    }
+
+    #[test]
+    fn every_builtin_profile_is_valid() {
+        let reader = BuiltinProfileReader::new();
+        for (filename, name) in [
+            ("lead_free.txt", "Lead Free"),
+            ("leaded.txt", "Leaded"),
+            ("low_temp.txt", "Low Temperature"),
+        ] {
+            let profile = block_on(reader.read_profile(filename)).unwrap();
+            assert_eq!(profile.name.as_str(), name);
+            assert_eq!(profile.steps.len(), 6);
+            assert!(profile.validate().is_ok(), "{} is not a valid profile", filename);
+        }
+    }
+
+    #[test]
+    fn unknown_builtin_profile_is_not_found() {
+        let reader = BuiltinProfileReader::new();
+        assert!(matches!(
+            block_on(reader.read_profile("missing.txt")),
+            Err(SdProfileError::FileNotFound)
+        ));
+    }
+}
@@ -0,0 +1,516 @@
+use heapless::String;
+use serde::{Deserialize, Serialize};
+
+use crate::temperature_sensor::ControlSensor;
+use crate::{Event, OvenType};
+
+/// Sample interval used by `PREVIEW_PROFILE` when none is given
+pub const DEFAULT_PREVIEW_INTERVAL_S: u32 = 10;
+
+/// Structured commands a host can send as a single JSON line, e.g.
+/// `"Start"`, `{"LoadProfile":"leaded.txt"}` or
+/// `{"UpdatePidParameters":{"kp":3.0,"ki":0.5,"kd":0.0}}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SerialCommand {
+    Start,
+    Stop,
+    Reset,
+    ListProfiles,
+    LoadProfile(String<64>),
+    GetProfile(String<64>),
+    PreviewProfile(u32),
+    SimulationReset,
+    UpdatePidParameters { kp: f32, ki: f32, kd: f32 },
+    SetThermalParams { max_rate: f32, thermal_mass: f32, loss: f32 },
+    SetIdleTarget(f32),
+    StartBake { target: f32, duration_s: u32 },
+    ClearError,
+    ResetCycleCount,
+    SetControlSensor(ControlSensor),
+    FullReset,
+    SetLight(bool),
+    AcknowledgeFault,
+    DumpEvents,
+    TestRelay { relay: u8, on: bool },
+    SetOvenType(OvenType),
+    SetOvershootGuard { band: f32, max_power: u8 },
+    SetFinishedAutoReset(Option<u32>),
+    SetFinishedHoldTarget(Option<f32>),
+    SetSafeToOpen { temperature: f32, samples: u8 },
+    SetControlPeriod(u32),
+}
+
+impl SerialCommand {
+    /// The controller event this command maps to
+    pub fn into_event(self) -> Event {
+        match self {
+            SerialCommand::Start => Event::StartCommand,
+            SerialCommand::Stop => Event::StopCommand,
+            SerialCommand::Reset => Event::ResetCommand,
+            SerialCommand::ListProfiles => Event::ListProfilesRequest,
+            SerialCommand::LoadProfile(filename) => Event::LoadProfile(filename),
+            SerialCommand::GetProfile(filename) => Event::GetProfile(filename),
+            SerialCommand::PreviewProfile(interval_s) => Event::PreviewProfile(interval_s),
+            SerialCommand::SimulationReset => Event::SimulationReset,
+            SerialCommand::UpdatePidParameters { kp, ki, kd } => {
+                Event::UpdatePidParameters { kp, ki, kd }
+            }
+            SerialCommand::SetThermalParams {
+                max_rate,
+                thermal_mass,
+                loss,
+            } => Event::SetThermalParams {
+                max_rate,
+                thermal_mass,
+                loss,
+            },
+            SerialCommand::SetIdleTarget(target) => Event::SetIdleTarget(target),
+            SerialCommand::StartBake { target, duration_s } => {
+                Event::StartBake { target, duration_s }
+            }
+            SerialCommand::ClearError => Event::ClearError,
+            SerialCommand::ResetCycleCount => Event::ResetCycleCount,
+            SerialCommand::SetControlSensor(sensor) => Event::SetControlSensor(sensor),
+            SerialCommand::FullReset => Event::FullReset,
+            SerialCommand::SetLight(on) => Event::SetLight(on),
+            SerialCommand::AcknowledgeFault => Event::AcknowledgeFault,
+            SerialCommand::DumpEvents => Event::DumpEvents,
+            SerialCommand::TestRelay { relay, on } => Event::TestRelay { relay, on },
+            SerialCommand::SetOvenType(oven_type) => Event::SetOvenType(oven_type),
+            SerialCommand::SetOvershootGuard { band, max_power } => {
+                Event::SetOvershootGuard { band, max_power }
+            }
+            SerialCommand::SetFinishedAutoReset(timeout_s) => {
+                Event::SetFinishedAutoReset(timeout_s)
+            }
+            SerialCommand::SetFinishedHoldTarget(target) => Event::SetFinishedHoldTarget(target),
+            SerialCommand::SetSafeToOpen {
+                temperature,
+                samples,
+            } => Event::SetSafeToOpen {
+                temperature,
+                samples,
+            },
+            SerialCommand::SetControlPeriod(period_ms) => Event::SetControlPeriod(period_ms),
+        }
+    }
+}
+
+/// Parse a line received over serial into a controller event.
+/// Accepts the plain text commands (`START`, `SET_PROFILE leaded.txt`, ...) or a
+/// JSON encoded `SerialCommand` or `Event`. Returns `None` for unknown or malformed input.
+pub fn parse_command(data: &str) -> Option<Event> {
+    let data = data.trim();
+    if data.starts_with('{') || data.starts_with('"') {
+        return parse_json_command(data);
+    }
+
+    let (command, argument) = match data.split_once(' ') {
+        Some((command, argument)) => (command, argument.trim()),
+        None => (data, ""),
+    };
+
+    match (command, argument) {
+        ("START", "") => Some(Event::StartCommand),
+        ("STOP", "") => Some(Event::StopCommand),
+        ("RESET", "") => Some(Event::ResetCommand),
+        ("LIST_PROFILES", "") => Some(Event::ListProfilesRequest),
+        ("CLEAR_ERROR", "") => Some(Event::ClearError),
+        ("RESET_CYCLES", "") => Some(Event::ResetCycleCount),
+        ("FULL_RESET", "") => Some(Event::FullReset),
+        ("ACK_FAULT", "") => Some(Event::AcknowledgeFault),
+        ("DUMP_EVENTS", "") => Some(Event::DumpEvents),
+        ("LIGHT", "ON") => Some(Event::SetLight(true)),
+        ("LIGHT", "OFF") => Some(Event::SetLight(false)),
+        ("SET_PROFILE", name) => parse_filename(name).map(Event::LoadProfile),
+        ("GET_PROFILE", name) => parse_filename(name).map(Event::GetProfile),
+        ("PREVIEW_PROFILE", "") => Some(Event::PreviewProfile(DEFAULT_PREVIEW_INTERVAL_S)),
+        ("PREVIEW_PROFILE", interval) => interval.parse().ok().map(Event::PreviewProfile),
+        ("CONTROL_SENSOR", "AIR") => Some(Event::SetControlSensor(ControlSensor::Air)),
+        ("CONTROL_SENSOR", "BOARD") => Some(Event::SetControlSensor(ControlSensor::Board)),
+        ("OVEN_TYPE", "RELAY") => Some(Event::SetOvenType(OvenType::RelayBank)),
+        ("OVEN_TYPE", "SSR") => Some(Event::SetOvenType(OvenType::Ssr)),
+        ("RELAY", params) => {
+            // RELAY <n> ON|OFF
+            let mut values = params.split_whitespace();
+            let relay = values.next()?.parse().ok()?;
+            let on = match (values.next(), values.next()) {
+                (Some("ON"), None) => true,
+                (Some("OFF"), None) => false,
+                _ => return None,
+            };
+            Some(Event::TestRelay { relay, on })
+        }
+        ("AUTO_RESET", "OFF") => Some(Event::SetFinishedAutoReset(None)),
+        ("AUTO_RESET", timeout_s) => timeout_s
+            .parse()
+            .ok()
+            .map(|timeout_s| Event::SetFinishedAutoReset(Some(timeout_s))),
+        ("HOLD_TARGET", "OFF") => Some(Event::SetFinishedHoldTarget(None)),
+        ("HOLD_TARGET", target) => target
+            .parse()
+            .ok()
+            .map(|target| Event::SetFinishedHoldTarget(Some(target))),
+        ("CONTROL_PERIOD", period_ms) => period_ms.parse().ok().map(Event::SetControlPeriod),
+        ("SAFE_TO_OPEN", params) => {
+            // SAFE_TO_OPEN <temperature> <samples>
+            let mut values = params.split_whitespace();
+            match (values.next(), values.next(), values.next()) {
+                (Some(temperature), Some(samples), None) => Some(Event::SetSafeToOpen {
+                    temperature: temperature.parse().ok()?,
+                    samples: samples.parse().ok()?,
+                }),
+                _ => None,
+            }
+        }
+        ("OVERSHOOT_GUARD", params) => {
+            // OVERSHOOT_GUARD <band> <max_power>
+            let mut values = params.split_whitespace();
+            match (values.next(), values.next(), values.next()) {
+                (Some(band), Some(max_power), None) => Some(Event::SetOvershootGuard {
+                    band: band.parse().ok()?,
+                    max_power: max_power.parse().ok()?,
+                }),
+                _ => None,
+            }
+        }
+        ("SET_IDLE_TARGET", target) => target.parse().ok().map(Event::SetIdleTarget),
+        ("BAKE", params) => {
+            // BAKE <target> <duration_s>
+            let mut values = params.split_whitespace();
+            match (values.next(), values.next(), values.next()) {
+                (Some(target), Some(duration_s), None) => Some(Event::StartBake {
+                    target: target.parse().ok()?,
+                    duration_s: duration_s.parse().ok()?,
+                }),
+                _ => None,
+            }
+        }
+        ("SET_THERMAL_PARAMS", params) => {
+            // SET_THERMAL_PARAMS <max_rate> <thermal_mass> <loss>
+            let mut values = params.split_whitespace().map(|v| v.parse::<f32>());
+            match (values.next(), values.next(), values.next(), values.next()) {
+                (Some(Ok(max_rate)), Some(Ok(thermal_mass)), Some(Ok(loss)), None) => {
+                    Some(Event::SetThermalParams {
+                        max_rate,
+                        thermal_mass,
+                        loss,
+                    })
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Profile filenames must be non-empty and fit the event's buffer
+fn parse_filename(name: &str) -> Option<String<64>> {
+    if name.is_empty() {
+        return None;
+    }
+    let mut filename = String::new();
+    if filename.push_str(name).is_err() {
+        defmt::warn!("Profile filename longer than {} bytes", filename.capacity());
+        return None;
+    }
+    Some(filename)
+}
+
+/// Parse a JSON encoded `SerialCommand`, or a raw `Event` in its wire format, into a
+/// controller event
+pub fn parse_json_command(data: &str) -> Option<Event> {
+    if let Ok((command, _)) = serde_json_core::from_str::<SerialCommand>(data) {
+        return Some(command.into_event());
+    }
+    serde_json_core::from_str::<Event>(data)
+        .ok()
+        .map(|(event, _)| event)
+}
+
+/// Parse the argument of `INJECT_FAULT <none|freeze|stuck <value>|range>`
+#[cfg(feature = "test_faults")]
+pub fn parse_fault(argument: &str) -> Option<crate::temperature_sensor::InjectedFault> {
+    use crate::temperature_sensor::InjectedFault;
+
+    let mut words = argument.split_whitespace();
+    let fault = match (words.next(), words.next()) {
+        (Some("none"), None) => InjectedFault::None,
+        (Some("freeze"), None) => InjectedFault::Freeze,
+        (Some("stuck"), Some(value)) => InjectedFault::Stuck(value.parse().ok()?),
+        (Some("range"), None) => InjectedFault::OutOfRange,
+        _ => return None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    Some(fault)
+}
+
+/// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF, no reflection,
+/// no final XOR) used to frame output lines. `crc16(b"123456789") == 0x29B1`.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Check a framed line of the form `<payload>*XXXX`, where XXXX is the
+/// upper-case hex CRC16 of the payload. Returns the payload if the CRC matches.
+pub fn verify_frame(line: &str) -> Option<&str> {
+    let (payload, checksum) = line.trim_end().rsplit_once('*')?;
+    if checksum.len() != 4 {
+        return None;
+    }
+    let expected = u16::from_str_radix(checksum, 16).ok()?;
+    if crc16(payload.as_bytes()) == expected {
+        Some(payload)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(b""), 0xFFFF);
+    }
+
+    #[test]
+    fn verify_frame_accepts_a_good_frame() {
+        assert_eq!(verify_frame("123456789*29B1"), Some("123456789"));
+        assert_eq!(verify_frame("123456789*29B1\r\n"), Some("123456789"));
+    }
+
+    #[test]
+    fn verify_frame_rejects_a_corrupted_frame() {
+        assert_eq!(verify_frame("123456780*29B1"), None);
+        assert_eq!(verify_frame("123456789*29B2"), None);
+        assert_eq!(verify_frame("123456789*ZZZZ"), None);
+    }
+
+    #[test]
+    fn verify_frame_rejects_a_short_frame() {
+        assert_eq!(verify_frame("123456789*29B"), None);
+        assert_eq!(verify_frame("123456789"), None);
+        assert_eq!(verify_frame(""), None);
+    }
+
+    #[test]
+    fn parses_text_commands() {
+        assert_eq!(parse_command("START"), Some(Event::StartCommand));
+        assert_eq!(parse_command("  STOP \r\n"), Some(Event::StopCommand));
+        assert_eq!(
+            parse_command("PREVIEW_PROFILE"),
+            Some(Event::PreviewProfile(DEFAULT_PREVIEW_INTERVAL_S))
+        );
+        assert_eq!(
+            parse_command("BAKE 120 3600"),
+            Some(Event::StartBake {
+                target: 120.0,
+                duration_s: 3600
+            })
+        );
+        assert_eq!(
+            parse_command("RELAY 3 ON"),
+            Some(Event::TestRelay { relay: 3, on: true })
+        );
+        assert_eq!(parse_command("AUTO_RESET OFF"), Some(Event::SetFinishedAutoReset(None)));
+    }
+
+    #[test]
+    fn rejects_unknown_text_commands() {
+        assert_eq!(parse_command(""), None);
+        assert_eq!(parse_command("LAUNCH"), None);
+        assert_eq!(parse_command("start"), None);
+        assert_eq!(parse_command("START NOW"), None);
+        assert_eq!(parse_command("LIGHT DIM"), None);
+        assert_eq!(parse_command("SET_PROFILE"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_text_arguments() {
+        assert_eq!(parse_command("BAKE 120"), None);
+        assert_eq!(parse_command("BAKE 120 3600 1"), None);
+        assert_eq!(parse_command("BAKE hot 3600"), None);
+        assert_eq!(parse_command("RELAY 2"), None);
+        assert_eq!(parse_command("RELAY 300 ON"), None);
+        assert_eq!(parse_command("CONTROL_PERIOD -5"), None);
+        assert_eq!(parse_command("SET_THERMAL_PARAMS 1 2"), None);
+    }
+
+    #[test]
+    fn parses_json_commands_and_raw_events() {
+        assert_eq!(parse_command("\"Start\""), Some(Event::StartCommand));
+        assert_eq!(
+            parse_command(r#"{"UpdatePidParameters":{"kp":3.0,"ki":0.5,"kd":0.0}}"#),
+            Some(Event::UpdatePidParameters {
+                kp: 3.0,
+                ki: 0.5,
+                kd: 0.0
+            })
+        );
+        assert_eq!(
+            parse_command(r#"{"DoorStateChanged":true}"#),
+            Some(Event::DoorStateChanged(true))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert_eq!(parse_json_command("{"), None);
+        assert_eq!(parse_json_command(r#"{"LoadProfile":}"#), None);
+        assert_eq!(parse_json_command(r#"{"StartBake":{"target":120.0}}"#), None);
+        assert_eq!(parse_json_command("\"Start"), None);
+    }
+
+    #[test]
+    fn rejects_unknown_json_commands() {
+        assert_eq!(parse_json_command("\"Launch\""), None);
+        assert_eq!(parse_json_command(r#"{"Launch":true}"#), None);
+    }
+
+    #[test]
+    fn rejects_out_of_range_json_fields() {
+        assert_eq!(parse_json_command(r#"{"TestRelay":{"relay":300,"on":true}}"#), None);
+        assert_eq!(parse_json_command(r#"{"PreviewProfile":-1}"#), None);
+        assert_eq!(parse_json_command(r#"{"SetControlPeriod":1.5}"#), None);
+        // One byte more than the 64 byte filename buffer
+        let long_name = concat!(
+            r#"{"LoadProfile":""#,
+            "0123456789012345678901234567890123456789012345678901234567890",
+            ".txt",
+            r#""}"#
+        );
+        assert_eq!(parse_json_command(long_name), None);
+    }
+
+    fn filename(name: &str) -> String<64> {
+        String::try_from(name).unwrap()
+    }
+
+    #[test]
+    fn every_text_command_parses() {
+        let table = [
+            ("START", Event::StartCommand),
+            ("STOP", Event::StopCommand),
+            ("RESET", Event::ResetCommand),
+            ("LIST_PROFILES", Event::ListProfilesRequest),
+            ("SET_PROFILE leaded.txt", Event::LoadProfile(filename("leaded.txt"))),
+            ("GET_PROFILE low_temp.txt", Event::GetProfile(filename("low_temp.txt"))),
+            ("PREVIEW_PROFILE 5", Event::PreviewProfile(5)),
+            ("CLEAR_ERROR", Event::ClearError),
+            ("RESET_CYCLES", Event::ResetCycleCount),
+            ("FULL_RESET", Event::FullReset),
+            ("ACK_FAULT", Event::AcknowledgeFault),
+            ("DUMP_EVENTS", Event::DumpEvents),
+            ("LIGHT ON", Event::SetLight(true)),
+            ("LIGHT OFF", Event::SetLight(false)),
+            ("CONTROL_SENSOR AIR", Event::SetControlSensor(ControlSensor::Air)),
+            ("CONTROL_SENSOR BOARD", Event::SetControlSensor(ControlSensor::Board)),
+            ("OVEN_TYPE RELAY", Event::SetOvenType(OvenType::RelayBank)),
+            ("OVEN_TYPE SSR", Event::SetOvenType(OvenType::Ssr)),
+            ("RELAY 2 OFF", Event::TestRelay { relay: 2, on: false }),
+            ("AUTO_RESET 300", Event::SetFinishedAutoReset(Some(300))),
+            ("HOLD_TARGET 80", Event::SetFinishedHoldTarget(Some(80.0))),
+            ("HOLD_TARGET OFF", Event::SetFinishedHoldTarget(None)),
+            ("CONTROL_PERIOD 250", Event::SetControlPeriod(250)),
+            (
+                "SAFE_TO_OPEN 45.5 3",
+                Event::SetSafeToOpen {
+                    temperature: 45.5,
+                    samples: 3,
+                },
+            ),
+            (
+                "OVERSHOOT_GUARD 10 30",
+                Event::SetOvershootGuard {
+                    band: 10.0,
+                    max_power: 30,
+                },
+            ),
+            ("SET_IDLE_TARGET 40.5", Event::SetIdleTarget(40.5)),
+            (
+                "SET_THERMAL_PARAMS 2.5 1.5 0.25",
+                Event::SetThermalParams {
+                    max_rate: 2.5,
+                    thermal_mass: 1.5,
+                    loss: 0.25,
+                },
+            ),
+        ];
+        for (command, event) in table {
+            assert_eq!(parse_command(command), Some(event), "{}", command);
+        }
+    }
+
+    #[test]
+    fn every_event_round_trips_through_json() {
+        let events = [
+            Event::StartCommand,
+            Event::StopCommand,
+            Event::ResetCommand,
+            Event::DoorStateChanged(true),
+            Event::LoadProfile(filename("leaded.txt")),
+            Event::ListProfilesRequest,
+            Event::GetProfile(filename("lead_free.txt")),
+            Event::PreviewProfile(10),
+            Event::SimulationReset,
+            Event::UpdatePidParameters {
+                kp: 2.0,
+                ki: 0.125,
+                kd: 0.5,
+            },
+            Event::SetThermalParams {
+                max_rate: 2.5,
+                thermal_mass: 1.5,
+                loss: 0.25,
+            },
+            Event::SetIdleTarget(40.0),
+            Event::StartBake {
+                target: 120.0,
+                duration_s: 3600,
+            },
+            Event::ClearError,
+            Event::ThermocoupleFault(false),
+            Event::ResetCycleCount,
+            Event::SetControlSensor(ControlSensor::Board),
+            Event::FullReset,
+            Event::SetLight(true),
+            Event::AcknowledgeFault,
+            Event::DumpEvents,
+            Event::TestRelay { relay: 4, on: true },
+            Event::SetOvenType(OvenType::Ssr),
+            Event::SetOvershootGuard {
+                band: 10.0,
+                max_power: 30,
+            },
+            Event::SetFinishedAutoReset(None),
+            Event::SetFinishedAutoReset(Some(60)),
+            Event::SetFinishedHoldTarget(Some(80.0)),
+            Event::SetSafeToOpen {
+                temperature: 50.0,
+                samples: 5,
+            },
+            Event::SetControlPeriod(500),
+        ];
+        for event in events {
+            let json = serde_json_core::to_string::<_, 256>(&event).unwrap();
+            let (decoded, _) = serde_json_core::from_str::<Event>(&json).unwrap();
+            assert_eq!(decoded, event, "{}", json);
+        }
+    }
+}
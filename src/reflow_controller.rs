@@ -1,90 +1,880 @@
-use defmt::info;
-use embassy_time::{Instant, Timer};
-use heapless::String;
+use defmt::{info, warn};
+#[cfg(feature = "rp2040")]
+use embassy_rp::watchdog::Watchdog;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use heapless::{Deque, String, Vec};
+use serde::Serialize;
+#[cfg(feature = "rp2040")]
 use {defmt_rtt as _, panic_probe as _};
 
 use crate::{
-    pid::PidController,
-    profile::{create_default_profile, Profile, StepName},
-    sd_profile_reader::{SdProfileError, SdProfileReader},
+    heater::{HEATER_FORCE_OFF, RELAY_SELF_TEST_RESULT},
+    pid::{validate_gains, PidController},
+    profile::{
+        create_default_profile, seconds_to_ticks, Profile, Step, StepName, MAX_PROFILE_TEMPERATURE,
+    },
+    profile_source::{ProfileReader, ProfileSource},
+    relay::RELAY_COUNT,
+    sd_profile_reader::SdProfileError,
+    settings::{Settings, LOADED_SETTINGS, SAVE_SETTINGS_CHANNEL},
     HeaterCommand,
 };
-use crate::{temperature_sensor::CURRENT_TEMPERATURE, HEATER_POWER};
 use crate::{
-    Event, OutputCommand, ReflowControllerState, Status, ACTIVE_PROFILE_CHANNEL, CURRENT_STATE,
-    INPUT_EVENT_CHANNEL, OUTPUT_COMMAND_CHANNEL, PROFILE_LIST_CHANNEL, SYSTEM_TICK_MILLIS,
+    scaled_elapsed, sleep_scaled, BusDevice, CommandAck, Event, FinishReason, OutputCommand,
+    OvenType, ReflowControllerState, Status, SystemEvent, ACTIVE_PROFILE_CHANNEL,
+    COMMAND_ACK_CHANNEL, CURRENT_STATE, EVENT_LOG_CHANNEL, I2C_ERROR_CHANNEL, INIT_PROGRESS,
+    INPUT_EVENT_CHANNEL, OUTPUT_COMMAND_CHANNEL, PROFILE_LIST_CHANNEL, PROFILE_PREVIEW_CHANNEL,
+    RUN_SCHEDULE_CHANNEL, SCHEMA_VERSION, SETPOINT_PREVIEW_CHANNEL, SYSTEM_EVENT_CHANNEL,
+    SYSTEM_TICK_MILLIS, TIME_SCALE,
 };
+use crate::{
+    temperature_sensor::{
+        ControlSensor, TemperatureReading, BOARD_TEMPERATURE, CURRENT_TEMPERATURE,
+    },
+    HEATER_POWER,
+};
+
+/// Default interval between control loop iterations
+pub const DEFAULT_CONTROL_PERIOD_MILLIS: u32 = 1000;
+/// Accepted control periods. The PID needs a non-zero `dt`, and the loop only feeds the
+/// watchdog once per period, so the longest leaves headroom below its timeout.
+pub const MIN_CONTROL_PERIOD_MILLIS: u32 = 100;
+pub const MAX_CONTROL_PERIOD_MILLIS: u32 = (WATCHDOG_TIMEOUT_MILLIS / 2) as u32;
+
+/// Wait after boot before the hardware checks, letting peripherals settle
+pub const STARTUP_DELAY_MILLIS: u32 = 1000;
+
+/// The startup delay is slept in slices this long, feeding the watchdog after each
+const STARTUP_DELAY_SLICE_MILLIS: u32 = (WATCHDOG_TIMEOUT_MILLIS / 4) as u32;
+
+/// Startup progress reported once each init stage has completed
+const INIT_PROGRESS_RELAYS: u8 = 30;
+const INIT_PROGRESS_SENSOR: u8 = 60;
+const INIT_PROGRESS_SD_CARD: u8 = 85;
+const INIT_PROGRESS_SETTINGS: u8 = 100;
+
+/// Distance below the peak temperature at which the overshoot guard engages
+pub const DEFAULT_OVERSHOOT_BAND: f32 = 10.0;
+/// Heater power limit applied while the overshoot guard is engaged
+pub const DEFAULT_OVERSHOOT_MAX_POWER: u8 = 30;
+
+/// Range of temperatures accepted from the sensor during the startup check
+const MIN_PLAUSIBLE_TEMPERATURE: f32 = -20.0;
+const MAX_PLAUSIBLE_TEMPERATURE: f32 = 400.0;
+
+/// Above this the oven is out of control (e.g. a welded relay). The fault latches and
+/// only `Event::AcknowledgeFault` clears it, once the oven has cooled.
+pub const OVERTEMPERATURE_LIMIT: f32 = 300.0;
+const OVERTEMPERATURE_MESSAGE: &str = "Overtemperature!";
+const FAULT_LATCHED_REASON: &str = "overtemperature fault latched, send ACK_FAULT";
+
+/// A reading older than this is treated as stale (the sensor updates every 500ms)
+pub const STALE_READING_MILLIS: u64 = 2000;
+
+/// Control periods in a row with a stale reading, while heating, before the sensor is
+/// declared dead and the controller goes to the error state
+pub const STALE_TICKS_BEFORE_FAULT: u8 = 3;
+const SENSOR_TIMEOUT_MESSAGE: &str = "Temperature sensor not updating";
+
+/// Extra attempts made when mounting the SD card fails with a transient error
+const SD_INIT_RETRIES: usize = 2;
+
+/// Reported in the state when profiles can't be read from the SD card
+const NO_SD_CARD_NOTICE: &str = "No SD card - using default profile";
+
+/// Reported while a reset from the error state waits for the oven to cool
+const COOLING_BEFORE_RESET_NOTICE: &str = "Cooling down before reset";
+
+/// PID gains used until settings are loaded from flash
+pub const DEFAULT_KP: f32 = 3.0;
+pub const DEFAULT_KI: f32 = 0.5;
+pub const DEFAULT_KD: f32 = 0.0;
+
+/// Default target temperature reported while idle
+pub const DEFAULT_IDLE_TARGET: f32 = 25.0;
+
+/// If the control loop stops feeding the watchdog for this long the chip resets.
+/// On boot the heater task switches every relay off, so a hung controller can't
+/// leave the elements energized.
+pub const WATCHDOG_TIMEOUT_MILLIS: u64 = 4000;
+
+/// Temperature below which the oven is considered safe after a stop or error
+pub const COOLDOWN_TEMPERATURE: f32 = 60.0;
+
+/// Consecutive control periods below the safe temperature before the oven is declared
+/// safe to open after a run, so a momentary dip doesn't count
+pub const DEFAULT_SAFE_TO_OPEN_SAMPLES: u8 = 5;
+
+/// Heater power limit while holding `finished_hold_target` after a run, enough to
+/// keep a warm oven warm without driving it back up to reflow temperatures
+const FINISHED_HOLD_MAX_POWER: u8 = 30;
+
+/// Target reported after a run when no hold temperature is set and the oven just cools
+const FINISHED_TARGET_TEMPERATURE: f32 = 25.0;
+
+/// Fraction above a step's max_rate the heating rate may reach before output is cut
+const RATE_WARNING_MARGIN: f32 = 0.2;
+
+/// Weight of the newest sample in the exponentially smoothed temperature rate
+const RATE_SMOOTHING: f32 = 0.3;
+
+/// Fan speed change per °C/s of cooling rate error, applied each control period
+const FAN_SPEED_GAIN: f32 = 20.0;
+
+/// Adjust the cooling fan speed so the oven cools at close to `max_rate`.
+/// `cooling_rate` is positive while the temperature is falling (°C/s).
+pub fn cooling_fan_speed(current_speed: u8, cooling_rate: f32, max_rate: f32) -> u8 {
+    // Positive error means we're cooling slower than allowed, so speed the fan up
+    let error = max_rate - cooling_rate;
+    let speed = current_speed as f32 + FAN_SPEED_GAIN * error;
+    speed.max(0.0).min(100.0) as u8
+}
+
+/// Temperature statistics accumulated over the readings of one run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunStats {
+    count: u32,
+    sum: f32,
+    sum_of_squares: f32,
+    min: f32,
+    max: f32,
+}
+
+impl RunStats {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            sum_of_squares: 0.0,
+            min: f32::MAX,
+            max: f32::MIN,
+        }
+    }
+
+    pub fn add(&mut self, sample: f32) {
+        self.count = self.count.saturating_add(1);
+        self.sum += sample;
+        self.sum_of_squares += sample * sample;
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Lowest sample, 0 before the first one
+    pub fn min(&self) -> f32 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.min
+        }
+    }
+
+    /// Highest sample, 0 before the first one
+    pub fn max(&self) -> f32 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.max
+        }
+    }
+
+    pub fn mean(&self) -> f32 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f32
+        }
+    }
+
+    /// Population standard deviation of the samples
+    pub fn std_dev(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let variance = self.sum_of_squares / self.count as f32 - mean * mean;
+        // Rounding can leave a tiny negative variance for near-constant samples
+        sqrt(variance.max(0.0))
+    }
+}
+
+/// Number of handled events kept for `DUMP_EVENTS`
+pub const EVENT_LOG_CAPACITY: usize = 16;
+
+/// An event the controller handled and when, in milliseconds since boot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EventLogEntry {
+    pub at_ms: u64,
+    pub event: Event,
+}
+
+/// The most recent events handled by the controller, oldest first. Once full the
+/// oldest entry is dropped to make room.
+pub struct EventLog {
+    entries: Deque<EventLogEntry, EVENT_LOG_CAPACITY>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self {
+            entries: Deque::new(),
+        }
+    }
+
+    pub fn record(&mut self, at_ms: u64, event: Event) {
+        if self.entries.is_full() {
+            self.entries.pop_front();
+        }
+        let _ = self.entries.push_back(EventLogEntry { at_ms, event });
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &EventLogEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Copy of the log, oldest first, for sending to the host
+    pub fn to_vec(&self) -> Vec<EventLogEntry, EVENT_LOG_CAPACITY> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+/// Square root by Newton's method, since `f32::sqrt` needs std
+fn sqrt(value: f32) -> f32 {
+    if value <= 0.0 {
+        return 0.0;
+    }
+    let mut estimate = if value > 1.0 { value / 2.0 } else { 1.0 };
+    for _ in 0..20 {
+        estimate = 0.5 * (estimate + value / estimate);
+    }
+    estimate
+}
+
+/// Number of consecutive readings that must meet a step's temperature before the
+/// step counts as reached, so a single noisy sample can't end it early.
+pub const STEP_CONFIRM_SAMPLES: u8 = 3;
+
+/// Whether `temperature` meets a step's target: at or below it for cooling steps,
+/// within 1°C of it or above for heating steps.
+pub fn step_temperature_reached(step: &Step, temperature: f32) -> bool {
+    if step.is_cooling {
+        temperature <= step.set_temperature
+    } else {
+        temperature >= (step.set_temperature - 1.0) // Allow small overshoot margin
+    }
+}
+
+/// Consecutive readings below `safe_temperature`, including this one. Any warmer
+/// reading starts the count again.
+pub fn count_cool_samples(cool_samples: u8, temperature: f32, safe_temperature: f32) -> u8 {
+    if temperature < safe_temperature {
+        cool_samples.saturating_add(1)
+    } else {
+        0
+    }
+}
+
+/// Whether the heat relays must be locked off: during a cooling step, while cooling
+/// down after a stop, and after a run unless a hold temperature is set.
+pub fn cooling_interlock(status: &Status, step_is_cooling: bool, hold: Option<f32>) -> bool {
+    match status {
+        Status::Running => step_is_cooling,
+        Status::CoolingDown => true,
+        Status::Finished => hold.is_none(),
+        _ => false,
+    }
+}
+
+/// Whether a finished run returns to idle on its own: auto-reset is enabled
+/// (`timeout_s` is `Some`), the oven is safe to open and it has been finished for at
+/// least `timeout_s` seconds.
+pub fn finished_auto_reset_due(
+    timeout_s: Option<u32>,
+    safe_to_open: bool,
+    finished_s: u64,
+) -> bool {
+    match timeout_s {
+        Some(timeout_s) => safe_to_open && finished_s >= timeout_s.into(),
+        None => false,
+    }
+}
+
+/// Heater power after the overshoot guard: on the reflow ramp, once the oven is within
+/// `band` degrees of the peak, `power` is limited to `max_power`.
+pub fn overshoot_limited_power(
+    step: &Step,
+    temperature: f32,
+    power: u8,
+    band: f32,
+    max_power: u8,
+) -> u8 {
+    if step.step_name == StepName::ReflowRamp && step.set_temperature - temperature <= band {
+        power.min(max_power)
+    } else {
+        power
+    }
+}
 
+/// Reflow oven state machine.
+///
+/// The controller is driven entirely through the shared channels: events arrive on
+/// `INPUT_EVENT_CHANNEL` and temperature readings on `CURRENT_TEMPERATURE`. The
+/// accessors below expose the internal state so a harness can feed those channels
+/// and check how the controller responds.
 pub struct ReflowController {
     target_temperature: f32,
+    idle_target: f32,
     current_temperature: f32,
     door_closed: bool,
     fan: bool,
+    fan_speed: u8, // value between 0 and 100
     light: bool,
-    heater_power: u8, // value between 0 and 100
+    heater_power: u8,      // value between 0 and 100
+    pid_output_raw: u8,    // PID output this tick before the overshoot and rate limits
+    temperature_rate: f32, // degrees per second
+    rate_warning: bool,
+    last_temperature_time: Option<Instant>,
+    stale_ticks: u8, // control periods in a row with a stale reading while heating
+    control_sensor: ControlSensor,
+    // Latest reading of whichever sensor isn't being controlled on
+    other_temperature: f32,
+    peak_temperature: f32,
+    run_stats: RunStats,
+    event_log: EventLog,
     profile: Profile,
+    profile_filename: String<64>,
+    profile_loaded: bool,
     current_step_index: usize,
+    step_reached_samples: u8,
+    dwelling: bool,
     status: Status,
     profile_start_time: Instant,
     step_start_time: Instant,
+    bake_duration_s: u32,
+    last_tick_start: Option<Instant>,
+    loop_time_ms: u32,
+    // Failed I2C transfers reported by the relay, air sensor and board sensor tasks
+    relay_i2c_errors: u32,
+    air_sensor_i2c_errors: u32,
+    board_sensor_i2c_errors: u32,
     pid_controller: PidController,
+    control_period_ms: u32,
+    oven_type: OvenType,
+    overshoot_band: f32,
+    overshoot_max_power: u8,
     error_message: String<256>,
-    sd_reader: SdProfileReader,
+    notice: String<64>,
+    error_reset_pending: bool,
+    fault_latched: bool,
+    cycles_completed: u32,
+    finish_reason: Option<FinishReason>,
+    finished_time: Instant,
+    finished_auto_reset_s: Option<u32>,
+    finished_hold_target: Option<f32>,
+    safe_temperature: f32,
+    safe_samples_required: u8,
+    cool_samples: u8,
+    safe_to_open: bool,
+    cooling_interlock_sent: Option<bool>,
+    exhaust_sent: Option<bool>,
+    light_sent: Option<bool>,
+    profile_reader: ProfileReader,
+    #[cfg(feature = "rp2040")]
+    watchdog: Option<Watchdog>,
 }
 
 impl ReflowController {
     pub fn new() -> Self {
+        Self::with_pid(DEFAULT_KP, DEFAULT_KI, DEFAULT_KD)
+    }
+
+    /// Create a controller whose PID starts with the given gains
+    pub fn with_pid(kp: f32, ki: f32, kd: f32) -> Self {
         Self {
             target_temperature: -100.0,
+            idle_target: DEFAULT_IDLE_TARGET,
             current_temperature: -100.0,
             door_closed: false,
             fan: false,
+            fan_speed: 0,
             light: false,
             heater_power: 0,
+            pid_output_raw: 0,
+            temperature_rate: 0.0,
+            rate_warning: false,
+            last_temperature_time: None,
+            stale_ticks: 0,
+            control_sensor: ControlSensor::Air,
+            other_temperature: 0.0,
+            peak_temperature: -100.0,
+            run_stats: RunStats::new(),
+            event_log: EventLog::new(),
             profile: create_default_profile(),
+            profile_filename: String::new(),
+            profile_loaded: false,
             current_step_index: 0,
+            step_reached_samples: 0,
+            dwelling: false,
             status: Status::Initializing,
             profile_start_time: Instant::now(),
             step_start_time: Instant::now(),
-            pid_controller: PidController::new(3.0, 0.5, 0.0),
+            bake_duration_s: 0,
+            last_tick_start: None,
+            loop_time_ms: 0,
+            relay_i2c_errors: 0,
+            air_sensor_i2c_errors: 0,
+            board_sensor_i2c_errors: 0,
+            pid_controller: PidController::new(kp, ki, kd),
+            control_period_ms: DEFAULT_CONTROL_PERIOD_MILLIS,
+            oven_type: OvenType::RelayBank,
+            overshoot_band: DEFAULT_OVERSHOOT_BAND,
+            overshoot_max_power: DEFAULT_OVERSHOOT_MAX_POWER,
             error_message: String::new(),
-            sd_reader: SdProfileReader::new(),
+            notice: String::new(),
+            error_reset_pending: false,
+            fault_latched: false,
+            cycles_completed: 0,
+            finish_reason: None,
+            finished_time: Instant::now(),
+            finished_auto_reset_s: None,
+            finished_hold_target: None,
+            safe_temperature: COOLDOWN_TEMPERATURE,
+            safe_samples_required: DEFAULT_SAFE_TO_OPEN_SAMPLES,
+            cool_samples: 0,
+            safe_to_open: false,
+            cooling_interlock_sent: None,
+            exhaust_sent: None,
+            light_sent: None,
+            profile_reader: ProfileReader::new(),
+            #[cfg(feature = "rp2040")]
+            watchdog: None,
         }
     }
 
     pub async fn run(&mut self) {
         loop {
-            if CURRENT_TEMPERATURE.signaled() {
-                let new_temp = CURRENT_TEMPERATURE.wait().await;
-                self.handle_new_temperature(new_temp).await;
-            }
-            // Check for input events
-            let receiver = INPUT_EVENT_CHANNEL.receiver();
+            self.tick().await;
+            sleep_scaled(self.control_period_ms).await;
+        }
+    }
 
-            if !receiver.is_empty() {
-                let event = receiver.receive().await;
-                self.handle_event(event).await;
-            }
-            match self.status {
-                Status::Initializing => self.init().await,
-                Status::Idle => self.idle().await,
-                Status::Running => self.running().await,
-                Status::Error => self.error().await,
-                Status::Finished => self.finished().await,
+    /// Run a single control loop iteration: take the latest temperature reading,
+    /// handle at most one pending event, run the handler for the current status,
+    /// then send the heater commands and publish the state.
+    ///
+    /// This is also the entry point for host tests (`std` feature): signal
+    /// `CURRENT_TEMPERATURE`, queue events on `INPUT_EVENT_CHANNEL` and call `tick()`
+    /// while draining the output channels, then check the getters below. See the
+    /// harness in this file's tests.
+    pub async fn tick(&mut self) {
+        let tick_start = Instant::now();
+        if let Some(last_tick_start) = self.last_tick_start {
+            self.loop_time_ms = tick_start.duration_since(last_tick_start).as_millis() as u32;
+        }
+        self.last_tick_start = Some(tick_start);
+        self.feed_watchdog();
+        let (control_signal, other_signal) = match self.control_sensor {
+            ControlSensor::Air => (&CURRENT_TEMPERATURE, &BOARD_TEMPERATURE),
+            ControlSensor::Board => (&BOARD_TEMPERATURE, &CURRENT_TEMPERATURE),
+        };
+        if control_signal.signaled() {
+            let reading = control_signal.wait().await;
+            self.handle_new_temperature(reading).await;
+        }
+        if other_signal.signaled() {
+            self.other_temperature = other_signal.wait().await.temp;
+        }
+        self.check_overtemperature().await;
+        self.check_sensor_timeout().await;
+        while let Ok(report) = I2C_ERROR_CHANNEL.try_receive() {
+            match report.device {
+                BusDevice::Relays => self.relay_i2c_errors = report.count,
+                BusDevice::AirSensor => self.air_sensor_i2c_errors = report.count,
+                BusDevice::BoardSensor => self.board_sensor_i2c_errors = report.count,
             }
-            let heater_sender = HEATER_POWER.sender();
-            heater_sender.send(HeaterCommand::SetFan(self.fan)).await;
+        }
+        // Check for input events
+        let receiver = INPUT_EVENT_CHANNEL.receiver();
+
+        if !receiver.is_empty() {
+            let event = receiver.receive().await;
+            self.handle_event(event).await;
+        }
+        // Only set again if the status handler runs the PID this tick
+        self.pid_output_raw = 0;
+        match self.status {
+            Status::Initializing => self.init().await,
+            Status::Idle => self.idle().await,
+            Status::Running => self.running().await,
+            Status::CoolingDown => self.cooling_down().await,
+            Status::Baking => self.baking().await,
+            Status::Error => self.error().await,
+            Status::Finished => self.finished().await,
+        }
+        let heater_sender = HEATER_POWER.sender();
+        // Only send the interlock when it changes to keep the heater channel free
+        let cooling_interlock = self.cooling_interlock();
+        if self.cooling_interlock_sent != Some(cooling_interlock) {
+            heater_sender
+                .send(HeaterCommand::SetCoolingInterlock(cooling_interlock))
+                .await;
+            self.cooling_interlock_sent = Some(cooling_interlock);
+        }
+        let exhaust = self.exhaust();
+        if self.exhaust_sent != Some(exhaust) {
+            OUTPUT_COMMAND_CHANNEL
+                .sender()
+                .send(OutputCommand::SetExhaust(exhaust))
+                .await;
+            self.exhaust_sent = Some(exhaust);
+        }
+        if self.light_sent != Some(self.light) {
+            OUTPUT_COMMAND_CHANNEL
+                .sender()
+                .send(OutputCommand::SetLight(self.light))
+                .await;
+            self.light_sent = Some(self.light);
+        }
+        if self.fan_speed_controlled() || self.step_fan_speed().is_some() {
             heater_sender
-                .send(crate::HeaterCommand::SetPower(self.heater_power))
+                .send(HeaterCommand::SetFanSpeed(self.fan_speed))
                 .await;
-            self.send_state();
-            Timer::after_millis((SYSTEM_TICK_MILLIS * 10).into()).await;
+        } else {
+            self.fan_speed = if self.fan { 100 } else { 0 };
+            heater_sender.send(HeaterCommand::SetFan(self.fan)).await;
+        }
+        heater_sender
+            .send(self.power_command(self.heater_power))
+            .await;
+        self.send_state();
+    }
+
+    /// Failed I2C transfers on the shared bus, all devices together
+    pub fn i2c_errors(&self) -> u32 {
+        self.relay_i2c_errors
+            .wrapping_add(self.air_sensor_i2c_errors)
+            .wrapping_add(self.board_sensor_i2c_errors)
+    }
+
+    /// Heater power command for the configured oven type
+    fn power_command(&self, power: u8) -> HeaterCommand {
+        self.oven_type.power_command(power)
+    }
+
+    /// Start the hardware watchdog; from now on each tick must feed it within
+    /// `WATCHDOG_TIMEOUT_MILLIS`.
+    #[cfg(feature = "rp2040")]
+    pub fn start_watchdog(&mut self, mut watchdog: Watchdog) {
+        watchdog.start(Duration::from_millis(WATCHDOG_TIMEOUT_MILLIS));
+        self.watchdog = Some(watchdog);
+    }
+
+    /// Feed the watchdog if one has been started. Builds without the `rp2040` feature
+    /// have no watchdog, so this does nothing there.
+    fn feed_watchdog(&mut self) {
+        #[cfg(feature = "rp2040")]
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.feed();
+        }
+    }
+
+    /// Configure the overshoot guard: within `band` degrees of the peak step's
+    /// temperature the heater power is limited to `max_power`.
+    fn set_overshoot_guard(&mut self, band: f32, max_power: u8) -> Result<(), &'static str> {
+        if !band.is_finite() || band < 0.0 {
+            return Err("band out of range");
+        }
+        if max_power > 100 {
+            return Err("power out of range");
         }
+        self.overshoot_band = band;
+        self.overshoot_max_power = max_power;
+        Ok(())
+    }
+
+    /// Change how often the control loop runs. The PID time step follows this period.
+    fn set_control_period(&mut self, control_period_ms: u32) -> Result<(), &'static str> {
+        if !(MIN_CONTROL_PERIOD_MILLIS..=MAX_CONTROL_PERIOD_MILLIS).contains(&control_period_ms) {
+            return Err("period out of range");
+        }
+        self.control_period_ms = control_period_ms;
+        Ok(())
+    }
+
+    /// Time between control loop iterations in seconds, used as the PID `dt`.
+    pub fn control_period_secs(&self) -> f32 {
+        self.control_period_ms as f32 / 1000.0
+    }
+
+    pub fn status(&self) -> Status {
+        self.status.clone()
+    }
+
+    pub fn heater_power(&self) -> u8 {
+        self.heater_power
+    }
+
+    pub fn fan(&self) -> bool {
+        self.fan
+    }
+
+    /// Fan PWM last sent to the heater task
+    pub fn fan_speed(&self) -> u8 {
+        self.fan_speed
+    }
+
+    pub fn target_temperature(&self) -> f32 {
+        self.target_temperature
+    }
+
+    pub fn current_temperature(&self) -> f32 {
+        self.current_temperature
+    }
+
+    /// Smoothed rate of change of the oven temperature in °C/s, 0 until two readings
+    pub fn temperature_rate(&self) -> f32 {
+        self.temperature_rate
+    }
+
+    /// Time between the starts of the last two ticks, 0 before the second tick. Should
+    /// sit just above the control period, growing when I2C or a channel stalls the loop.
+    pub fn loop_time_ms(&self) -> u32 {
+        self.loop_time_ms
+    }
+
+    pub fn current_step_index(&self) -> usize {
+        self.current_step_index
+    }
+
+    /// Current PID gains as (kp, ki, kd)
+    pub fn pid_parameters(&self) -> (f32, f32, f32) {
+        self.pid_controller.get_parameters()
     }
 
     async fn init(&mut self) {
-        Timer::after_millis((SYSTEM_TICK_MILLIS * 10).into()).await; // 1 second in simulation time
-        self.enter_idle_state();
+        self.report_init_progress(0);
+        self.startup_delay().await;
+        // The SD card must be mounted before the saved profile can be restored
+        let result = self.initialize().await;
+        self.load_settings().await;
+        self.report_init_progress(INIT_PROGRESS_SETTINGS);
+        match result {
+            Ok(()) => self.enter_idle_state(),
+            Err(message) => {
+                info!("Initialization failed: {}", message);
+                self.enter_error_state(message).await;
+            }
+        }
+    }
+
+    /// Check the hardware before allowing a run: the relay board must answer, the
+    /// temperature sensor must produce a plausible reading. Without an SD card the
+    /// built-in default profile stays available.
+    pub async fn initialize(&mut self) -> Result<(), &'static str> {
+        let init_timeout = Duration::from_millis((SYSTEM_TICK_MILLIS * 20).into());
+
+        // The mock sensor only updates after receiving a heater command, so do this first
+        RELAY_SELF_TEST_RESULT.reset();
+        HEATER_POWER.sender().send(HeaterCommand::SelfTest).await;
+        match with_timeout(init_timeout, RELAY_SELF_TEST_RESULT.wait()).await {
+            Ok(true) => {}
+            Ok(false) => return Err("Relay board not responding"),
+            Err(_) => return Err("Relay self-test timed out"),
+        }
+        self.report_init_progress(INIT_PROGRESS_RELAYS);
+
+        match with_timeout(init_timeout, CURRENT_TEMPERATURE.wait()).await {
+            Ok(reading)
+                if (MIN_PLAUSIBLE_TEMPERATURE..=MAX_PLAUSIBLE_TEMPERATURE)
+                    .contains(&reading.temp) =>
+            {
+                self.handle_new_temperature(reading).await;
+            }
+            Ok(_) => return Err("Implausible temperature reading"),
+            Err(_) => return Err("Temperature sensor not responding"),
+        }
+        self.report_init_progress(INIT_PROGRESS_SENSOR);
+
+        if let Err(err) = self.init_sd_card().await {
+            warn!(
+                "SD card unavailable ({:?}), falling back to default profile",
+                err
+            );
+            self.use_default_profile();
+        }
+        self.report_init_progress(INIT_PROGRESS_SD_CARD);
+
+        Ok(())
+    }
+
+    /// Publish startup progress for the splash screen. Init can take several seconds,
+    /// so the watchdog is fed as each stage completes.
+    fn report_init_progress(&mut self, progress_pct: u8) {
+        self.feed_watchdog();
+        INIT_PROGRESS.sender().send(progress_pct);
+    }
+
+    /// Wait out `STARTUP_DELAY_MILLIS`. The watchdog is already running, so it is fed
+    /// between slices rather than left to expire during a long delay.
+    async fn startup_delay(&mut self) {
+        let mut remaining = STARTUP_DELAY_MILLIS;
+        while remaining > 0 {
+            let slice = remaining.min(STARTUP_DELAY_SLICE_MILLIS);
+            Timer::after_millis(slice.into()).await;
+            remaining -= slice;
+            self.feed_watchdog();
+        }
+    }
+
+    /// Temperature to hold with limited heater power once a run has finished. `None`
+    /// (the default) leaves the heater off and lets the oven cool.
+    fn set_finished_hold_target(&mut self, target: Option<f32>) -> Result<(), &'static str> {
+        if let Some(target) = target {
+            if !(0.0..=MAX_PROFILE_TEMPERATURE).contains(&target) {
+                return Err("target out of range");
+            }
+        }
+        self.finished_hold_target = target;
+        if self.status == Status::Finished {
+            self.target_temperature = target.unwrap_or(FINISHED_TARGET_TEMPERATURE);
+            if target.is_none() {
+                self.heater_power = 0;
+                self.fan = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// After a run the oven is safe to open once it has read below `temperature` for
+    /// `samples` consecutive control periods.
+    fn set_safe_to_open(&mut self, temperature: f32, samples: u8) -> Result<(), &'static str> {
+        if !(0.0..=MAX_PROFILE_TEMPERATURE).contains(&temperature) {
+            return Err("temperature out of range");
+        }
+        if samples == 0 {
+            return Err("samples must be at least 1");
+        }
+        self.safe_temperature = temperature;
+        self.safe_samples_required = samples;
+        Ok(())
+    }
+
+    /// Count consecutive readings below the safe temperature. Returns true on the tick
+    /// the oven becomes safe to open.
+    fn update_safe_to_open(&mut self) -> bool {
+        self.cool_samples = count_cool_samples(
+            self.cool_samples,
+            self.current_temperature,
+            self.safe_temperature,
+        );
+        let safe = self.cool_samples >= self.safe_samples_required;
+        let became_safe = safe && !self.safe_to_open;
+        self.safe_to_open = safe;
+        became_safe
+    }
+
+    fn reset_safe_to_open(&mut self) {
+        self.cool_samples = 0;
+        self.safe_to_open = false;
+    }
+
+    /// Apply the settings persisted in flash, if the settings task has loaded them.
+    async fn load_settings(&mut self) {
+        let settings = match with_timeout(
+            Duration::from_millis((SYSTEM_TICK_MILLIS * 10).into()),
+            LOADED_SETTINGS.wait(),
+        )
+        .await
+        {
+            Ok(settings) => settings,
+            Err(_) => {
+                warn!("Timed out waiting for stored settings, using defaults");
+                return;
+            }
+        };
+
+        if !self
+            .pid_controller
+            .update_parameters(settings.kp, settings.ki, settings.kd, true)
+        {
+            warn!("Stored PID gains are out of range, keeping the defaults");
+        }
+        self.cycles_completed = settings.cycles_completed;
+        self.set_control_sensor(settings.control_sensor);
+        self.oven_type = settings.oven_type;
+        if self
+            .set_overshoot_guard(settings.overshoot_band, settings.overshoot_max_power)
+            .is_err()
+        {
+            warn!("Stored overshoot guard is out of range, keeping the defaults");
+        }
+        self.finished_auto_reset_s = settings.finished_auto_reset_s;
+        if self
+            .set_finished_hold_target(settings.finished_hold_target)
+            .is_err()
+        {
+            warn!("Stored hold temperature is out of range, ignoring it");
+        }
+        if self
+            .set_safe_to_open(settings.safe_temperature, settings.safe_to_open_samples)
+            .is_err()
+        {
+            warn!("Stored safe-to-open settings are out of range, keeping the defaults");
+        }
+        if self.set_control_period(settings.control_period_ms).is_err() {
+            warn!("Stored control period is out of range, keeping the default");
+        }
+
+        if !settings.profile_filename.is_empty() {
+            match self
+                .profile_reader
+                .read_profile(settings.profile_filename.as_str())
+                .await
+            {
+                Ok(profile) => {
+                    info!("Restored profile: {}", profile.name.as_str());
+                    self.profile = profile;
+                    self.profile_filename = settings.profile_filename;
+                    self.profile_loaded = true;
+                }
+                Err(err) => {
+                    warn!(
+                        "Could not restore profile {}: {:?}",
+                        settings.profile_filename.as_str(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    /// Queue the current PID gains and profile filename to be written to flash.
+    async fn save_settings(&self) {
+        let (kp, ki, kd) = self.pid_controller.get_parameters();
+        let settings = Settings {
+            kp,
+            ki,
+            kd,
+            profile_filename: self.profile_filename.clone(),
+            cycles_completed: self.cycles_completed,
+            control_sensor: self.control_sensor,
+            oven_type: self.oven_type,
+            overshoot_band: self.overshoot_band,
+            overshoot_max_power: self.overshoot_max_power,
+            finished_auto_reset_s: self.finished_auto_reset_s,
+            finished_hold_target: self.finished_hold_target,
+            safe_temperature: self.safe_temperature,
+            safe_to_open_samples: self.safe_samples_required,
+            control_period_ms: self.control_period_ms,
+        };
+        SAVE_SETTINGS_CHANNEL.sender().send(settings).await;
     }
 
     fn enter_idle_state(&mut self) {
@@ -92,7 +882,7 @@ impl ReflowController {
         self.heater_power = 0;
         self.fan = false;
         self.light = false;
-        self.target_temperature = 25.0;
+        self.target_temperature = self.idle_target;
     }
 
     async fn idle(&mut self) {
@@ -109,12 +899,32 @@ impl ReflowController {
         }
     }
 
-    async fn enter_finished_state(&mut self) {
+    async fn enter_finished_state(&mut self, reason: FinishReason) {
+        info!("Finished: {}", reason);
         self.status = Status::Finished;
+        self.finish_reason = Some(reason);
+        self.finished_time = Instant::now();
+        self.reset_safe_to_open();
+        self.cycles_completed = self.cycles_completed.saturating_add(1);
+        self.save_settings().await;
         self.heater_power = 0;
         self.fan = true;
         self.light = false;
-        self.target_temperature = 25.0;
+        self.target_temperature = self
+            .finished_hold_target
+            .unwrap_or(FINISHED_TARGET_TEMPERATURE);
+        self.pid_controller.reset_integral();
+        SYSTEM_EVENT_CHANNEL
+            .sender()
+            .send(SystemEvent::Finished {
+                peak: self.peak_temperature,
+                duration_s: scaled_elapsed(self.profile_start_time).as_secs() as u32,
+                reason,
+                min: self.run_stats.min(),
+                mean: self.run_stats.mean(),
+                std_dev: self.run_stats.std_dev(),
+            })
+            .await;
         OUTPUT_COMMAND_CHANNEL
             .sender()
             .send(OutputCommand::SetStartButtonLight(crate::LedState::Blink(
@@ -125,8 +935,45 @@ impl ReflowController {
     }
 
     async fn finished(&mut self) {
+        if self.update_safe_to_open() {
+            info!("Oven is safe to open");
+            SYSTEM_EVENT_CHANNEL
+                .sender()
+                .send(SystemEvent::SafeToOpen)
+                .await;
+            OUTPUT_COMMAND_CHANNEL
+                .sender()
+                .send(OutputCommand::SetStartButtonLight(crate::LedState::LedOn))
+                .await;
+        }
+        if self.finished_auto_reset_due() {
+            info!("Auto-reset timeout elapsed and oven is cool, returning to idle");
+            self.exit_finished_state().await;
+            return;
+        }
+        if self.finished_hold_target.is_some() {
+            self.hold_finished_temperature();
+            return;
+        }
         // Wait for user to reset
-        Timer::after_millis((SYSTEM_TICK_MILLIS * 10).into()).await; // 1 second in simulation time
+        sleep_scaled(SYSTEM_TICK_MILLIS * 10).await; // 1 second in simulation time
+    }
+
+    /// Hold the oven at `finished_hold_target`: the fan brings it down while it is
+    /// above the target, then the PID keeps it there with limited power.
+    fn hold_finished_temperature(&mut self) {
+        self.fan = self.current_temperature > self.target_temperature;
+        self.update_heater_power();
+        self.heater_power = self.heater_power.min(FINISHED_HOLD_MAX_POWER);
+    }
+
+    /// Whether the oven is safe to open and the auto-reset timeout has passed since finishing
+    fn finished_auto_reset_due(&self) -> bool {
+        finished_auto_reset_due(
+            self.finished_auto_reset_s,
+            self.safe_to_open,
+            scaled_elapsed(self.finished_time).as_secs(),
+        )
     }
 
     async fn exit_finished_state(&mut self) {
@@ -137,64 +984,285 @@ impl ReflowController {
         self.status = Status::Running;
         self.fan = false;
         self.profile_start_time = Instant::now();
+        self.step_start_time = Instant::now();
+        self.peak_temperature = self.current_temperature;
+        self.run_stats = RunStats::new();
+        self.reset_safe_to_open();
         self.current_step_index = 0;
+        self.step_reached_samples = 0;
+        self.dwelling = self.profile.initial_dwell_s > 0;
         self.update_setpoint();
         // Reset PID integral term for clean profile start
         self.pid_controller.reset_integral();
+        RUN_SCHEDULE_CHANNEL
+            .sender()
+            .send(self.profile.clone())
+            .await;
     }
 
-    fn step_completed(&self) -> bool {
+    /// Returns whether the current step has (reached its temperature, run for its time).
+    fn step_progress(&self) -> (bool, bool) {
+        let temp_reached = self.step_reached_samples >= STEP_CONFIRM_SAMPLES;
+        (temp_reached, self.step_time_remaining() == 0)
+    }
+
+    /// Time left before the current step's step_time is met, in the same units
+    pub fn step_time_remaining(&self) -> u32 {
         let step = &self.profile.steps[self.current_step_index];
         let time_elapsed =
-            (self.step_start_time.elapsed().as_millis() as u32 / SYSTEM_TICK_MILLIS) as u32;
-        let step_end_time = step.step_time;
-        let temp_reached = if step.is_cooling {
-            self.current_temperature <= step.set_temperature
-        } else {
-            self.current_temperature >= (step.set_temperature - 1.0) // Allow small overshoot margin
-        };
-        time_elapsed >= step_end_time && temp_reached
+            (scaled_elapsed(self.step_start_time).as_millis() as u32 / SYSTEM_TICK_MILLIS) as u32;
+        step.step_time.saturating_sub(time_elapsed)
     }
 
-    async fn running(&mut self) {
-        // Check if we've reached the target temperature for the current step
-        self.update_setpoint();
-        if self.step_completed() {
-            // Move to the next step if available
-            if self.current_step_index + 1 < self.profile.steps.len() {
-                self.fan = self.profile.steps[self.current_step_index].has_fan;
-                self.current_step_index += 1;
-                self.step_start_time = Instant::now();
-                self.update_setpoint();
-                // Reset PID integral term for clean step transition
-                self.pid_controller.reset_integral();
-            } else {
+    /// How far the current step's temperature is from the current temperature,
+    /// positive while still heating towards it. Zero outside of a running step.
+    pub fn step_temp_delta(&self) -> f32 {
+        if self.status != Status::Running || self.dwelling {
+            return 0.0;
+        }
+        self.profile.steps[self.current_step_index].set_temperature - self.current_temperature
+    }
+
+    fn step_completed(&self) -> bool {
+        let (temp_reached, time_met) = self.step_progress();
+        time_met && temp_reached
+    }
+
+    /// Overall profile completion, comparing ticks since the run started against
+    /// the profile's total duration in ticks, the unit step_time is counted in.
+    pub fn total_progress_pct(&self) -> u8 {
+        match self.status {
+            Status::Running => {
+                // The profile timeline restarts once the initial dwell is over
+                let mut elapsed = (scaled_elapsed(self.profile_start_time).as_millis()
+                    / SYSTEM_TICK_MILLIS as u64) as u32;
+                if !self.dwelling {
+                    let dwell_ticks = seconds_to_ticks(self.profile.initial_dwell_s);
+                    elapsed = elapsed.saturating_add(dwell_ticks);
+                }
+                self.profile.progress_pct(elapsed)
+            }
+            Status::Finished => 100,
+            _ => 0,
+        }
+    }
+
+    /// Whether the oven is purely cooling, in which case the heat relays must stay off
+    fn cooling_interlock(&self) -> bool {
+        cooling_interlock(
+            &self.status,
+            self.profile.steps[self.current_step_index].is_cooling,
+            self.finished_hold_target,
+        )
+    }
+
+    /// Whether the exhaust should be open: during cooling steps and after a hot stop
+    fn exhaust(&self) -> bool {
+        match self.status {
+            Status::Running => {
+                !self.dwelling && self.profile.steps[self.current_step_index].is_cooling
+            }
+            Status::CoolingDown => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the fan speed is being modulated to follow the cooling rate
+    fn fan_speed_controlled(&self) -> bool {
+        if self.status != Status::Running || self.dwelling {
+            return false;
+        }
+        let step = &self.profile.steps[self.current_step_index];
+        step.is_cooling && step.has_fan && step.fan_speed.is_none()
+    }
+
+    /// Fixed fan speed the current step asks for with an explicit `fan_speed`
+    fn step_fan_speed(&self) -> Option<u8> {
+        if self.status != Status::Running || self.dwelling {
+            return None;
+        }
+        let step = &self.profile.steps[self.current_step_index];
+        step.fan_speed.map(|_| step.fan_speed())
+    }
+
+    async fn running(&mut self) {
+        self.peak_temperature = self.peak_temperature.max(self.current_temperature);
+        if self.dwelling {
+            self.dwell();
+            return;
+        }
+        // Check if we've reached the target temperature for the current step
+        self.update_setpoint();
+        if self.step_completed() {
+            // Move to the next step if available
+            if self.current_step_index + 1 < self.profile.steps.len() {
+                self.fan = self.profile.steps[self.current_step_index].has_fan;
+                self.current_step_index += 1;
+                self.step_start_time = Instant::now();
+                self.step_reached_samples = 0;
+                self.update_setpoint();
+                // Reset PID integral term for clean step transition
+                self.pid_controller.reset_integral();
+            } else {
                 // Completed all steps
                 self.exit_running_state().await;
-                self.enter_finished_state().await;
+                self.enter_finished_state(FinishReason::Completed).await;
                 return;
             }
         }
-        self.heater_power = self
-            .pid_controller
-            .update(self.target_temperature, self.current_temperature);
+        self.update_heater_power();
+
+        // Ease off as the oven approaches the peak so the PID doesn't overshoot it
+        self.heater_power = overshoot_limited_power(
+            &self.profile.steps[self.current_step_index],
+            self.current_temperature,
+            self.heater_power,
+            self.overshoot_band,
+            self.overshoot_max_power,
+        );
+
+        // Back off if the oven is heating faster than the step allows
+        let step = &self.profile.steps[self.current_step_index];
+        let rate_warning =
+            !step.is_cooling && self.temperature_rate > step.max_rate * (1.0 + RATE_WARNING_MARGIN);
+        if rate_warning != self.rate_warning {
+            if rate_warning {
+                warn!(
+                    "Heating at {}°C/s, above the step's max rate of {}°C/s",
+                    self.temperature_rate, step.max_rate
+                );
+            }
+            self.rate_warning = rate_warning;
+        }
+        if rate_warning {
+            self.heater_power /= 2;
+        }
+
+        if self.fan_speed_controlled() {
+            let max_rate = self.profile.steps[self.current_step_index].max_rate;
+            self.fan = true;
+            self.fan_speed = cooling_fan_speed(self.fan_speed, -self.temperature_rate, max_rate);
+        } else if let Some(speed) = self.step_fan_speed() {
+            self.fan = speed > 0;
+            self.fan_speed = speed;
+        }
+    }
+
+    /// Run the PID against the current target and apply its output to the heater
+    fn update_heater_power(&mut self) {
+        // Don't heat, or integrate error, against a reading that is no longer current.
+        // `check_sensor_timeout` raises the error if it stays that way.
+        if self.temperature_stale() {
+            warn!("Temperature reading is stale, heater off");
+            self.heater_power = 0;
+            self.pid_output_raw = 0;
+            return;
+        }
+        self.heater_power = self.pid_controller.update(
+            self.target_temperature,
+            self.current_temperature,
+            self.control_period_secs(),
+        );
+        self.pid_output_raw = self.heater_power;
+    }
+
+    /// Hold at the first step's temperature for the profile's initial dwell. The
+    /// profile timeline, and so the first step, starts once the dwell is over.
+    fn dwell(&mut self) {
+        // initial_dwell_s is in seconds, unlike step_time which is counted in ticks
+        let elapsed_s = scaled_elapsed(self.step_start_time).as_secs();
+        if elapsed_s >= self.profile.initial_dwell_s.into() {
+            info!("Initial dwell complete, starting profile");
+            self.dwelling = false;
+            self.profile_start_time = Instant::now();
+            self.step_start_time = Instant::now();
+            self.step_reached_samples = 0;
+            self.update_setpoint();
+            return;
+        }
+        self.target_temperature = self.profile.steps[0].set_temperature;
+        self.update_heater_power();
+    }
+
+    fn enter_baking_state(&mut self, target: f32, duration_s: u32) {
+        self.status = Status::Baking;
+        self.fan = false;
+        self.light = false;
+        self.target_temperature = target;
+        self.bake_duration_s = duration_s;
+        self.profile_start_time = Instant::now();
+        self.peak_temperature = self.current_temperature;
+        self.run_stats = RunStats::new();
+        self.pid_controller.reset_integral();
+    }
+
+    /// Seconds left before a bake finishes, zero outside of a bake
+    pub fn bake_remaining_s(&self) -> u32 {
+        if self.status != Status::Baking {
+            return 0;
+        }
+        let elapsed = scaled_elapsed(self.profile_start_time).as_secs() as u32;
+        self.bake_duration_s.saturating_sub(elapsed)
+    }
+
+    async fn baking(&mut self) {
+        self.peak_temperature = self.peak_temperature.max(self.current_temperature);
+        if self.bake_remaining_s() == 0 {
+            info!("Bake complete");
+            self.exit_running_state().await;
+            self.enter_finished_state(FinishReason::Completed).await;
+            return;
+        }
+        self.update_heater_power();
+    }
+
+    /// Force the oven into a known safe condition from any status: heater off, fan
+    /// off, no active step. The heater is told immediately rather than on the next
+    /// tick. Callers that want the fan running to cool the oven turn it back on.
+    async fn enter_safe_state(&mut self) {
+        self.dwelling = false;
+        self.rate_warning = false;
+        self.heater_power = 0;
+        self.fan = false;
+        self.light = false;
+        self.target_temperature = 0.0;
+        // Don't wait for the heater task to get through its current power cycle
+        HEATER_FORCE_OFF.signal(());
+        let heater_sender = HEATER_POWER.sender();
+        heater_sender.send(self.power_command(0)).await;
+        heater_sender.send(HeaterCommand::SetFan(false)).await;
     }
 
     async fn exit_running_state(&mut self) {
+        self.enter_safe_state().await;
+        self.fan = true;
+        self.target_temperature = 25.0;
+    }
+
+    fn enter_cooling_down_state(&mut self) {
+        self.status = Status::CoolingDown;
+        self.reset_safe_to_open();
         self.heater_power = 0;
         self.fan = true;
         self.light = false;
         self.target_temperature = 25.0;
     }
 
+    async fn cooling_down(&mut self) {
+        self.heater_power = 0;
+        self.fan = true;
+        if self.update_safe_to_open() {
+            info!("Cooldown complete, returning to idle");
+            self.enter_idle_state();
+        }
+    }
+
     async fn enter_error_state(&mut self, message: &str) {
+        self.error_reset_pending = false;
         self.error_message.clear();
         let _ = self.error_message.push_str(message);
         self.status = Status::Error;
-        self.heater_power = 0;
-        self.fan = false;
-        self.light = false;
-        self.target_temperature = 0.0;
+        self.enter_safe_state().await;
         OUTPUT_COMMAND_CHANNEL
             .sender()
             .send(OutputCommand::SetStartButtonLight(crate::LedState::Blink(
@@ -206,39 +1274,149 @@ impl ReflowController {
 
     async fn error(&mut self) {
         self.heater_power = 0;
-        self.fan = false;
-        self.light = false;
+        // Keep air moving while waiting to cool down for a deferred reset
+        self.fan = self.error_reset_pending;
         self.target_temperature = 0.0;
+        if self.error_reset_pending && self.current_temperature < COOLDOWN_TEMPERATURE {
+            info!("Oven has cooled, completing reset");
+            self.exit_error_state();
+        }
+    }
+
+    /// Latch an overtemperature fault, even if the controller is already in the error
+    /// state for another reason. Once latched it isn't raised again while the oven
+    /// stays over the limit; only `ACK_FAULT` on a cooled oven clears it.
+    async fn check_overtemperature(&mut self) {
+        if self.current_temperature <= OVERTEMPERATURE_LIMIT || self.fault_latched {
+            return;
+        }
+        warn!(
+            "Overtemperature: {}°C is above the {}°C limit",
+            self.current_temperature, OVERTEMPERATURE_LIMIT
+        );
+        self.fault_latched = true;
+        self.enter_error_state(OVERTEMPERATURE_MESSAGE).await;
+    }
+
+    /// Go to the error state once the reading has been stale for
+    /// `STALE_TICKS_BEFORE_FAULT` control periods in a status that heats. Outside of
+    /// those the heater is off anyway, so a sensor that stops updating isn't a fault.
+    async fn check_sensor_timeout(&mut self) {
+        let heating = match self.status {
+            Status::Running | Status::Baking => true,
+            Status::Finished => self.finished_hold_target.is_some(),
+            _ => false,
+        };
+        if !heating || !self.temperature_stale() {
+            self.stale_ticks = 0;
+            return;
+        }
+        self.stale_ticks = self.stale_ticks.saturating_add(1);
+        if self.stale_ticks < STALE_TICKS_BEFORE_FAULT {
+            return;
+        }
+        warn!(
+            "No temperature reading for {} control periods",
+            STALE_TICKS_BEFORE_FAULT
+        );
+        self.stale_ticks = 0;
+        self.enter_error_state(SENSOR_TIMEOUT_MESSAGE).await;
+    }
+
+    /// Leave the error state, unless the oven is still too hot to start another run,
+    /// in which case the reset happens once it has cooled.
+    fn reset_from_error(&mut self) {
+        if self.current_temperature >= COOLDOWN_TEMPERATURE {
+            info!("Cooling down before reset");
+            self.error_reset_pending = true;
+            self.notice.clear();
+            let _ = self.notice.push_str(COOLING_BEFORE_RESET_NOTICE);
+        } else {
+            self.exit_error_state();
+        }
     }
 
     fn exit_error_state(&mut self) {
-        self.status = Status::Idle;
-        self.heater_power = 0;
-        self.fan = false;
-        self.light = false;
-        self.target_temperature = 0.0;
+        if self.error_reset_pending {
+            self.error_reset_pending = false;
+            self.notice.clear();
+        }
         self.error_message.clear();
+        self.enter_idle_state();
+    }
+
+    /// Tell the host whether a command was accepted, with the reason if it wasn't
+    async fn acknowledge(&self, command: &'static str, result: Result<(), &'static str>) {
+        let ack = CommandAck {
+            command,
+            accepted: result.is_ok(),
+            reason: result.err().unwrap_or(""),
+        };
+        COMMAND_ACK_CHANNEL.sender().send(ack).await;
     }
 
     fn send_state(&mut self) {
+        let (step_temp_reached, step_time_met) = if self.status == Status::Running && !self.dwelling
+        {
+            self.step_progress()
+        } else {
+            (false, false)
+        };
+        let (kp, ki, kd) = self.pid_controller.get_parameters();
         let state = ReflowControllerState {
+            schema_version: SCHEMA_VERSION,
             status: self.status.clone(),
             target_temperature: self.target_temperature,
-            current_temperature: self.current_temperature,
+            current_temperature: self.air_temperature(),
+            board_temperature: self.board_temperature(),
+            control_sensor: self.control_sensor,
+            temperature_rate: self.temperature_rate,
+            rate_warning: self.rate_warning,
             door_closed: self.door_closed,
             fan: self.fan,
+            fan_speed: if self.fan { self.fan_speed } else { 0 },
             light: self.light,
             heater_power: self.heater_power,
+            pid_output_raw: self.pid_output_raw,
             timer: if self.status == Status::Idle {
                 0
             } else {
-                self.profile_start_time.elapsed().as_millis() as u32 / SYSTEM_TICK_MILLIS
+                scaled_elapsed(self.profile_start_time).as_millis() as u32 / SYSTEM_TICK_MILLIS
             },
             current_profile: self.profile.name.clone(),
-            current_step: self.profile.steps[self.current_step_index]
-                .step_name
-                .to_str(),
+            active_profile_checksum: self.profile.checksum(),
+            current_step: if self.dwelling {
+                "Dwell"
+            } else {
+                self.profile.steps[self.current_step_index]
+                    .step_name
+                    .to_str()
+            },
+            current_step_index: self.current_step_index as u8,
+            total_steps: self.profile.steps.len() as u8,
+            step_temp_reached,
+            step_time_met,
+            step_time_remaining: if self.status == Status::Running && !self.dwelling {
+                self.step_time_remaining()
+            } else {
+                0
+            },
+            step_temp_delta: self.step_temp_delta(),
+            total_progress_pct: self.total_progress_pct(),
+            profile_duration_s: self.profile.total_duration_s(),
+            bake_remaining_s: self.bake_remaining_s(),
+            loop_time_ms: self.loop_time_ms,
+            uptime_s: Instant::now().as_secs() as u32,
+            i2c_errors: self.i2c_errors(),
+            kp,
+            ki,
+            kd,
+            cycles_completed: self.cycles_completed,
+            finish_reason: self.finish_reason,
             error_message: self.error_message.clone(),
+            fault_latched: self.fault_latched,
+            safe_to_open: self.safe_to_open,
+            notice: self.notice.clone(),
         };
         CURRENT_STATE.sender().send(state);
     }
@@ -256,7 +1434,7 @@ impl ReflowController {
                 - self.target_temperature;
             let time_remaining = self.profile.steps[self.current_step_index]
                 .target_time
-                .saturating_sub(self.profile_start_time.elapsed().as_secs() as u32);
+                .saturating_sub(scaled_elapsed(self.profile_start_time).as_secs() as u32);
             if time_remaining > 0 && set_temp_diff > 0.0 {
                 let adjustment = difference / time_remaining as f32;
                 self.target_temperature = self.target_temperature + adjustment;
@@ -269,23 +1447,60 @@ impl ReflowController {
         {
             self.target_temperature = self.profile.steps[self.current_step_index].set_temperature;
         }
+
+        // A NaN would otherwise stick, since the ramp builds on the previous target
+        if !self.target_temperature.is_finite() {
+            warn!("Setpoint is not finite, using the step temperature");
+            self.target_temperature = self.profile.steps[self.current_step_index].set_temperature;
+        }
     }
 
     async fn handle_event(&mut self, event: Event) {
+        self.event_log
+            .record(Instant::now().as_millis(), event.clone());
         match event {
             Event::StartCommand => {
-                if self.status == Status::Idle && self.door_closed {
+                if self.status != Status::Idle || !self.door_closed {
+                    info!("Cannot start: either not idle or door is open");
+                } else if !self.profile_loaded {
+                    info!("Cannot start: no profile has been loaded");
+                } else if let Err(err) = self.profile.validate() {
+                    info!("Cannot start: active profile is invalid ({})", err);
+                } else {
                     info!("Starting reflow process");
                     self.enter_running_state().await;
+                }
+            }
+            Event::StartBake { target, duration_s } => {
+                if self.status != Status::Idle || !self.door_closed {
+                    info!("Cannot bake: either not idle or door is open");
+                } else if !(0.0..=MAX_PROFILE_TEMPERATURE).contains(&target) || duration_s == 0 {
+                    info!(
+                        "Cannot bake: invalid target {} or duration {}s",
+                        target, duration_s
+                    );
                 } else {
-                    info!("Cannot start: either not idle or door is open");
+                    info!("Baking at {}°C for {}s", target, duration_s);
+                    self.enter_baking_state(target, duration_s);
                 }
             }
             Event::StopCommand => {
-                if self.status == Status::Running {
+                let cooling_step = self.status == Status::Running
+                    && self.profile.steps[self.current_step_index].step_name == StepName::Cooling;
+                if cooling_step {
+                    // The board has already reflowed, so this still counts as a finished run
+                    info!("Stopped during the cooling step, finishing reflow process");
+                    self.exit_running_state().await;
+                    self.enter_finished_state(FinishReason::StoppedEarly).await;
+                } else if self.status == Status::Running || self.status == Status::Baking {
                     info!("Stopping reflow process");
                     self.exit_running_state().await;
-                    self.enter_idle_state();
+                    if self.current_temperature > COOLDOWN_TEMPERATURE {
+                        info!("Oven is hot, cooling down before returning to idle");
+                        self.enter_cooling_down_state();
+                    } else {
+                        self.enter_idle_state();
+                    }
                 }
             }
             Event::ResetCommand => {
@@ -294,28 +1509,212 @@ impl ReflowController {
                     self.exit_finished_state().await;
                 }
                 if self.status == Status::Error {
-                    info!("Resetting from error state to idle");
+                    if self.fault_latched {
+                        warn!("Overtemperature fault latched, send ACK_FAULT to clear it");
+                    } else {
+                        info!("Resetting from error state to idle");
+                        self.reset_from_error();
+                    }
+                }
+            }
+            Event::SetLight(on) => {
+                if matches!(self.status, Status::Running | Status::Baking) {
+                    warn!("Ignoring light command while heating");
+                    self.acknowledge("LIGHT", Err("busy")).await;
+                } else {
+                    self.light = on;
+                    self.acknowledge("LIGHT", Ok(())).await;
+                }
+            }
+            Event::FullReset => match self.status {
+                Status::Error if self.fault_latched => {
+                    self.acknowledge("FULL_RESET", Err(FAULT_LATCHED_REASON))
+                        .await;
+                }
+                Status::Idle | Status::Finished | Status::Error => {
+                    info!("Full controller reset");
+                    self.full_reset().await;
+                    self.acknowledge("FULL_RESET", Ok(())).await;
+                }
+                _ => self.acknowledge("FULL_RESET", Err("busy")).await,
+            },
+            Event::SetControlSensor(sensor) => {
+                if matches!(self.status, Status::Running | Status::Baking) {
+                    self.acknowledge("CONTROL_SENSOR", Err("busy")).await;
+                } else {
+                    self.set_control_sensor(sensor);
+                    self.save_settings().await;
+                    self.acknowledge("CONTROL_SENSOR", Ok(())).await;
+                }
+            }
+            Event::SetOvenType(oven_type) => {
+                if matches!(self.status, Status::Running | Status::Baking) {
+                    self.acknowledge("OVEN_TYPE", Err("busy")).await;
+                } else {
+                    info!("Oven type set to {}", oven_type);
+                    self.oven_type = oven_type;
+                    self.save_settings().await;
+                    self.acknowledge("OVEN_TYPE", Ok(())).await;
+                }
+            }
+            Event::SetOvershootGuard { band, max_power } => {
+                if matches!(self.status, Status::Running | Status::Baking) {
+                    self.acknowledge("OVERSHOOT_GUARD", Err("busy")).await;
+                } else {
+                    let result = self.set_overshoot_guard(band, max_power);
+                    if result.is_ok() {
+                        info!(
+                            "Overshoot guard: {}% within {}°C of the peak",
+                            max_power, band
+                        );
+                        self.save_settings().await;
+                    }
+                    self.acknowledge("OVERSHOOT_GUARD", result).await;
+                }
+            }
+            Event::SetFinishedAutoReset(timeout_s) => {
+                match timeout_s {
+                    Some(timeout_s) => info!("Auto-reset {}s after a run once safe", timeout_s),
+                    None => info!("Auto-reset after a run disabled"),
+                }
+                self.finished_auto_reset_s = timeout_s;
+                self.save_settings().await;
+                self.acknowledge("AUTO_RESET", Ok(())).await;
+            }
+            Event::SetFinishedHoldTarget(target) => {
+                let result = self.set_finished_hold_target(target);
+                if result.is_ok() {
+                    match target {
+                        Some(target) => info!("Holding {}°C after a run", target),
+                        None => info!("Letting the oven cool after a run"),
+                    }
+                    self.save_settings().await;
+                }
+                self.acknowledge("HOLD_TARGET", result).await;
+            }
+            Event::SetSafeToOpen {
+                temperature,
+                samples,
+            } => {
+                let result = self.set_safe_to_open(temperature, samples);
+                if result.is_ok() {
+                    info!(
+                        "Safe to open after {} readings below {}°C",
+                        samples, temperature
+                    );
+                    self.save_settings().await;
+                }
+                self.acknowledge("SAFE_TO_OPEN", result).await;
+            }
+            Event::SetControlPeriod(control_period_ms) => {
+                if matches!(self.status, Status::Running | Status::Baking) {
+                    self.acknowledge("CONTROL_PERIOD", Err("busy")).await;
+                } else {
+                    let result = self.set_control_period(control_period_ms);
+                    if result.is_ok() {
+                        info!("Control period set to {}ms", control_period_ms);
+                        self.save_settings().await;
+                    }
+                    self.acknowledge("CONTROL_PERIOD", result).await;
+                }
+            }
+            Event::TestRelay { relay, on } => {
+                if self.status != Status::Idle {
+                    warn!("Ignoring relay test outside of idle");
+                    self.acknowledge("RELAY", Err("only available in idle"))
+                        .await;
+                } else if !(1..=RELAY_COUNT).contains(&relay) {
+                    self.acknowledge("RELAY", Err("no such relay")).await;
+                } else {
+                    HEATER_POWER
+                        .sender()
+                        .send(HeaterCommand::TestRelay { relay, on })
+                        .await;
+                    self.acknowledge("RELAY", Ok(())).await;
+                }
+            }
+            Event::ResetCycleCount => {
+                info!(
+                    "Resetting completed cycle count ({})",
+                    self.cycles_completed
+                );
+                self.cycles_completed = 0;
+                self.save_settings().await;
+                self.acknowledge("RESET_CYCLES", Ok(())).await;
+            }
+            Event::DumpEvents => {
+                EVENT_LOG_CHANNEL
+                    .sender()
+                    .send(self.event_log.to_vec())
+                    .await;
+            }
+            Event::AcknowledgeFault => {
+                if !self.fault_latched {
+                    self.acknowledge("ACK_FAULT", Err("no fault latched")).await;
+                } else if self.current_temperature >= COOLDOWN_TEMPERATURE {
+                    self.acknowledge("ACK_FAULT", Err("oven still hot")).await;
+                } else {
+                    info!("Overtemperature fault acknowledged");
+                    self.fault_latched = false;
                     self.exit_error_state();
+                    self.acknowledge("ACK_FAULT", Ok(())).await;
+                }
+            }
+            Event::ClearError => {
+                if self.fault_latched {
+                    self.acknowledge("CLEAR_ERROR", Err(FAULT_LATCHED_REASON))
+                        .await;
+                } else if self.status == Status::Error {
+                    info!("Clearing error state");
+                    // Accepted even while hot: the reset then completes once the oven
+                    // has cooled, with COOLING_BEFORE_RESET_NOTICE shown until it does
+                    self.reset_from_error();
+                    self.acknowledge("CLEAR_ERROR", Ok(())).await;
+                } else {
+                    info!("Cannot clear error: not in error state");
+                    self.acknowledge("CLEAR_ERROR", Err("not in error state"))
+                        .await;
+                }
+            }
+            Event::ThermocoupleFault(open) => {
+                if self.status != Status::Error {
+                    let message = if open {
+                        "Thermocouple disconnected"
+                    } else {
+                        "Thermocouple shorted"
+                    };
+                    info!("{}, entering error state", message);
+                    self.enter_error_state(message).await;
                 }
             }
             Event::DoorStateChanged(closed) => {
                 self.door_closed = closed;
+                if !closed && self.status == Status::Baking {
+                    info!("Door opened while baking, entering error state");
+                    self.enter_error_state("Door opened while baking!").await;
+                }
                 if !closed && self.status == Status::Running {
                     if self.profile.steps[self.current_step_index].step_name != StepName::Cooling {
                         info!("Door opened while running, entering error state");
                         self.enter_error_state("Door opened while running!").await;
                     } else {
-                        info!("Door opened during cooling step, stopping reflow process");
+                        info!("Door opened during cooling step, finishing reflow process");
+                        self.exit_running_state().await;
+                        self.enter_finished_state(FinishReason::DoorOpened).await;
                     }
                 }
             }
             Event::LoadProfile(filename) => {
                 if self.status == Status::Idle {
                     info!("Loading profile: {}", filename.as_str());
-                    match self.sd_reader.read_profile(filename.as_str()).await {
+                    match self.profile_reader.read_profile(filename.as_str()).await {
                         Ok(profile) => {
                             info!("Successfully loaded profile: {}", profile.name.as_str());
+                            self.notice.clear();
                             self.profile = profile.clone();
+                            self.profile_filename = filename.clone();
+                            self.profile_loaded = true;
+                            self.save_settings().await;
                             // Send active profile over USB
                             let sender = ACTIVE_PROFILE_CHANNEL.sender();
                             sender.send(profile).await;
@@ -333,6 +1732,10 @@ impl ReflowController {
                             SdProfileError::SdCardError => {
                                 self.enter_error_state("SD card error").await;
                             }
+                            SdProfileError::NoCard => {
+                                info!("No SD card, keeping the default profile");
+                                self.use_default_profile();
+                            }
                             SdProfileError::TooManyProfiles => {
                                 self.enter_error_state("Too many profiles").await;
                             }
@@ -358,41 +1761,1085 @@ impl ReflowController {
                     }
                 }
             }
+            Event::GetProfile(filename) => {
+                info!("Reading profile for preview: {}", filename.as_str());
+                match self.profile_reader.read_profile(filename.as_str()).await {
+                    Ok(profile) => {
+                        let sender = PROFILE_PREVIEW_CHANNEL.sender();
+                        sender.send(profile).await;
+                    }
+                    Err(err) => {
+                        info!("Error reading profile for preview: {:?}", err);
+                    }
+                }
+            }
+            Event::SetIdleTarget(target) => {
+                if !target.is_finite() || target < 0.0 || target > MAX_PROFILE_TEMPERATURE {
+                    warn!("Rejecting idle target temperature {}", target);
+                    self.acknowledge("IDLE_TARGET", Err("target out of range"))
+                        .await;
+                } else {
+                    info!("Setting idle target temperature to {}", target);
+                    self.idle_target = target;
+                    if self.status == Status::Idle {
+                        self.target_temperature = target;
+                    }
+                    self.acknowledge("IDLE_TARGET", Ok(())).await;
+                }
+            }
+            Event::PreviewProfile(interval_s) => {
+                info!("Previewing setpoint curve every {}s", interval_s);
+                let samples = self.profile.preview(interval_s);
+                SETPOINT_PREVIEW_CHANNEL.sender().send(samples).await;
+            }
             Event::SimulationReset => {
                 info!("Triggering simulation reset");
                 let heater_sender = HEATER_POWER.sender();
                 heater_sender.send(HeaterCommand::SimulationReset).await;
             }
-            Event::UpdatePidParameters { kp, ki, kd } => {
-                info!("Updating PID parameters: Kp={}, Ki={}, Kd={}", kp, ki, kd);
-                // Update PID controller parameters with integral reset for stability
-                self.pid_controller.update_parameters(kp, ki, kd, true);
+            Event::UpdatePidParameters { kp, ki, kd } => match validate_gains(kp, ki, kd) {
+                Ok(()) => {
+                    info!("Updating PID parameters: Kp={}, Ki={}, Kd={}", kp, ki, kd);
+                    // Update PID controller parameters with integral reset for stability
+                    self.pid_controller.update_parameters(kp, ki, kd, true);
+                    self.save_settings().await;
 
-                // Also send to heater task for logging (though it doesn't use PID directly)
-                let heater_sender = HEATER_POWER.sender();
-                heater_sender.send(HeaterCommand::UpdatePidParameters { kp, ki, kd }).await;
+                    // Also send to heater task for logging (though it doesn't use PID directly)
+                    let heater_sender = HEATER_POWER.sender();
+                    heater_sender
+                        .send(HeaterCommand::UpdatePidParameters { kp, ki, kd })
+                        .await;
+                    self.acknowledge("PID", Ok(())).await;
+                }
+                Err(reason) => {
+                    warn!(
+                        "Rejecting PID parameters Kp={}, Ki={}, Kd={}: {}",
+                        kp, ki, kd, reason
+                    );
+                    self.acknowledge("PID", Err(reason)).await;
+                }
+            },
+            Event::SetThermalParams {
+                max_rate,
+                thermal_mass,
+                loss,
+            } => {
+                let valid = [max_rate, thermal_mass, loss]
+                    .iter()
+                    .all(|value| value.is_finite() && *value >= 0.0);
+                if !valid {
+                    warn!(
+                        "Rejecting thermal parameters: max_rate={}, mass={}, loss={}",
+                        max_rate, thermal_mass, loss
+                    );
+                    self.acknowledge("THERMAL_PARAMS", Err("value out of range"))
+                        .await;
+                } else {
+                    info!(
+                        "Updating simulation thermal parameters: max_rate={}, mass={}, loss={}",
+                        max_rate, thermal_mass, loss
+                    );
+                    let heater_sender = HEATER_POWER.sender();
+                    heater_sender
+                        .send(HeaterCommand::SetThermalParams {
+                            max_rate,
+                            thermal_mass,
+                            loss,
+                        })
+                        .await;
+                    self.acknowledge("THERMAL_PARAMS", Ok(())).await;
+                }
             }
         }
         self.send_state();
     }
 
-    async fn handle_new_temperature(&mut self, new_temperature: f32) {
-        self.current_temperature = new_temperature;
+    async fn handle_new_temperature(&mut self, reading: TemperatureReading) {
+        if !reading.temp.is_finite() {
+            warn!("Ignoring non-finite temperature reading");
+            return;
+        }
+        if let Some(last_time) = self.last_temperature_time {
+            if let Some(elapsed) = reading.at.checked_duration_since(last_time) {
+                let dt = (elapsed * TIME_SCALE).as_millis() as f32 / 1000.0;
+                if dt > 0.0 {
+                    let rate = (reading.temp - self.current_temperature) / dt;
+                    self.temperature_rate =
+                        RATE_SMOOTHING * rate + (1.0 - RATE_SMOOTHING) * self.temperature_rate;
+                }
+            }
+        }
+        self.last_temperature_time = Some(reading.at);
+        self.current_temperature = reading.temp;
+
+        if matches!(self.status, Status::Running | Status::Baking) && !self.dwelling {
+            self.run_stats.add(reading.temp);
+        }
+
+        if self.status == Status::Running {
+            let step = &self.profile.steps[self.current_step_index];
+            self.step_reached_samples = if step_temperature_reached(step, reading.temp) {
+                self.step_reached_samples.saturating_add(1)
+            } else {
+                0
+            };
+        }
+    }
+
+    /// Switch the sensor the controller regulates on. The readings start over so the
+    /// rate and staleness checks don't mix the two sensors.
+    fn set_control_sensor(&mut self, sensor: ControlSensor) {
+        if sensor == self.control_sensor {
+            return;
+        }
+        if sensor == ControlSensor::Board && !cfg!(feature = "board_sensor") {
+            warn!("Controlling on the board sensor without the board_sensor feature");
+        }
+        info!("Controlling on the {} sensor", sensor);
+        self.control_sensor = sensor;
+        core::mem::swap(&mut self.current_temperature, &mut self.other_temperature);
+        self.last_temperature_time = None;
+        self.temperature_rate = 0.0;
+    }
+
+    /// Latest oven air temperature, whichever sensor is being controlled on
+    pub fn air_temperature(&self) -> f32 {
+        match self.control_sensor {
+            ControlSensor::Air => self.current_temperature,
+            ControlSensor::Board => self.other_temperature,
+        }
+    }
+
+    /// Latest board temperature, 0 if there's no board sensor
+    pub fn board_temperature(&self) -> f32 {
+        match self.control_sensor {
+            ControlSensor::Air => self.other_temperature,
+            ControlSensor::Board => self.current_temperature,
+        }
+    }
+
+    /// Age of the last temperature reading, or `None` if nothing has been read yet
+    pub fn reading_age(&self) -> Option<Duration> {
+        self.last_temperature_time
+            .map(|at| Instant::now().saturating_duration_since(at))
+    }
+
+    /// True if the sensor has stopped delivering readings
+    pub fn temperature_stale(&self) -> bool {
+        match self.reading_age() {
+            Some(age) => age > Duration::from_millis(STALE_READING_MILLIS),
+            None => true,
+        }
     }
 
     pub async fn get_available_profiles(
         &self,
     ) -> Result<heapless::Vec<heapless::String<64>, 16>, SdProfileError> {
-        self.sd_reader.list_profiles().await
+        self.profile_reader.list_profiles().await
     }
 
+    /// Mount the SD card, retrying transient errors. A missing card is reported
+    /// straight away as `SdProfileError::NoCard`.
     pub async fn init_sd_card(&mut self) -> Result<(), SdProfileError> {
-        self.sd_reader.init().await
+        let mut attempts = 0;
+        loop {
+            match self.profile_reader.init().await {
+                Ok(()) => return Ok(()),
+                Err(SdProfileError::SdCardError) if attempts < SD_INIT_RETRIES => {
+                    attempts += 1;
+                    warn!(
+                        "SD card init failed (attempt {}/{}); retrying...",
+                        attempts,
+                        SD_INIT_RETRIES + 1
+                    );
+                    Timer::after_millis((SYSTEM_TICK_MILLIS * 2).into()).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Rebuild the controller as if it had just been constructed and initialized:
+    /// fresh PID, default profile, no error or timers. The persisted settings, the
+    /// configuration set at startup and what is known about the hardware are kept.
+    async fn full_reset(&mut self) {
+        let (kp, ki, kd) = self.pid_parameters();
+        let previous = core::mem::replace(self, Self::with_pid(kp, ki, kd));
+
+        #[cfg(feature = "rp2040")]
+        {
+            self.watchdog = previous.watchdog;
+        }
+        self.profile_reader = previous.profile_reader;
+        self.control_period_ms = previous.control_period_ms;
+        self.oven_type = previous.oven_type;
+        self.finished_auto_reset_s = previous.finished_auto_reset_s;
+        self.finished_hold_target = previous.finished_hold_target;
+        self.safe_temperature = previous.safe_temperature;
+        self.safe_samples_required = previous.safe_samples_required;
+        self.overshoot_band = previous.overshoot_band;
+        self.overshoot_max_power = previous.overshoot_max_power;
+        self.cycles_completed = previous.cycles_completed;
+        self.control_sensor = previous.control_sensor;
+        self.door_closed = previous.door_closed;
+        self.current_temperature = previous.current_temperature;
+        self.other_temperature = previous.other_temperature;
+        self.last_temperature_time = previous.last_temperature_time;
+        self.relay_i2c_errors = previous.relay_i2c_errors;
+        self.air_sensor_i2c_errors = previous.air_sensor_i2c_errors;
+        self.board_sensor_i2c_errors = previous.board_sensor_i2c_errors;
+        self.idle_target = previous.idle_target;
+
+        // The rebuilt controller has the default profile. It only counts as loaded if
+        // it was already the one in use, a profile from the SD card has to be loaded
+        // again before the next run.
+        if previous.profile_loaded && previous.profile_filename.is_empty() {
+            self.use_default_profile();
+        }
+        self.enter_safe_state().await;
+        self.enter_idle_state();
+        ACTIVE_PROFILE_CHANNEL
+            .sender()
+            .send(self.profile.clone())
+            .await;
+    }
+
+    /// Select the built-in profile so a run is still possible without an SD card
+    fn use_default_profile(&mut self) {
+        self.profile = create_default_profile();
+        self.profile_filename.clear();
+        self.profile_loaded = true;
+        self.notice.clear();
+        let _ = self.notice.push_str(NO_SD_CARD_NOTICE);
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
-pub async fn controller_task() {
+pub async fn controller_task(r: crate::WatchdogResources) {
     let mut controller = ReflowController::new();
+    controller.start_watchdog(Watchdog::new(r.watchdog));
     controller.run().await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::create_lead_free_profile;
+
+    fn step(name: StepName) -> Step {
+        create_lead_free_profile()
+            .steps
+            .into_iter()
+            .find(|step| step.step_name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn overshoot_guard_limits_power_near_the_peak() {
+        let reflow = step(StepName::ReflowRamp);
+        let peak = reflow.set_temperature;
+        assert_eq!(
+            overshoot_limited_power(&reflow, peak - 20.0, 80, 10.0, 30),
+            80
+        );
+        assert_eq!(
+            overshoot_limited_power(&reflow, peak - 10.0, 80, 10.0, 30),
+            30
+        );
+        assert_eq!(
+            overshoot_limited_power(&reflow, peak - 2.0, 80, 10.0, 30),
+            30
+        );
+        assert_eq!(
+            overshoot_limited_power(&reflow, peak - 2.0, 20, 10.0, 30),
+            20
+        );
+    }
+
+    #[test]
+    fn finished_returns_to_idle_once_safe_and_timed_out() {
+        assert!(!finished_auto_reset_due(None, true, 3600));
+        assert!(!finished_auto_reset_due(Some(60), false, 3600));
+        assert!(!finished_auto_reset_due(Some(60), true, 59));
+        assert!(finished_auto_reset_due(Some(60), true, 60));
+    }
+
+    #[test]
+    fn finished_interlock_released_only_with_a_hold_target() {
+        assert!(cooling_interlock(&Status::Finished, false, None));
+        assert!(!cooling_interlock(&Status::Finished, false, Some(100.0)));
+        assert!(cooling_interlock(&Status::CoolingDown, false, Some(100.0)));
+        assert!(cooling_interlock(&Status::Running, true, Some(100.0)));
+        assert!(!cooling_interlock(&Status::Running, false, None));
+        assert!(!cooling_interlock(&Status::Idle, false, None));
+    }
+
+    #[test]
+    fn warm_reading_resets_the_cool_sample_count() {
+        let mut samples = 0;
+        for _ in 0..4 {
+            samples = count_cool_samples(samples, 45.0, 50.0);
+        }
+        assert_eq!(samples, 4);
+        samples = count_cool_samples(samples, 50.0, 50.0);
+        assert_eq!(samples, 0);
+        samples = count_cool_samples(samples, 45.0, 50.0);
+        assert_eq!(samples, 1);
+    }
+
+    #[test]
+    fn overshoot_guard_only_applies_to_the_reflow_ramp() {
+        let soak = step(StepName::Soak);
+        let temperature = soak.set_temperature - 2.0;
+        assert_eq!(
+            overshoot_limited_power(&soak, temperature, 80, 10.0, 30),
+            80
+        );
+    }
+
+    #[test]
+    fn with_pid_sets_the_starting_gains() {
+        let controller = ReflowController::with_pid(2.0, 0.25, 0.5);
+        assert_eq!(controller.pid_parameters(), (2.0, 0.25, 0.5));
+        assert_eq!(
+            ReflowController::new().pid_parameters(),
+            (DEFAULT_KP, DEFAULT_KI, DEFAULT_KD)
+        );
+    }
+
+    #[test]
+    fn run_stats_summarise_the_samples() {
+        let mut stats = RunStats::new();
+        assert_eq!(stats.min(), 0.0);
+        assert_eq!(stats.max(), 0.0);
+        assert_eq!(stats.mean(), 0.0);
+        assert_eq!(stats.std_dev(), 0.0);
+
+        for sample in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.add(sample);
+        }
+        assert_eq!(stats.count(), 8);
+        assert_eq!(stats.min(), 2.0);
+        assert_eq!(stats.max(), 9.0);
+        assert_eq!(stats.mean(), 5.0);
+        assert!((stats.std_dev() - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn event_log_drops_the_oldest_event_once_full() {
+        let mut log = EventLog::new();
+        log.record(0, Event::StartCommand);
+        log.record(1, Event::StopCommand);
+        let entries = log.to_vec();
+        assert_eq!(entries[0].event, Event::StartCommand);
+        assert_eq!(entries[1].event, Event::StopCommand);
+
+        for at_ms in 2..EVENT_LOG_CAPACITY as u64 + 2 {
+            log.record(at_ms, Event::ResetCommand);
+        }
+        assert_eq!(log.len(), EVENT_LOG_CAPACITY);
+        let times: Vec<u64, EVENT_LOG_CAPACITY> = log.entries().map(|entry| entry.at_ms).collect();
+        assert_eq!(times[0], 2);
+        assert!(times.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    /// Feed a reading taken `second` simulated seconds after boot
+    fn feed_reading(controller: &mut ReflowController, second: u64, temp: f32) {
+        let at = Instant::from_millis(second * 1000 / crate::TIME_SCALE as u64);
+        embassy_futures::block_on(
+            controller.handle_new_temperature(TemperatureReading { temp, at }),
+        );
+    }
+
+    #[test]
+    fn temperature_rate_is_smoothed_from_successive_readings() {
+        let mut controller = ReflowController::new();
+        let mut rates = [0.0; 4];
+        for (second, temp) in [25.0, 27.0, 29.0, 31.0].into_iter().enumerate() {
+            feed_reading(&mut controller, second as u64, temp);
+            rates[second] = controller.temperature_rate();
+        }
+        // The first sample has nothing to compare with, then 2C/s is approached
+        assert_eq!(rates[0], 0.0);
+        assert!((rates[1] - 0.6).abs() < 1e-4);
+        assert!((rates[2] - 1.02).abs() < 1e-4);
+        assert!((rates[3] - 1.314).abs() < 1e-4);
+
+        // A non-finite reading leaves the rate alone
+        feed_reading(&mut controller, 4, f32::NAN);
+        assert!((controller.temperature_rate() - 1.314).abs() < 1e-4);
+    }
+
+    #[test]
+    fn temperature_rate_settles_on_a_steady_ramp() {
+        let mut controller = ReflowController::new();
+        for second in 0..30 {
+            feed_reading(&mut controller, second, 200.0 - second as f32);
+        }
+        assert!((controller.temperature_rate() + 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn switching_the_control_sensor_restarts_the_rate() {
+        let mut controller = ReflowController::new();
+        for second in 0..5 {
+            feed_reading(&mut controller, second, 25.0 + 2.0 * second as f32);
+        }
+        assert!(controller.temperature_rate() > 0.0);
+
+        controller.set_control_sensor(ControlSensor::Board);
+        assert_eq!(controller.temperature_rate(), 0.0);
+        // The first board reading has nothing to compare with
+        feed_reading(&mut controller, 5, 40.0);
+        assert_eq!(controller.temperature_rate(), 0.0);
+    }
+
+    #[test]
+    fn step_temperature_needs_consecutive_readings() {
+        let mut controller = ReflowController::new();
+        controller.status = Status::Running;
+        let target = controller.profile.steps[0].set_temperature;
+        // One low sample starts the count over
+        let readings = [target, target, target - 5.0, target, target];
+        for (second, temp) in readings.into_iter().enumerate() {
+            feed_reading(&mut controller, second as u64, temp);
+        }
+        assert!(!controller.step_progress().0);
+
+        feed_reading(&mut controller, readings.len() as u64, target);
+        assert!(controller.step_progress().0);
+    }
+
+    /// Drives the controller on the host through `tick()`, with the other tasks
+    /// replaced by the test: it signals the temperatures and injects the events.
+    #[cfg(feature = "std")]
+    mod harness {
+        use super::*;
+        use crate::profile::{ProfileBuilder, PROFILE_STEPS};
+        use embassy_futures::{block_on, select::select, yield_now};
+
+        use crate::lock_statics;
+
+        /// Keep every channel the controller sends on empty so `tick()` never waits
+        async fn drain_outputs() {
+            loop {
+                while COMMAND_ACK_CHANNEL.try_receive().is_ok() {}
+                drain_all_but_acks();
+                yield_now().await;
+            }
+        }
+
+        fn drain_all_but_acks() {
+            while HEATER_POWER.try_receive().is_ok() {}
+            while OUTPUT_COMMAND_CHANNEL.try_receive().is_ok() {}
+            while SYSTEM_EVENT_CHANNEL.try_receive().is_ok() {}
+            while RUN_SCHEDULE_CHANNEL.try_receive().is_ok() {}
+            while ACTIVE_PROFILE_CHANNEL.try_receive().is_ok() {}
+            while PROFILE_LIST_CHANNEL.try_receive().is_ok() {}
+            while PROFILE_PREVIEW_CHANNEL.try_receive().is_ok() {}
+            while EVENT_LOG_CHANNEL.try_receive().is_ok() {}
+            while SETPOINT_PREVIEW_CHANNEL.try_receive().is_ok() {}
+            while SAVE_SETTINGS_CHANNEL.try_receive().is_ok() {}
+        }
+
+        /// One control loop iteration with `temperature` as the latest reading, then
+        /// wait a system tick so step times advance
+        fn tick(controller: &mut ReflowController, temperature: f32) {
+            CURRENT_TEMPERATURE.signal(TemperatureReading::new(temperature));
+            tick_without_reading(controller);
+        }
+
+        /// One control loop iteration with the sensor silent
+        fn tick_without_reading(controller: &mut ReflowController) {
+            block_on(select(controller.tick(), drain_outputs()));
+            block_on(Timer::after_millis(SYSTEM_TICK_MILLIS.into()));
+        }
+
+        fn send(controller: &mut ReflowController, event: Event, temperature: f32) {
+            INPUT_EVENT_CHANNEL.try_send(event).unwrap();
+            tick(controller, temperature);
+        }
+
+        /// Send `event` and return the ACK or NAK it was answered with
+        fn send_for_ack(
+            controller: &mut ReflowController,
+            event: Event,
+            temperature: f32,
+        ) -> CommandAck {
+            while COMMAND_ACK_CHANNEL.try_receive().is_ok() {}
+            INPUT_EVENT_CHANNEL.try_send(event).unwrap();
+            CURRENT_TEMPERATURE.signal(TemperatureReading::new(temperature));
+            block_on(select(controller.tick(), async {
+                loop {
+                    drain_all_but_acks();
+                    yield_now().await;
+                }
+            }));
+            block_on(Timer::after_millis(SYSTEM_TICK_MILLIS.into()));
+            COMMAND_ACK_CHANNEL.try_receive().unwrap()
+        }
+
+        /// Every step lasts one system tick, so a run takes a few seconds
+        fn short_profile() -> Profile {
+            ProfileBuilder::new()
+                .name("Short")
+                .add_step(StepName::Preheat, 150.0, 1, 2.0, false, false)
+                .add_step(StepName::Soak, 180.0, 1, 1.0, false, false)
+                .add_step(StepName::Ramp, 220.0, 1, 2.0, false, false)
+                .add_step(StepName::ReflowRamp, 245.0, 1, 2.0, false, false)
+                .add_step(StepName::ReflowCool, 220.0, 1, 3.0, true, true)
+                .add_step(StepName::Cooling, 50.0, 1, 3.0, true, true)
+                .build()
+                .unwrap()
+        }
+
+        fn idle_controller() -> ReflowController {
+            let mut controller = ReflowController::new();
+            controller.profile = short_profile();
+            controller.profile_loaded = true;
+            controller.enter_idle_state();
+            controller
+        }
+
+        /// An idle controller with the door closed, started on the short profile
+        fn running_controller() -> ReflowController {
+            let mut controller = idle_controller();
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            send(&mut controller, Event::StartCommand, 25.0);
+            assert_eq!(controller.status(), Status::Running);
+            controller
+        }
+
+        /// Read each step's own temperature until the run reaches step `index`
+        fn run_until_step(controller: &mut ReflowController, index: usize) {
+            for _ in 0..100 {
+                if controller.status() != Status::Running
+                    || controller.current_step_index() >= index
+                {
+                    return;
+                }
+                let temperature =
+                    controller.profile.steps[controller.current_step_index()].set_temperature;
+                tick(controller, temperature);
+            }
+        }
+
+        /// The state sent by the last tick
+        fn published_state() -> ReflowControllerState {
+            CURRENT_STATE.try_get().unwrap()
+        }
+
+        #[test]
+        fn run_progresses_from_idle_through_running_to_finished() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            tick(&mut controller, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+
+            // Refused while the door is open
+            send(&mut controller, Event::StartCommand, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            send(&mut controller, Event::StartCommand, 25.0);
+            assert_eq!(controller.status(), Status::Running);
+            assert_eq!(controller.current_step_index(), 0);
+
+            // Read each step's own temperature until the run completes
+            let mut steps_seen = 0;
+            for _ in 0..100 {
+                if controller.status() != Status::Running {
+                    break;
+                }
+                steps_seen = steps_seen.max(controller.current_step_index() + 1);
+                let temperature =
+                    controller.profile.steps[controller.current_step_index()].set_temperature;
+                tick(&mut controller, temperature);
+            }
+
+            assert_eq!(steps_seen, PROFILE_STEPS);
+            assert_eq!(controller.status(), Status::Finished);
+            assert_eq!(controller.heater_power(), 0);
+        }
+
+        #[test]
+        fn frozen_sensor_during_a_run_is_a_fault() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            send(&mut controller, Event::StartCommand, 25.0);
+            tick(&mut controller, 25.0);
+            assert_eq!(controller.status(), Status::Running);
+            assert!(controller.heater_power() > 0);
+
+            // The sensor stops publishing
+            block_on(Timer::after_millis(STALE_READING_MILLIS + 100));
+            for _ in 1..STALE_TICKS_BEFORE_FAULT {
+                tick_without_reading(&mut controller);
+                assert!(controller.temperature_stale());
+                assert_eq!(controller.status(), Status::Running);
+                assert_eq!(controller.heater_power(), 0);
+            }
+            tick_without_reading(&mut controller);
+            assert_eq!(controller.status(), Status::Error);
+            assert_eq!(controller.heater_power(), 0);
+            assert_eq!(controller.error_message.as_str(), SENSOR_TIMEOUT_MESSAGE);
+        }
+
+        #[test]
+        fn clearing_an_error_returns_to_the_idle_target() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            send(&mut controller, Event::SetIdleTarget(40.0), 25.0);
+            send(&mut controller, Event::ThermocoupleFault(true), 25.0);
+            assert_eq!(controller.status(), Status::Error);
+            assert_eq!(controller.target_temperature(), 0.0);
+
+            send(&mut controller, Event::ClearError, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+            assert_eq!(controller.target_temperature(), 40.0);
+            assert!(controller.error_message.is_empty());
+        }
+
+        #[test]
+        fn overtemperature_latches_until_acknowledged_cool() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            let hot = OVERTEMPERATURE_LIMIT + 10.0;
+            // Raised over an error that was already showing
+            send(&mut controller, Event::ThermocoupleFault(true), 25.0);
+            tick(&mut controller, hot);
+            assert_eq!(controller.status(), Status::Error);
+            assert!(controller.fault_latched);
+            assert_eq!(controller.error_message.as_str(), OVERTEMPERATURE_MESSAGE);
+
+            // Neither CLEAR_ERROR nor an early ACK_FAULT lets go of it
+            send(&mut controller, Event::ClearError, hot);
+            send(&mut controller, Event::AcknowledgeFault, hot);
+            assert_eq!(controller.status(), Status::Error);
+            assert!(controller.fault_latched);
+
+            tick(&mut controller, 25.0);
+            assert_eq!(controller.status(), Status::Error);
+            send(&mut controller, Event::AcknowledgeFault, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+            assert!(!controller.fault_latched);
+        }
+
+        #[test]
+        fn clearing_an_error_while_hot_is_acked_and_deferred() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            send(&mut controller, Event::ThermocoupleFault(true), 100.0);
+
+            let ack = send_for_ack(&mut controller, Event::ClearError, 100.0);
+            assert!(ack.accepted);
+            assert_eq!(controller.status(), Status::Error);
+            assert_eq!(controller.notice.as_str(), COOLING_BEFORE_RESET_NOTICE);
+
+            tick(&mut controller, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+            assert!(controller.notice.is_empty());
+        }
+
+        #[test]
+        fn full_reset_keeps_settings_but_not_an_sd_profile() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            let _ = controller.profile_filename.push_str("leaded.txt");
+            send(&mut controller, Event::SetIdleTarget(40.0), 25.0);
+            send(&mut controller, Event::SetOvenType(OvenType::Ssr), 25.0);
+            send(&mut controller, Event::FullReset, 25.0);
+
+            assert_eq!(controller.status(), Status::Idle);
+            assert_eq!(controller.target_temperature(), 40.0);
+            assert_eq!(controller.oven_type, OvenType::Ssr);
+            assert!(!controller.profile_loaded);
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            send(&mut controller, Event::StartCommand, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+
+            // Running on the default profile, which stays selected
+            let mut controller = idle_controller();
+            controller.use_default_profile();
+            send(&mut controller, Event::FullReset, 25.0);
+            assert!(controller.profile_loaded);
+            assert_eq!(controller.profile.name.as_str(), "Default Profile");
+        }
+
+        #[test]
+        fn non_finite_idle_target_is_rejected() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            send(&mut controller, Event::SetIdleTarget(f32::NAN), 25.0);
+            assert_eq!(controller.target_temperature(), DEFAULT_IDLE_TARGET);
+            send(&mut controller, Event::SetIdleTarget(f32::INFINITY), 25.0);
+            assert_eq!(controller.target_temperature(), DEFAULT_IDLE_TARGET);
+            send(&mut controller, Event::SetIdleTarget(40.0), 25.0);
+            assert_eq!(controller.target_temperature(), 40.0);
+        }
+
+        #[test]
+        fn tick_takes_the_latest_reading_and_one_event() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            INPUT_EVENT_CHANNEL
+                .try_send(Event::SetIdleTarget(30.0))
+                .unwrap();
+            INPUT_EVENT_CHANNEL
+                .try_send(Event::SetIdleTarget(40.0))
+                .unwrap();
+
+            tick(&mut controller, 35.0);
+            assert_eq!(controller.current_temperature(), 35.0);
+            assert_eq!(controller.target_temperature(), 30.0);
+            assert_eq!(published_state().target_temperature, 30.0);
+
+            tick(&mut controller, 36.0);
+            assert_eq!(controller.target_temperature(), 40.0);
+            assert_eq!(published_state().current_temperature, 36.0);
+        }
+
+        #[test]
+        fn door_opened_during_cooling_finishes_the_run() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            run_until_step(&mut controller, PROFILE_STEPS - 1);
+            send(&mut controller, Event::DoorStateChanged(false), 50.0);
+            assert_eq!(controller.status(), Status::Finished);
+            assert_eq!(controller.finish_reason, Some(FinishReason::DoorOpened));
+            assert_eq!(controller.heater_power(), 0);
+            assert!(controller.fan());
+
+            // Before the cooling step it is a fault
+            let mut controller = running_controller();
+            send(&mut controller, Event::DoorStateChanged(false), 25.0);
+            assert_eq!(controller.status(), Status::Error);
+            assert_eq!(controller.heater_power(), 0);
+        }
+
+        #[test]
+        fn start_needs_a_loaded_valid_profile() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            controller.profile_loaded = false;
+            send(&mut controller, Event::StartCommand, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+
+            controller.profile_loaded = true;
+            controller.profile.steps[0].step_time = 0;
+            send(&mut controller, Event::StartCommand, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+
+            controller.profile = short_profile();
+            send(&mut controller, Event::StartCommand, 25.0);
+            assert_eq!(controller.status(), Status::Running);
+        }
+
+        #[test]
+        fn stop_while_hot_cools_down_before_idle() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            send(&mut controller, Event::StopCommand, 150.0);
+            assert_eq!(controller.status(), Status::CoolingDown);
+            assert_eq!(controller.heater_power(), 0);
+            assert!(controller.fan());
+
+            for _ in 1..DEFAULT_SAFE_TO_OPEN_SAMPLES {
+                tick(&mut controller, 40.0);
+                assert_eq!(controller.status(), Status::CoolingDown);
+            }
+            tick(&mut controller, 40.0);
+            assert_eq!(controller.status(), Status::Idle);
+
+            // A cool oven goes straight back to idle
+            let mut controller = running_controller();
+            send(&mut controller, Event::StopCommand, 40.0);
+            assert_eq!(controller.status(), Status::Idle);
+        }
+
+        #[test]
+        fn finishing_sends_the_run_summary() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            tick(&mut controller, 100.0);
+            tick(&mut controller, 140.0);
+            drain_all_but_acks();
+
+            block_on(controller.enter_finished_state(FinishReason::Completed));
+            let Ok(SystemEvent::Finished {
+                peak,
+                reason,
+                min,
+                mean,
+                ..
+            }) = SYSTEM_EVENT_CHANNEL.try_receive()
+            else {
+                panic!("no finished event");
+            };
+            assert_eq!(peak, 140.0);
+            assert_eq!(reason, FinishReason::Completed);
+            assert_eq!(min, 100.0);
+            assert_eq!(mean, 120.0);
+        }
+
+        #[test]
+        fn state_tells_which_step_condition_is_pending() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            // Preheat's single tick has passed but the oven is still cold
+            tick(&mut controller, 25.0);
+            let state = published_state();
+            assert!(!state.step_temp_reached);
+            assert!(state.step_time_met);
+
+            // At temperature, on a step with time still to run
+            controller.profile.steps[0].step_time = 600;
+            for _ in 0..STEP_CONFIRM_SAMPLES {
+                tick(&mut controller, 150.0);
+            }
+            let state = published_state();
+            assert!(state.step_temp_reached);
+            assert!(!state.step_time_met);
+            assert_eq!(controller.current_step_index(), 0);
+        }
+
+        #[test]
+        fn state_reports_the_step_number_and_count() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            let state = published_state();
+            assert_eq!(state.current_step_index, 0);
+            assert_eq!(state.total_steps, PROFILE_STEPS as u8);
+
+            run_until_step(&mut controller, 2);
+            let state = published_state();
+            assert_eq!(state.current_step_index, 2);
+            assert_eq!(state.current_step, "Ramp");
+        }
+
+        #[test]
+        fn implausible_first_reading_is_an_init_error() {
+            let _statics = lock_statics();
+            let mut controller = ReflowController::new();
+            LOADED_SETTINGS.reset();
+            CURRENT_TEMPERATURE.reset();
+            block_on(select(controller.init(), async {
+                // Stand in for the heater task's relay self-test, then the sensor
+                while HEATER_POWER.receive().await != HeaterCommand::SelfTest {}
+                RELAY_SELF_TEST_RESULT.signal(true);
+                CURRENT_TEMPERATURE
+                    .signal(TemperatureReading::new(MAX_PLAUSIBLE_TEMPERATURE + 100.0));
+                drain_outputs().await
+            }));
+            assert_eq!(controller.status(), Status::Error);
+            assert_eq!(
+                controller.error_message.as_str(),
+                "Implausible temperature reading"
+            );
+        }
+
+        #[test]
+        fn bake_holds_the_target_then_finishes() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            let bake = Event::StartBake {
+                target: 120.0,
+                duration_s: 1,
+            };
+            send(&mut controller, bake, 25.0);
+            assert_eq!(controller.status(), Status::Baking);
+            assert_eq!(controller.bake_remaining_s(), 1);
+
+            tick(&mut controller, 25.0);
+            assert_eq!(controller.target_temperature(), 120.0);
+            assert!(controller.heater_power() > 0);
+
+            block_on(Timer::after_secs(1));
+            tick(&mut controller, 100.0);
+            assert_eq!(controller.status(), Status::Finished);
+            assert_eq!(controller.finish_reason, Some(FinishReason::Completed));
+            assert_eq!(controller.heater_power(), 0);
+        }
+
+        #[test]
+        fn state_reports_updated_pid_gains() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            let gains = Event::UpdatePidParameters {
+                kp: 2.0,
+                ki: 0.25,
+                kd: 0.5,
+            };
+            assert!(send_for_ack(&mut controller, gains, 25.0).accepted);
+            let state = published_state();
+            assert_eq!((state.kp, state.ki, state.kd), (2.0, 0.25, 0.5));
+        }
+
+        #[test]
+        fn reset_while_hot_waits_for_the_oven_to_cool() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            send(&mut controller, Event::ThermocoupleFault(true), 100.0);
+            send(&mut controller, Event::ResetCommand, 100.0);
+            assert_eq!(controller.status(), Status::Error);
+            assert_eq!(controller.notice.as_str(), COOLING_BEFORE_RESET_NOTICE);
+            assert!(controller.fan());
+
+            tick(&mut controller, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+
+            // A cool oven resets straight away
+            send(&mut controller, Event::ThermocoupleFault(true), 25.0);
+            send(&mut controller, Event::ResetCommand, 25.0);
+            assert_eq!(controller.status(), Status::Idle);
+        }
+
+        #[test]
+        fn initial_dwell_is_reported_before_preheat() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            controller.profile.initial_dwell_s = 1;
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            send(&mut controller, Event::StartCommand, 25.0);
+            tick(&mut controller, 25.0);
+            assert_eq!(published_state().current_step, "Dwell");
+            assert_eq!(
+                controller.target_temperature(),
+                controller.profile.steps[0].set_temperature
+            );
+
+            block_on(Timer::after_secs(1));
+            tick(&mut controller, 25.0);
+            assert_eq!(published_state().current_step, "Preheat");
+        }
+
+        #[test]
+        fn heating_faster_than_the_max_rate_halves_the_power() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            let max_rate = controller.profile.steps[0].max_rate;
+            tick(&mut controller, 30.0);
+            assert!(controller.temperature_rate() > max_rate * (1.0 + RATE_WARNING_MARGIN));
+            assert!(published_state().rate_warning);
+            assert_eq!(controller.heater_power(), controller.pid_output_raw / 2);
+        }
+
+        #[test]
+        fn completed_runs_are_counted_until_reset() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            run_until_step(&mut controller, PROFILE_STEPS);
+            assert_eq!(controller.status(), Status::Finished);
+            assert_eq!(controller.cycles_completed, 1);
+            assert_eq!(published_state().cycles_completed, 1);
+
+            assert!(send_for_ack(&mut controller, Event::ResetCycleCount, 25.0).accepted);
+            assert_eq!(controller.cycles_completed, 0);
+        }
+
+        #[test]
+        fn safe_state_switches_the_heater_off_at_once() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            tick(&mut controller, 25.0);
+            assert!(controller.heater_power() > 0);
+            drain_all_but_acks();
+            HEATER_FORCE_OFF.reset();
+
+            block_on(controller.enter_safe_state());
+            assert_eq!(controller.heater_power(), 0);
+            assert!(!controller.fan());
+            assert!(HEATER_FORCE_OFF.signaled());
+            assert_eq!(
+                HEATER_POWER.try_receive().unwrap(),
+                controller.power_command(0)
+            );
+            assert_eq!(
+                HEATER_POWER.try_receive().unwrap(),
+                HeaterCommand::SetFan(false)
+            );
+            HEATER_FORCE_OFF.reset();
+        }
+
+        #[test]
+        fn loop_time_is_the_time_between_ticks() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            tick(&mut controller, 25.0);
+            assert_eq!(controller.loop_time_ms(), 0);
+
+            tick(&mut controller, 25.0);
+            let loop_time = controller.loop_time_ms();
+            assert!((SYSTEM_TICK_MILLIS..10 * SYSTEM_TICK_MILLIS).contains(&loop_time));
+            assert_eq!(published_state().loop_time_ms, loop_time);
+        }
+
+        #[test]
+        fn raw_pid_output_shows_what_the_overshoot_guard_cut() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            let guard = Event::SetOvershootGuard {
+                band: 50.0,
+                max_power: 10,
+            };
+            send(&mut controller, guard, 25.0);
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            send(&mut controller, Event::StartCommand, 25.0);
+            run_until_step(&mut controller, 3);
+            tick(&mut controller, 225.0);
+
+            let state = published_state();
+            assert!(state.heater_power <= 10);
+            assert!(state.pid_output_raw > state.heater_power);
+        }
+
+        #[test]
+        fn finish_reason_tells_how_the_run_ended() {
+            let _statics = lock_statics();
+            let mut controller = running_controller();
+            run_until_step(&mut controller, PROFILE_STEPS);
+            assert_eq!(controller.finish_reason, Some(FinishReason::Completed));
+            assert_eq!(
+                published_state().finish_reason,
+                Some(FinishReason::Completed)
+            );
+
+            let mut controller = running_controller();
+            run_until_step(&mut controller, PROFILE_STEPS - 1);
+            send(&mut controller, Event::StopCommand, 50.0);
+            assert_eq!(controller.finish_reason, Some(FinishReason::StoppedEarly));
+        }
+
+        #[test]
+        fn light_is_switched_in_idle_but_not_while_heating() {
+            let _statics = lock_statics();
+            let mut controller = idle_controller();
+            assert!(send_for_ack(&mut controller, Event::SetLight(true), 25.0).accepted);
+            assert!(published_state().light);
+
+            send(&mut controller, Event::DoorStateChanged(true), 25.0);
+            send(&mut controller, Event::StartCommand, 25.0);
+            let ack = send_for_ack(&mut controller, Event::SetLight(false), 25.0);
+            assert!(!ack.accepted);
+            assert_eq!(ack.reason, "busy");
+            assert!(controller.light);
+        }
+
+        #[test]
+        fn relay_test_is_only_accepted_in_idle() {
+            let _statics = lock_statics();
+            let test_relay = Event::TestRelay { relay: 1, on: true };
+            let mut controller = idle_controller();
+            assert!(send_for_ack(&mut controller, test_relay.clone(), 25.0).accepted);
+
+            let mut controller = running_controller();
+            let ack = send_for_ack(&mut controller, test_relay, 25.0);
+            assert!(!ack.accepted);
+            assert_eq!(ack.reason, "only available in idle");
+        }
+    }
+}
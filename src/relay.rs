@@ -1,5 +1,6 @@
 use core::fmt;
 use defmt::Format;
+use embassy_time::Timer;
 use embedded_hal_async::i2c::I2c;
 
 /// Relay Board driver error
@@ -7,6 +8,7 @@ use embedded_hal_async::i2c::I2c;
 pub enum Error<I2cE> {
     I2c(I2cE),
     InvalidRelayNumber,
+    UnexpectedResponse(u8),
 }
 
 impl<I2cE: fmt::Debug> fmt::Display for Error<I2cE> {
@@ -14,10 +16,17 @@ impl<I2cE: fmt::Debug> fmt::Display for Error<I2cE> {
         match self {
             Error::I2c(_) => write!(f, "I2C error"),
             Error::InvalidRelayNumber => write!(f, "Invalid relay number"),
+            Error::UnexpectedResponse(byte) => write!(f, "Unexpected response: {:#04x}", byte),
         }
     }
 }
 
+/// Factory default I2C address of the relay board
+pub const RELAY_BOARD_DEFAULT_ADDR: u8 = 0x08;
+
+/// Relays on the board, numbered from 1
+pub const RELAY_COUNT: u8 = 4;
+
 pub enum RelayCommand {
     RelayOneToggle = 0x01,
     RelayTwoToggle = 0x02,
@@ -47,6 +56,9 @@ where
 {
     addr: u8,
     i2c: I2C,
+    // Active-low board: the outputs are energized when the board reports them off
+    invert: bool,
+    i2c_errors: u32,
 }
 
 impl<I2C, E> RelayController<I2C, E>
@@ -54,25 +66,68 @@ where
     I2C: I2c<Error = E>,
 {
     pub fn new(i2c_device: I2C) -> Self {
+        Self::with_address(i2c_device, RELAY_BOARD_DEFAULT_ADDR)
+    }
+
+    /// Use a relay board that has been re-addressed from the factory default
+    pub fn with_address(i2c_device: I2C, addr: u8) -> Self {
+        Self::with_options(i2c_device, addr, false)
+    }
+
+    /// Full configuration. With `invert` the on/off, status and PWM semantics are
+    /// flipped for active-low boards, so relay_on still switches the load on.
+    pub fn with_options(i2c_device: I2C, addr: u8, invert: bool) -> Self {
         RelayController {
-            addr: 0x08,
+            addr,
             i2c: i2c_device,
+            invert,
+            i2c_errors: 0,
+        }
+    }
+
+    /// Failed I2C transfers since the controller was created, retried ones included
+    pub fn i2c_errors(&self) -> u32 {
+        self.i2c_errors
+    }
+
+    fn i2c_error(&mut self, err: E) -> Error<E> {
+        self.i2c_errors = self.i2c_errors.wrapping_add(1);
+        Error::I2c(err)
+    }
+
+    /// Board command that switches every load off (or on, if `!off`)
+    fn all_command(&self, off: bool) -> RelayCommand {
+        if off != self.invert {
+            RelayCommand::RelayAllOff
+        } else {
+            RelayCommand::RelayAllOn
+        }
+    }
+
+    /// Translate between the load's PWM duty and the value the board uses
+    fn pwm_value(&self, value: u8) -> u8 {
+        if self.invert {
+            255 - value
+        } else {
+            value
         }
     }
 
     pub async fn all_off(&mut self) -> Result<(), Error<E>> {
+        let command = self.all_command(true);
         self.i2c
-            .write(self.addr, &[RelayCommand::RelayAllOff as u8])
+            .write(self.addr, &[command as u8])
             .await
-            .map_err(Error::I2c)?;
+            .map_err(|e| self.i2c_error(e))?;
         Ok(())
     }
 
     pub async fn all_on(&mut self) -> Result<(), Error<E>> {
+        let command = self.all_command(false);
         self.i2c
-            .write(self.addr, &[RelayCommand::RelayAllOn as u8])
+            .write(self.addr, &[command as u8])
             .await
-            .map_err(Error::I2c)?;
+            .map_err(|e| self.i2c_error(e))?;
         Ok(())
     }
 
@@ -84,10 +139,10 @@ where
         self.i2c
             .write(
                 self.addr,
-                &[RelayCommand::RelayOnePWM as u8 + relay - 1, value],
+                &[RelayCommand::RelayOnePWM as u8 + relay - 1, self.pwm_value(value)],
             )
             .await
-            .map_err(Error::I2c)?;
+            .map_err(|e| self.i2c_error(e))?;
         Ok(())
     }
 
@@ -104,8 +159,37 @@ where
                 &mut buffer,
             )
             .await
-            .map_err(Error::I2c)?;
-        Ok(buffer[0])
+            .map_err(|e| self.i2c_error(e))?;
+        Ok(self.pwm_value(buffer[0]))
+    }
+
+    /// Walk a relay's PWM value from its current setting towards `target`,
+    /// changing it by at most `step` every `delay_ms`. Used to soft-start elements.
+    pub async fn ramp_pwm(
+        &mut self,
+        relay: u8,
+        target: u8,
+        step: u8,
+        delay_ms: u32,
+    ) -> Result<(), Error<E>> {
+        if relay < 1 || relay > 4 {
+            return Err(Error::InvalidRelayNumber);
+        }
+
+        let step = step.max(1);
+        let mut value = self.get_pwm(relay).await?;
+        while value != target {
+            value = if value < target {
+                value.saturating_add(step).min(target)
+            } else {
+                value.saturating_sub(step).max(target)
+            };
+            self.set_pwm(relay, value).await?;
+            if value != target {
+                Timer::after_millis(delay_ms.into()).await;
+            }
+        }
+        Ok(())
     }
 
     pub async fn relay_toggle(&mut self, relay: u8) -> Result<(), Error<E>> {
@@ -124,7 +208,7 @@ where
         self.i2c
             .write(self.addr, &[command as u8])
             .await
-            .map_err(Error::I2c)?;
+            .map_err(|e| self.i2c_error(e))?;
         Ok(())
     }
 
@@ -162,19 +246,227 @@ where
             2 => RelayCommand::RelayTwoStatus,
             3 => RelayCommand::RelayThreeStatus,
             4 => RelayCommand::RelayFourStatus,
-            _ => panic!("Invalid relay number"),
+            _ => return Err(Error::InvalidRelayNumber),
         };
 
         self.i2c
             .write_read(self.addr, &[command as u8], &mut buffer)
             .await
-            .map_err(Error::I2c)?;
-        let status = match buffer[0] {
-            0x00 => RelayStatus::Off,
-            0x0F => RelayStatus::On,
-            _ => panic!("Unknown relay status"),
+            .map_err(|e| self.i2c_error(e))?;
+        let status = match (buffer[0], self.invert) {
+            (0x00, false) | (0x0F, true) => RelayStatus::Off,
+            (0x0F, false) | (0x00, true) => RelayStatus::On,
+            (other, _) => return Err(Error::UnexpectedResponse(other)),
         };
         Ok(status)
     }
+
+    /// Read the status of all four relays, in relay order
+    pub async fn all_status(&mut self) -> Result<[RelayStatus; 4], Error<E>> {
+        let mut statuses = [RelayStatus::Off; 4];
+        for (index, status) in statuses.iter_mut().enumerate() {
+            *status = self.relay_status(index as u8 + 1).await?;
+        }
+        Ok(statuses)
+    }
+}
+
+
+/// Relay board on a fake I2C bus, for tests of the code that drives it. Keeps each
+/// relay's state and PWM value like the real board and records every write.
+#[cfg(test)]
+pub(crate) mod mock {
+    use embedded_hal_async::i2c::{self, ErrorKind, ErrorType, I2c, Operation};
+    use std::vec::Vec;
+
+    #[derive(Debug)]
+    pub struct MockI2cError;
+
+    impl i2c::Error for MockI2cError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    #[derive(Default)]
+    pub struct MockRelayBoard {
+        /// Raw output of each relay as the board sees it, relay 1 first
+        pub on: [bool; 4],
+        /// Raw PWM value of each relay
+        pub pwm: [u8; 4],
+        /// Every write in order, with the address it went to
+        pub writes: Vec<(u8, Vec<u8>)>,
+        /// Reported instead of the real status byte when set
+        pub status_reply: Option<u8>,
+        /// Fail every transfer, like a board that has dropped off the bus
+        pub fail: bool,
+        /// Last command byte written, which decides what a read returns
+        pub command: u8,
+    }
+
+    impl MockRelayBoard {
+        /// Every write's bytes, without the address
+        pub fn written(&self) -> Vec<Vec<u8>> {
+            self.writes.iter().map(|(_, bytes)| bytes.clone()).collect()
+        }
+
+        fn receive(&mut self, address: u8, bytes: &[u8]) {
+            self.writes.push((address, bytes.to_vec()));
+            self.command = bytes[0];
+            match (bytes[0], bytes.get(1)) {
+                (command @ 0x01..=0x04, None) => {
+                    let relay = (command - 0x01) as usize;
+                    self.on[relay] = !self.on[relay];
+                }
+                (0x0A, None) => self.on = [false; 4],
+                (0x0B, None) => self.on = [true; 4],
+                (command @ 0x10..=0x13, Some(&value)) => {
+                    self.pwm[(command - 0x10) as usize] = value;
+                }
+                _ => {}
+            }
+        }
+
+        fn reply(&self) -> u8 {
+            match self.command {
+                command @ 0x05..=0x08 => {
+                    let on = self.on[(command - 0x05) as usize];
+                    self.status_reply.unwrap_or(if on { 0x0F } else { 0x00 })
+                }
+                command @ 0x10..=0x13 => self.pwm[(command - 0x10) as usize],
+                _ => 0xFF,
+            }
+        }
+    }
+
+    impl ErrorType for MockRelayBoard {
+        type Error = MockI2cError;
+    }
+
+    impl I2c for MockRelayBoard {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            if self.fail {
+                return Err(MockI2cError);
+            }
+            for operation in operations {
+                match operation {
+                    Operation::Write(bytes) => self.receive(address, bytes),
+                    Operation::Read(buffer) => buffer.fill(self.reply()),
+                }
+            }
+            Ok(())
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::mock::MockRelayBoard;
+    use super::*;
+    use embassy_futures::block_on;
+    use std::vec::Vec;
+
+    #[test]
+    fn ramp_pwm_steps_to_the_target() {
+        let mut board = MockRelayBoard::default();
+        board.pwm[0] = 10;
+        let mut relays = RelayController::new(&mut board);
+        block_on(relays.ramp_pwm(1, 45, 10, 1)).unwrap();
+        // Read the current value, then step up by 10 and stop on the target
+        assert_eq!(
+            board.written(),
+            [&[0x10][..], &[0x10, 20], &[0x10, 30], &[0x10, 40], &[0x10, 45]]
+        );
+
+        board.writes.clear();
+        let mut relays = RelayController::new(&mut board);
+        // A zero step still moves, one at a time
+        block_on(relays.ramp_pwm(1, 42, 0, 1)).unwrap();
+        assert_eq!(board.written()[1..], [&[0x10, 44][..], &[0x10, 43], &[0x10, 42]]);
+        assert_eq!(board.pwm[0], 42);
+    }
+
+    #[test]
+    fn ramp_pwm_rejects_a_bad_relay() {
+        let mut board = MockRelayBoard::default();
+        let mut relays = RelayController::new(&mut board);
+        assert!(matches!(
+            block_on(relays.ramp_pwm(5, 100, 10, 1)),
+            Err(Error::InvalidRelayNumber)
+        ));
+        assert!(board.writes.is_empty());
+    }
+
+    #[test]
+    fn all_status_reads_every_relay_in_order() {
+        let mut board = MockRelayBoard {
+            on: [true, false, false, true],
+            ..Default::default()
+        };
+        let mut relays = RelayController::new(&mut board);
+        let statuses = block_on(relays.all_status()).unwrap();
+        assert_eq!(
+            statuses,
+            [RelayStatus::On, RelayStatus::Off, RelayStatus::Off, RelayStatus::On]
+        );
+        assert_eq!(board.written(), [[0x05], [0x06], [0x07], [0x08]]);
+    }
+
+    #[test]
+    fn unexpected_status_byte_is_an_error() {
+        let mut board = MockRelayBoard {
+            status_reply: Some(0x42),
+            ..Default::default()
+        };
+        let mut relays = RelayController::new(&mut board);
+        assert!(matches!(
+            block_on(relays.relay_status(1)),
+            Err(Error::UnexpectedResponse(0x42))
+        ));
+        assert!(matches!(
+            block_on(relays.all_status()),
+            Err(Error::UnexpectedResponse(0x42))
+        ));
+        // A relay in an unknown state isn't toggled
+        assert!(block_on(relays.relay_on(2)).is_err());
+        assert!(!board.written().contains(&std::vec![0x02]));
+    }
+
+    #[test]
+    fn with_address_talks_to_that_address() {
+        let mut board = MockRelayBoard::default();
+        block_on(RelayController::new(&mut board).all_off()).unwrap();
+        block_on(RelayController::with_address(&mut board, 0x11).all_off()).unwrap();
+        let addresses: Vec<u8> =
+            board.writes.iter().map(|(address, _)| *address).collect();
+        assert_eq!(addresses, [RELAY_BOARD_DEFAULT_ADDR, 0x11]);
+    }
+
+    #[test]
+    fn inverted_board_flips_commands_status_and_pwm() {
+        let mut board = MockRelayBoard {
+            on: [true, false, true, true],
+            pwm: [0, 0, 0, 55],
+            ..Default::default()
+        };
+        let mut relays = RelayController::with_options(&mut board, RELAY_BOARD_DEFAULT_ADDR, true);
+        // A raw 0x00 is an energized output on an active-low board
+        assert_eq!(block_on(relays.relay_status(1)).unwrap(), RelayStatus::Off);
+        assert_eq!(block_on(relays.relay_status(2)).unwrap(), RelayStatus::On);
+        assert_eq!(block_on(relays.get_pwm(4)).unwrap(), 200);
+
+        block_on(relays.relay_on(1)).unwrap();
+        block_on(relays.set_pwm(3, 0)).unwrap();
+        block_on(relays.all_off()).unwrap();
+        block_on(relays.all_on()).unwrap();
+
+        let writes = board.written();
+        assert_eq!(writes[4..], [&[0x01][..], &[0x12, 255], &[0x0B], &[0x0A]]);
+        assert_eq!(board.on, [false; 4]);
+        assert_eq!(board.pwm[2], 255);
+    }
+}
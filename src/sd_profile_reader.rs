@@ -1,11 +1,63 @@
+//! Reads reflow profiles from the SD card.
+//!
+//! Profile files are plain text, one entry per line:
+//!
+//! ```text
+//! # Full-line comment
+//! name: <profile name>
+//! units: F
+//! initial_dwell_s: <seconds>
+//! <step_name>,<temperature>,<target_time>,<step_time>,<max_rate>,<is_cooling>  # inline comment
+//! ```
+//!
+//! Blank lines are ignored, anything after `#` on a step line is a comment, and a
+//! single trailing comma after the last field is allowed. `step_name` is one of
+//! preheat, soak, ramp, reflow_ramp, reflow_cool or cooling; the numeric fields
+//! must parse and `is_cooling` is `true` or `false`. A profile has exactly six steps.
+//!
+//! The optional `units:` header is `C` (the default) or `F`. Fahrenheit profiles
+//! have their temperatures and rates converted to Celsius while parsing.
+//! `initial_dwell_s` holds the oven at the first step's temperature for that many
+//! seconds before the profile starts, letting the board reach equilibrium.
+
 use defmt::{error, info, warn};
 use heapless::{String, Vec};
 
-use crate::profile::{Profile, Step, StepName};
+use crate::profile::{
+    create_lead_free_profile, create_leaded_profile, create_low_temp_profile, Profile, Step,
+    StepName,
+};
+use crate::profile_source::ProfileSource;
+
+/// Maximum length in bytes of a profile name, the capacity of `Profile::name`
+const MAX_PROFILE_NAME_LEN: usize = 32;
+
+/// Copy a profile name into its fixed buffer, cutting it at the last character that
+/// fits instead of silently dropping it when it is too long.
+fn profile_name_from(name: &str) -> String<MAX_PROFILE_NAME_LEN> {
+    let mut end = name.len().min(MAX_PROFILE_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    if end < name.len() {
+        warn!(
+            "Profile name longer than {} bytes, truncated: {}",
+            MAX_PROFILE_NAME_LEN, name
+        );
+    }
+    let mut profile_name = String::new();
+    let _ = profile_name.push_str(&name[..end]);
+    profile_name
+}
+
+fn fahrenheit_to_celsius(fahrenheit: f32) -> f32 {
+    (fahrenheit - 32.0) * 5.0 / 9.0
+}
 
 #[derive(Debug, defmt::Format)]
 pub enum SdProfileError {
     SdCardError,
+    NoCard,
     FileNotFound,
     ParseError,
     InvalidFormat,
@@ -22,15 +74,36 @@ impl SdProfileReader {
         Self { initialized: false }
     }
 
+    /// Card detect switch - always present until the SD card hardware is wired up
+    fn card_detected(&self) -> bool {
+        true
+    }
+
+    fn ensure_initialized(&self) -> Result<(), SdProfileError> {
+        if self.initialized {
+            Ok(())
+        } else {
+            Err(SdProfileError::NoCard)
+        }
+    }
+}
+
+impl ProfileSource for SdProfileReader {
     /// Initialize SD card interface - placeholder for now
-    pub async fn init(&mut self) -> Result<(), SdProfileError> {
+    async fn init(&mut self) -> Result<(), SdProfileError> {
+        if !self.card_detected() {
+            self.initialized = false;
+            warn!("No SD card detected");
+            return Err(SdProfileError::NoCard);
+        }
         self.initialized = true;
         info!("SD card interface initialized (mock)");
         Ok(())
     }
 
     /// List available profile files on SD card
-    pub async fn list_profiles(&self) -> Result<Vec<String<64>, 16>, SdProfileError> {
+    async fn list_profiles(&self) -> Result<Vec<String<64>, 16>, SdProfileError> {
+        self.ensure_initialized()?;
         // For now, return a mock list - will be implemented when SD card support is added
         let mut profiles = Vec::new();
 
@@ -50,330 +123,273 @@ impl SdProfileReader {
     }
 
     /// Read and parse a profile from SD card
-    pub async fn read_profile(&self, filename: &str) -> Result<Profile, SdProfileError> {
+    async fn read_profile(&self, filename: &str) -> Result<Profile, SdProfileError> {
+        self.ensure_initialized()?;
         info!("Reading profile: {}", filename);
 
         // For now, return mock data based on filename - will be implemented when SD card support is added
         match filename {
-            "lead_free.txt" => Ok(self.create_lead_free_profile()),
-            "leaded.txt" => Ok(self.create_leaded_profile()),
-            "low_temp.txt" => Ok(self.create_low_temp_profile()),
+            "lead_free.txt" => Ok(create_lead_free_profile()),
+            "leaded.txt" => Ok(create_leaded_profile()),
+            "low_temp.txt" => Ok(create_low_temp_profile()),
             _ => {
                 error!("Profile file not found: {}", filename);
                 Err(SdProfileError::FileNotFound)
             }
         }
     }
+}
 
-    /// Parse profile content from text
-    fn parse_profile_content(&self, content: &str, name: &str) -> Result<Profile, SdProfileError> {
-        let mut steps = Vec::<Step, 6>::new();
-        let mut profile_name = String::<32>::new();
-        let _ = profile_name.push_str(name);
+/// Parse profile content from text. `name` is the filename, used to pick a default
+/// profile name when the content has no `name:` line.
+pub(crate) fn parse_profile_content(content: &str, name: &str) -> Result<Profile, SdProfileError> {
+    let mut steps = Vec::<Step, 6>::new();
+    let mut profile_name = profile_name_from(name);
+    let mut fahrenheit = false;
+    let mut initial_dwell_s = 0;
 
-        for line in content.lines() {
-            let line = line.trim();
+    // Files saved by some Windows editors start with a UTF-8 byte order mark.
+    // `lines()` and `trim()` already take care of CRLF line endings.
+    let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
 
-            // Skip empty lines and comments
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
+    for line in content.lines() {
+        let line = line.trim();
 
-            // Parse profile name
-            if line.starts_with("name:") {
-                if let Some(name_part) = line.strip_prefix("name:") {
-                    profile_name.clear();
-                    let _ = profile_name.push_str(name_part.trim());
-                }
-                continue;
-            }
+        // Skip empty lines and comments
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
 
-            // Parse step: step_name,temperature,target_time,step_time,max_rate,is_cooling
-            let parts: heapless::Vec<&str, 6> = line.split(',').collect();
-            if parts.len() != 6 {
-                warn!("Invalid line format: {}", line);
-                continue;
+        // Parse profile name
+        if line.starts_with("name:") {
+            if let Some(name_part) = line.strip_prefix("name:") {
+                profile_name = profile_name_from(name_part.trim());
             }
+            continue;
+        }
 
-            let step_name = match parts[0].trim() {
-                "preheat" | "Preheat" | "PREHEAT" => StepName::Preheat,
-                "soak" | "Soak" | "SOAK" => StepName::Soak,
-                "ramp" | "Ramp" | "RAMP" => StepName::Ramp,
-                "reflow_ramp" | "ReflowRamp" | "REFLOW_RAMP" => StepName::ReflowRamp,
-                "reflow_cool" | "ReflowCool" | "REFLOW_COOL" => StepName::ReflowCool,
-                "cooling" | "Cooling" | "COOLING" => StepName::Cooling,
-                _ => {
-                    warn!("Unknown step name: {}", parts[0]);
-                    continue;
+        // Parse temperature units
+        if let Some(units) = line.strip_prefix("units:") {
+            fahrenheit = match units.trim() {
+                "C" | "c" => false,
+                "F" | "f" => true,
+                other => {
+                    error!("Unknown units: {}", other);
+                    return Err(SdProfileError::ParseError);
                 }
             };
+            continue;
+        }
 
-            let temperature: f32 = parts[1].trim().parse().map_err(|_| {
-                error!("Invalid temperature: {}", parts[1]);
-                SdProfileError::ParseError
-            })?;
-
-            let target_time: u32 = parts[2].trim().parse().map_err(|_| {
-                error!("Invalid target_time: {}", parts[2]);
-                SdProfileError::ParseError
-            })?;
-
-            let step_time: u32 = parts[3].trim().parse().map_err(|_| {
-                error!("Invalid step_time: {}", parts[3]);
-                SdProfileError::ParseError
-            })?;
-
-            let max_rate: f32 = parts[4].trim().parse().map_err(|_| {
-                error!("Invalid max_rate: {}", parts[4]);
-                SdProfileError::ParseError
-            })?;
-
-            let is_cooling: bool = parts[5].trim().parse().map_err(|_| {
-                error!("Invalid is_cooling: {}", parts[5]);
+        // Parse the optional dwell before the first step
+        if let Some(dwell) = line.strip_prefix("initial_dwell_s:") {
+            initial_dwell_s = dwell.trim().parse().map_err(|_| {
+                error!("Invalid initial_dwell_s: {}", dwell);
                 SdProfileError::ParseError
             })?;
+            continue;
+        }
 
-            let step = Step {
-                step_name,
-                set_temperature: temperature,
-                target_time,
-                step_time,
-                max_rate,
-                is_cooling,
-                has_fan: false, // Default to false; can be extended to parse if needed
-            };
+        // Strip an inline comment and tolerate a trailing comma
+        let line = match line.split_once('#') {
+            Some((step, _comment)) => step.trim_end(),
+            None => line,
+        };
+        let line = line.strip_suffix(',').unwrap_or(line).trim_end();
+        if line.is_empty() {
+            continue;
+        }
 
-            if steps.push(step).is_err() {
-                error!("Too many steps in profile");
-                return Err(SdProfileError::InvalidFormat);
+        // Parse step: step_name,temperature,target_time,step_time,max_rate,is_cooling
+        let mut parts = heapless::Vec::<&str, 6>::new();
+        let mut too_many_fields = false;
+        for part in line.split(',') {
+            if parts.push(part).is_err() {
+                too_many_fields = true;
+                break;
             }
         }
+        if too_many_fields || parts.len() != 6 {
+            warn!("Invalid line format: {}", line);
+            continue;
+        }
 
-        if steps.len() != 6 {
-            error!("Profile must have exactly 6 steps, found {}", steps.len());
+        let step_name = match parts[0].trim() {
+            "preheat" | "Preheat" | "PREHEAT" => StepName::Preheat,
+            "soak" | "Soak" | "SOAK" => StepName::Soak,
+            "ramp" | "Ramp" | "RAMP" => StepName::Ramp,
+            "reflow_ramp" | "ReflowRamp" | "REFLOW_RAMP" => StepName::ReflowRamp,
+            "reflow_cool" | "ReflowCool" | "REFLOW_COOL" => StepName::ReflowCool,
+            "cooling" | "Cooling" | "COOLING" => StepName::Cooling,
+            _ => {
+                warn!("Unknown step name: {}", parts[0]);
+                continue;
+            }
+        };
+
+        let temperature: f32 = parts[1].trim().parse().map_err(|_| {
+            error!("Invalid temperature: {}", parts[1]);
+            SdProfileError::ParseError
+        })?;
+
+        let target_time: u32 = parts[2].trim().parse().map_err(|_| {
+            error!("Invalid target_time: {}", parts[2]);
+            SdProfileError::ParseError
+        })?;
+
+        let step_time: u32 = parts[3].trim().parse().map_err(|_| {
+            error!("Invalid step_time: {}", parts[3]);
+            SdProfileError::ParseError
+        })?;
+
+        let max_rate: f32 = parts[4].trim().parse().map_err(|_| {
+            error!("Invalid max_rate: {}", parts[4]);
+            SdProfileError::ParseError
+        })?;
+
+        let is_cooling: bool = parts[5].trim().parse().map_err(|_| {
+            error!("Invalid is_cooling: {}", parts[5]);
+            SdProfileError::ParseError
+        })?;
+
+        let step = Step {
+            step_name,
+            set_temperature: temperature,
+            target_time,
+            step_time,
+            max_rate,
+            is_cooling,
+            has_fan: false, // Default to false; can be extended to parse if needed
+            fan_speed: None,
+        };
+
+        if steps.push(step).is_err() {
+            error!("Too many steps in profile");
             return Err(SdProfileError::InvalidFormat);
         }
+    }
 
-        // Convert Vec to array
-        let steps_array: [Step; 6] = [
-            steps[0].clone(),
-            steps[1].clone(),
-            steps[2].clone(),
-            steps[3].clone(),
-            steps[4].clone(),
-            steps[5].clone(),
-        ];
-
-        // Use the parsed profile name or default based on filename
-        if profile_name.is_empty() {
-            let default_name = match name {
-                "lead_free.txt" => "Lead Free",
-                "leaded.txt" => "Leaded",
-                "low_temp.txt" => "Low Temperature",
-                _ => "Custom Profile",
-            };
-            let _ = profile_name.push_str(default_name);
+    if fahrenheit {
+        for step in steps.iter_mut() {
+            step.set_temperature = fahrenheit_to_celsius(step.set_temperature);
+            // A rate is a temperature difference, so only the scale changes
+            step.max_rate *= 5.0 / 9.0;
         }
+    }
 
-        Ok(Profile {
-            name: profile_name,
-            steps: steps_array,
-        })
+    if steps.len() != 6 {
+        error!("Profile must have exactly 6 steps, found {}", steps.len());
+        return Err(SdProfileError::InvalidFormat);
     }
 
-    // Mock profiles for testing
-    fn create_lead_free_profile(&self) -> Profile {
-        let mut name = heapless::String::new();
-        let _ = name.push_str("Lead Free");
-
-        Profile {
-            name,
-            steps: [
-                Step {
-                    step_name: StepName::Preheat,
-                    set_temperature: 150.0,
-                    target_time: 90,
-                    step_time: 90,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Soak,
-                    set_temperature: 180.0,
-                    target_time: 180,
-                    step_time: 90,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Ramp,
-                    set_temperature: 217.0,
-                    target_time: 210,
-                    step_time: 30,
-                    max_rate: 3.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::ReflowRamp,
-                    set_temperature: 245.0,
-                    target_time: 240,
-                    step_time: 30,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::ReflowCool,
-                    set_temperature: 217.0,
-                    target_time: 270,
-                    step_time: 30,
-                    max_rate: 2.0,
-                    is_cooling: true,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Cooling,
-                    set_temperature: 50.0,
-                    target_time: 330,
-                    step_time: 60,
-                    max_rate: 5.0,
-                    is_cooling: true,
-                    has_fan: true,
-                },
-            ],
-        }
+    // Convert Vec to array
+    let steps_array: [Step; 6] = [
+        steps[0].clone(),
+        steps[1].clone(),
+        steps[2].clone(),
+        steps[3].clone(),
+        steps[4].clone(),
+        steps[5].clone(),
+    ];
+
+    // Use the parsed profile name or default based on filename
+    if profile_name.is_empty() {
+        let default_name = match name {
+            "lead_free.txt" => "Lead Free",
+            "leaded.txt" => "Leaded",
+            "low_temp.txt" => "Low Temperature",
+            _ => "Custom Profile",
+        };
+        let _ = profile_name.push_str(default_name);
+    }
+
+    Ok(Profile {
+        name: profile_name,
+        steps: steps_array,
+        initial_dwell_s,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEPS: &str = "\
+preheat,150,90,90,2.0,false
+soak,180,180,90,2.0,false
+ramp,217,210,30,3.0,false
+reflow_ramp,245,240,30,2.0,false
+reflow_cool,217,270,30,2.0,true
+cooling,50,330,60,5.0,true
+";
+
+    fn parse(content: &str) -> Profile {
+        parse_profile_content(content, "test.txt").unwrap()
+    }
+
+    fn temperatures(profile: &Profile) -> [f32; 6] {
+        profile.steps.clone().map(|step| step.set_temperature)
     }
 
-    fn create_leaded_profile(&self) -> Profile {
-        let mut name = heapless::String::new();
-        let _ = name.push_str("Leaded");
-
-        Profile {
-            name,
-            steps: [
-                Step {
-                    step_name: StepName::Preheat,
-                    set_temperature: 100.0,
-                    target_time: 180,
-                    step_time: 180,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Soak,
-                    set_temperature: 150.0,
-                    target_time: 270,
-                    step_time: 90,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Ramp,
-                    set_temperature: 183.0,
-                    target_time: 300,
-                    step_time: 30,
-                    max_rate: 3.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::ReflowRamp,
-                    set_temperature: 215.0,
-                    target_time: 330,
-                    step_time: 30,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::ReflowCool,
-                    set_temperature: 183.0,
-                    target_time: 360,
-                    step_time: 30,
-                    max_rate: 2.0,
-                    is_cooling: true,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Cooling,
-                    set_temperature: 50.0,
-                    target_time: 420,
-                    step_time: 60,
-                    max_rate: 5.0,
-                    is_cooling: true,
-                    has_fan: true,
-                },
-            ],
+    #[test]
+    fn line_endings_and_bom_parse_the_same() {
+        let lf = parse(&std::format!("name: Test\n{}", STEPS));
+        let crlf = parse(&std::format!("name: Test\n{}", STEPS).replace('\n', "\r\n"));
+        let bom = parse(&std::format!("\u{FEFF}name: Test\n{}", STEPS));
+        for profile in [&lf, &crlf, &bom] {
+            assert_eq!(profile.name.as_str(), "Test");
+            assert_eq!(temperatures(profile), [150.0, 180.0, 217.0, 245.0, 217.0, 50.0]);
         }
     }
 
-    fn create_low_temp_profile(&self) -> Profile {
-        let mut name = heapless::String::new();
-        let _ = name.push_str("Low Temperature");
-
-        Profile {
-            name,
-            steps: [
-                Step {
-                    step_name: StepName::Preheat,
-                    set_temperature: 80.0,
-                    target_time: 45,
-                    step_time: 45,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Soak,
-                    set_temperature: 120.0,
-                    target_time: 105,
-                    step_time: 60,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Ramp,
-                    set_temperature: 150.0,
-                    target_time: 135,
-                    step_time: 30,
-                    max_rate: 3.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::ReflowRamp,
-                    set_temperature: 180.0,
-                    target_time: 165,
-                    step_time: 30,
-                    max_rate: 2.0,
-                    is_cooling: false,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::ReflowCool,
-                    set_temperature: 150.0,
-                    target_time: 195,
-                    step_time: 30,
-                    max_rate: 2.0,
-                    is_cooling: true,
-                    has_fan: false,
-                },
-                Step {
-                    step_name: StepName::Cooling,
-                    set_temperature: 50.0,
-                    target_time: 255,
-                    step_time: 60,
-                    max_rate: 5.0,
-                    is_cooling: true,
-                    has_fan: true,
-                },
-            ],
+    #[test]
+    fn inline_comment_and_trailing_comma_are_ignored() {
+        let content = STEPS
+            .replace("preheat,150,90,90,2.0,false", "preheat,150,90,90,2.0,false  # warm up")
+            .replace("cooling,50,330,60,5.0,true", "cooling,50,330,60,5.0,true,");
+        let profile = parse(&content);
+        assert_eq!(profile.steps[0].set_temperature, 150.0);
+        assert!(!profile.steps[0].is_cooling);
+        assert_eq!(profile.steps[5].step_time, 60);
+        assert!(profile.steps[5].is_cooling);
+    }
+
+    #[test]
+    fn fahrenheit_profile_matches_celsius() {
+        let celsius = parse(STEPS);
+        let fahrenheit = parse(concat!(
+            "units: F\n",
+            "preheat,302,90,90,3.6,false\n",
+            "soak,356,180,90,3.6,false\n",
+            "ramp,422.6,210,30,5.4,false\n",
+            "reflow_ramp,473,240,30,3.6,false\n",
+            "reflow_cool,422.6,270,30,3.6,true\n",
+            "cooling,122,330,60,9.0,true\n",
+        ));
+        for (c, f) in celsius.steps.iter().zip(fahrenheit.steps.iter()) {
+            assert!((c.set_temperature - f.set_temperature).abs() < 0.01);
+            assert!((c.max_rate - f.max_rate).abs() < 0.01);
         }
     }
+
+    #[test]
+    fn long_name_is_truncated() {
+        let long_name = "A".repeat(40);
+        let profile = parse(&std::format!("name: {}\n{}", long_name, STEPS));
+        assert_eq!(profile.name.as_str(), &long_name[..MAX_PROFILE_NAME_LEN]);
+
+        // A multi-byte character isn't split
+        let name = "é".repeat(20);
+        let profile = parse(&std::format!("name: {}\n{}", name, STEPS));
+        assert_eq!(profile.name.chars().count(), MAX_PROFILE_NAME_LEN / 2);
+    }
+
+    #[test]
+    fn wrong_step_count_is_rejected() {
+        let five_steps = STEPS.lines().take(5).collect::<std::vec::Vec<_>>().join("\n");
+        assert!(matches!(
+            parse_profile_content(&five_steps, "test.txt"),
+            Err(SdProfileError::InvalidFormat)
+        ));
+    }
 }
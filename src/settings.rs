@@ -0,0 +1,274 @@
+#[cfg(feature = "rp2040")]
+use defmt::{error, info, warn};
+#[cfg(feature = "rp2040")]
+use embassy_rp::flash::{Blocking, Flash, ERASE_SIZE};
+#[cfg(feature = "rp2040")]
+use embassy_rp::peripherals::FLASH;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use heapless::String;
+use serde::{Deserialize, Serialize};
+
+use crate::reflow_controller::{
+    COOLDOWN_TEMPERATURE, DEFAULT_CONTROL_PERIOD_MILLIS, DEFAULT_KD, DEFAULT_KI, DEFAULT_KP,
+    DEFAULT_OVERSHOOT_BAND, DEFAULT_OVERSHOOT_MAX_POWER, DEFAULT_SAFE_TO_OPEN_SAMPLES,
+};
+use crate::temperature_sensor::ControlSensor;
+use crate::OvenType;
+
+/// Total size of the on-board QSPI flash
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+/// Settings live in the last erase sector of flash, which memory.x keeps out
+/// of the program image.
+#[cfg(feature = "rp2040")]
+const SETTINGS_OFFSET: u32 = (FLASH_SIZE - ERASE_SIZE) as u32;
+
+/// Marker written in front of the payload so an erased sector isn't parsed
+const SETTINGS_MAGIC: [u8; 4] = *b"RFL1";
+
+/// Magic (4 bytes) followed by the payload length (u16, little-endian)
+const HEADER_LEN: usize = 6;
+
+#[cfg(any(feature = "rp2040", test))]
+const BUFFER_SIZE: usize = 512;
+
+#[cfg(feature = "rp2040")]
+pub type SettingsFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+/// Settings that survive a reset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    #[serde(default)]
+    pub profile_filename: String<64>,
+    #[serde(default)]
+    pub cycles_completed: u32,
+    #[serde(default)]
+    pub control_sensor: ControlSensor,
+    #[serde(default)]
+    pub oven_type: OvenType,
+    #[serde(default = "default_overshoot_band")]
+    pub overshoot_band: f32,
+    #[serde(default = "default_overshoot_max_power")]
+    pub overshoot_max_power: u8,
+    #[serde(default)]
+    pub finished_auto_reset_s: Option<u32>,
+    #[serde(default)]
+    pub finished_hold_target: Option<f32>,
+    #[serde(default = "default_safe_temperature")]
+    pub safe_temperature: f32,
+    #[serde(default = "default_safe_to_open_samples")]
+    pub safe_to_open_samples: u8,
+    #[serde(default = "default_control_period_ms")]
+    pub control_period_ms: u32,
+}
+
+fn default_overshoot_band() -> f32 {
+    DEFAULT_OVERSHOOT_BAND
+}
+
+fn default_overshoot_max_power() -> u8 {
+    DEFAULT_OVERSHOOT_MAX_POWER
+}
+
+fn default_safe_temperature() -> f32 {
+    COOLDOWN_TEMPERATURE
+}
+
+fn default_safe_to_open_samples() -> u8 {
+    DEFAULT_SAFE_TO_OPEN_SAMPLES
+}
+
+fn default_control_period_ms() -> u32 {
+    DEFAULT_CONTROL_PERIOD_MILLIS
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            kp: DEFAULT_KP,
+            ki: DEFAULT_KI,
+            kd: DEFAULT_KD,
+            profile_filename: String::new(),
+            cycles_completed: 0,
+            control_sensor: ControlSensor::Air,
+            oven_type: OvenType::RelayBank,
+            overshoot_band: DEFAULT_OVERSHOOT_BAND,
+            overshoot_max_power: DEFAULT_OVERSHOOT_MAX_POWER,
+            finished_auto_reset_s: None,
+            finished_hold_target: None,
+            safe_temperature: COOLDOWN_TEMPERATURE,
+            safe_to_open_samples: DEFAULT_SAFE_TO_OPEN_SAMPLES,
+            control_period_ms: DEFAULT_CONTROL_PERIOD_MILLIS,
+        }
+    }
+}
+
+#[derive(Debug, defmt::Format)]
+pub enum SettingsError {
+    Flash,
+    Encode,
+    Decode,
+    NotFound,
+}
+
+/// Settings to be written to flash, sent by the controller when they change
+pub static SAVE_SETTINGS_CHANNEL: Channel<CriticalSectionRawMutex, Settings, 1> = Channel::new();
+/// Settings read from flash at boot (defaults if nothing valid was stored)
+pub static LOADED_SETTINGS: Signal<CriticalSectionRawMutex, Settings> = Signal::new();
+
+/// Encode settings into `buf` as magic + length + JSON payload.
+/// Returns the number of bytes used.
+pub fn encode_settings(settings: &Settings, buf: &mut [u8]) -> Result<usize, SettingsError> {
+    if buf.len() < HEADER_LEN {
+        return Err(SettingsError::Encode);
+    }
+    let len = serde_json_core::to_slice(settings, &mut buf[HEADER_LEN..])
+        .map_err(|_| SettingsError::Encode)?;
+    buf[0..4].copy_from_slice(&SETTINGS_MAGIC);
+    buf[4..6].copy_from_slice(&(len as u16).to_le_bytes());
+    Ok(HEADER_LEN + len)
+}
+
+/// Decode settings previously written by `encode_settings`
+pub fn decode_settings(buf: &[u8]) -> Result<Settings, SettingsError> {
+    if buf.len() < HEADER_LEN || buf[0..4] != SETTINGS_MAGIC {
+        return Err(SettingsError::NotFound);
+    }
+    let len = u16::from_le_bytes([buf[4], buf[5]]) as usize;
+    let payload = buf
+        .get(HEADER_LEN..HEADER_LEN + len)
+        .ok_or(SettingsError::Decode)?;
+    let (settings, _) =
+        serde_json_core::from_slice::<Settings>(payload).map_err(|_| SettingsError::Decode)?;
+    Ok(settings)
+}
+
+/// Read the persisted settings from the reserved flash sector
+#[cfg(feature = "rp2040")]
+pub fn load_settings(flash: &mut SettingsFlash) -> Result<Settings, SettingsError> {
+    let mut buf = [0u8; BUFFER_SIZE];
+    flash
+        .blocking_read(SETTINGS_OFFSET, &mut buf)
+        .map_err(|_| SettingsError::Flash)?;
+    decode_settings(&buf)
+}
+
+/// Erase the reserved flash sector and write the given settings to it. Skipped when
+/// the sector already holds exactly these bytes, to spare the flash an erase cycle.
+/// Returns whether the flash was written.
+#[cfg(feature = "rp2040")]
+pub fn save_settings(
+    flash: &mut SettingsFlash,
+    settings: &Settings,
+) -> Result<bool, SettingsError> {
+    let mut buf = [0xFFu8; BUFFER_SIZE];
+    let len = encode_settings(settings, &mut buf)?;
+    let mut stored = [0u8; BUFFER_SIZE];
+    flash
+        .blocking_read(SETTINGS_OFFSET, &mut stored)
+        .map_err(|_| SettingsError::Flash)?;
+    if stored[..len] == buf[..len] {
+        return Ok(false);
+    }
+    flash
+        .blocking_erase(SETTINGS_OFFSET, SETTINGS_OFFSET + ERASE_SIZE as u32)
+        .map_err(|_| SettingsError::Flash)?;
+    flash
+        .blocking_write(SETTINGS_OFFSET, &buf)
+        .map_err(|_| SettingsError::Flash)?;
+    Ok(true)
+}
+
+#[cfg(feature = "rp2040")]
+#[embassy_executor::task]
+pub async fn settings_task(r: crate::FlashResources) {
+    let mut flash: SettingsFlash = Flash::new_blocking(r.flash);
+
+    let settings = match load_settings(&mut flash) {
+        Ok(settings) => {
+            info!("Loaded settings from flash");
+            settings
+        }
+        Err(SettingsError::NotFound) => {
+            info!("No stored settings, using defaults");
+            Settings::default()
+        }
+        Err(err) => {
+            warn!("Failed to load settings ({}), using defaults", err);
+            Settings::default()
+        }
+    };
+    LOADED_SETTINGS.signal(settings);
+
+    let receiver = SAVE_SETTINGS_CHANNEL.receiver();
+    loop {
+        let settings = receiver.receive().await;
+        match save_settings(&mut flash, &settings) {
+            Ok(true) => info!("Settings saved to flash"),
+            Ok(false) => info!("Settings unchanged, flash not rewritten"),
+            Err(err) => error!("Failed to save settings: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = Settings {
+            cycles_completed: 42,
+            oven_type: OvenType::Ssr,
+            finished_hold_target: Some(80.0),
+            ..Settings::default()
+        };
+        let mut buf = [0xFFu8; BUFFER_SIZE];
+        let len = encode_settings(&settings, &mut buf).unwrap();
+        assert_eq!(&buf[0..4], b"RFL1");
+        assert_eq!(u16::from_le_bytes([buf[4], buf[5]]) as usize, len - HEADER_LEN);
+        assert_eq!(decode_settings(&buf).unwrap(), settings);
+    }
+
+    #[test]
+    fn erased_sector_has_no_settings() {
+        let buf = [0xFFu8; BUFFER_SIZE];
+        assert!(matches!(decode_settings(&buf), Err(SettingsError::NotFound)));
+        assert!(matches!(decode_settings(b"RFL"), Err(SettingsError::NotFound)));
+    }
+
+    #[test]
+    fn length_past_the_buffer_is_rejected() {
+        let mut buf = [0xFFu8; BUFFER_SIZE];
+        encode_settings(&Settings::default(), &mut buf).unwrap();
+        buf[4..6].copy_from_slice(&(BUFFER_SIZE as u16).to_le_bytes());
+        assert!(matches!(decode_settings(&buf), Err(SettingsError::Decode)));
+    }
+
+    #[test]
+    fn truncated_payload_is_rejected() {
+        let mut buf = [0xFFu8; BUFFER_SIZE];
+        let len = encode_settings(&Settings::default(), &mut buf).unwrap();
+        let payload_len = (len - HEADER_LEN - 1) as u16;
+        buf[4..6].copy_from_slice(&payload_len.to_le_bytes());
+        assert!(matches!(decode_settings(&buf), Err(SettingsError::Decode)));
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let payload = br#"{"kp":2.0,"ki":0.1,"kd":0.5}"#;
+        let mut buf = [0xFFu8; BUFFER_SIZE];
+        buf[0..4].copy_from_slice(b"RFL1");
+        buf[4..6].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        buf[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+        let settings = decode_settings(&buf).unwrap();
+        assert_eq!(settings.kp, 2.0);
+        assert_eq!(settings.control_sensor, ControlSensor::Air);
+        assert_eq!(settings.control_period_ms, DEFAULT_CONTROL_PERIOD_MILLIS);
+    }
+}
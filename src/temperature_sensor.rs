@@ -1,88 +1,427 @@
 use defmt::*;
+use serde::{Deserialize, Serialize};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::signal::Signal;
-use embassy_time::Timer;
+use embassy_time::{with_timeout, Duration, Instant};
+use embedded_hal_async::i2c::I2c;
 
-#[cfg(not(feature = "mock_temperature_sensor"))]
+#[cfg(all(feature = "rp2040", not(feature = "mock_temperature_sensor")))]
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
-#[cfg(not(feature = "mock_temperature_sensor"))]
-use embassy_time::with_timeout;
 
-#[cfg(not(feature = "mock_temperature_sensor"))]
-use crate::mcp9600;
+use crate::mcp9600::{self, ThermocoupleFault};
+use crate::{BusDevice, Event, I2cErrorReport, I2C_ERROR_CHANNEL, INPUT_EVENT_CHANNEL};
+#[cfg(feature = "rp2040")]
 use crate::I2c0Bus;
 use crate::SYSTEM_TICK_MILLIS;
 
-pub static CURRENT_TEMPERATURE: Signal<CriticalSectionRawMutex, f32> = Signal::new();
+/// A temperature reading and the time it was taken
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TemperatureReading {
+    pub temp: f32,
+    pub at: Instant,
+}
+
+impl TemperatureReading {
+    /// A reading taken now
+    pub fn new(temp: f32) -> Self {
+        Self {
+            temp,
+            at: Instant::now(),
+        }
+    }
+
+    /// How long ago the reading was taken, relative to `now`
+    pub fn age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.at)
+    }
+
+    /// Whether the reading is older than `max_age` at `now`
+    pub fn is_stale(&self, now: Instant, max_age: Duration) -> bool {
+        self.age(now) > max_age
+    }
+}
+
+pub static CURRENT_TEMPERATURE: Signal<CriticalSectionRawMutex, TemperatureReading> =
+    Signal::new();
+
+/// Readings from the optional second thermocouple attached to the board
+pub static BOARD_TEMPERATURE: Signal<CriticalSectionRawMutex, TemperatureReading> =
+    Signal::new();
 
-#[cfg(not(feature = "mock_temperature_sensor"))]
+/// I2C address of the board sensor, the first one stays at `MCP9600_I2C_BASE_ADDR`
+pub const BOARD_SENSOR_ADDR: u8 = 0x66;
+
+/// Which thermocouple the controller regulates on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Format, Serialize, Deserialize)]
+pub enum ControlSensor {
+    /// The oven air, the only sensor on a single-sensor build
+    #[default]
+    Air,
+    /// The thermocouple on the board, needs the `board_sensor` feature
+    Board,
+}
+
+/// Why a temperature source has no reading to give
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum SensorError {
+    /// The sensor didn't acknowledge or the transfer failed
+    Bus,
+    /// The sensor didn't answer in time
+    Timeout,
+    /// The thermocouple is disconnected (`open`) or shorted
+    Thermocouple { open: bool },
+    /// Nothing to report this time, e.g. a simulated sensor told to freeze
+    Unavailable,
+}
+
+/// Anything the sensor task can take temperatures from: the MCP9600, the simulated
+/// oven or a recorded trace.
+#[allow(async_fn_in_trait)]
+pub trait TemperatureSource {
+    /// Take one reading in °C
+    async fn read(&mut self) -> Result<f32, SensorError>;
+
+    /// Failed I2C transfers so far, for sources on the bus
+    fn i2c_errors(&self) -> u32 {
+        0
+    }
+}
+
+impl<I2C, E> TemperatureSource for mcp9600::Mcp9600<I2C, E>
+where
+    I2C: I2c<Error = E>,
+{
+    async fn read(&mut self) -> Result<f32, SensorError> {
+        // Don't pass on readings from a disconnected or shorted probe
+        match self.check_thermocouple().await {
+            Err(mcp9600::Error::SensorFault(status)) => {
+                let open = status.thermocouple_fault() == Some(ThermocoupleFault::Open);
+                return Err(SensorError::Thermocouple { open });
+            }
+            Err(_) => error!("Error reading sensor status"),
+            Ok(()) => {}
+        }
+
+        let timeout = Duration::from_millis((SYSTEM_TICK_MILLIS * 2).into());
+        match with_timeout(timeout, self.read_hot_c()).await {
+            Ok(Ok(temp)) => Ok(temp),
+            Ok(Err(_)) => Err(SensorError::Bus),
+            Err(_) => Err(SensorError::Timeout),
+        }
+    }
+
+    fn i2c_errors(&self) -> u32 {
+        mcp9600::Mcp9600::i2c_errors(self)
+    }
+}
+
+/// Publish readings from `source` to `output` until the end of time. With
+/// `report_faults` a thermocouple fault is passed on to the controller, once per fault.
+/// I2C errors are reported as coming from `device`.
+pub async fn run_source<S: TemperatureSource>(
+    source: &mut S,
+    output: &Signal<CriticalSectionRawMutex, TemperatureReading>,
+    device: BusDevice,
+    report_faults: bool,
+) -> ! {
+    let mut fault_reported = false;
+    let mut reported_i2c_errors = 0;
+    loop {
+        match source.read().await {
+            Ok(temp) => {
+                fault_reported = false;
+                output.signal(TemperatureReading::new(temp));
+            }
+            Err(SensorError::Thermocouple { open }) => {
+                if !fault_reported {
+                    error!("Thermocouple fault (open circuit: {})", open);
+                    if report_faults {
+                        INPUT_EVENT_CHANNEL
+                            .sender()
+                            .send(Event::ThermocoupleFault(open))
+                            .await;
+                    }
+                    fault_reported = true;
+                }
+            }
+            Err(SensorError::Unavailable) => {}
+            Err(err) => error!("Error reading temperature: {}", err),
+        }
+
+        let i2c_errors = source.i2c_errors();
+        if i2c_errors != reported_i2c_errors {
+            let report = I2cErrorReport {
+                device,
+                count: i2c_errors,
+            };
+            if I2C_ERROR_CHANNEL.try_send(report).is_ok() {
+                reported_i2c_errors = i2c_errors;
+            }
+        }
+        crate::sleep_scaled(SYSTEM_TICK_MILLIS * 5).await;
+    }
+}
+
+/// Read the board thermocouple. Faults are only logged since the air sensor is
+/// still watched by the main sensor task.
+#[cfg(all(
+    feature = "rp2040",
+    feature = "board_sensor",
+    not(feature = "mock_temperature_sensor")
+))]
+#[embassy_executor::task]
+pub async fn run_board_temperature_sensor(i2c_bus: &'static I2c0Bus) -> ! {
+    let i2c_dev = I2cDevice::new(i2c_bus);
+    let mut sensor = mcp9600::Mcp9600::with_address(i2c_dev, BOARD_SENSOR_ADDR);
+
+    info!("Starting board temperature sensor task");
+    run_source(&mut sensor, &BOARD_TEMPERATURE, BusDevice::BoardSensor, false).await
+}
+
+#[cfg(all(feature = "rp2040", not(feature = "mock_temperature_sensor")))]
 #[embassy_executor::task]
 pub async fn run_temperature_sensor(i2c_bus: &'static I2c0Bus) -> ! {
     let i2c_dev = I2cDevice::new(i2c_bus);
     let mut sensor = mcp9600::Mcp9600::new(i2c_dev);
 
     info!("Starting temperature sensor task");
+    run_source(&mut sensor, &CURRENT_TEMPERATURE, BusDevice::AirSensor, true).await
+}
 
-    loop {
-        let temp_reading = with_timeout(
-            Duration::from_millis((SYSTEM_TICK_MILLIS * 2).into()),
-            sensor.read_hot_c(),
-        )
-        .await;
-        let temp = match temp_reading {
-            Ok(Ok(t)) => t,
-            Ok(Err(_)) => {
-                error!("Error reading temperature");
-                continue;
-            }
-            Err(_) => {
-                error!("Temperature read timed out");
-                continue;
-            }
-        };
-        CURRENT_TEMPERATURE.signal(temp);
-        Timer::after_millis((SYSTEM_TICK_MILLIS * 5).into()).await;
+/// Ambient temperature the simulated oven starts at and cools towards
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+const AMBIENT_TEMPERATURE: f32 = 25.0;
+/// Element heating rate at 100% power (degrees C/second)
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+const MAX_HEATING_RATE: f32 = 3.0;
+/// Factor affecting heat retention of the chamber air (0-1)
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+const THERMAL_MASS: f32 = 0.3;
+/// Chamber heat loss to ambient per degree difference
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+const HEAT_LOSS_COEFFICIENT: f32 = 0.1;
+/// Heat transfer from the element to the chamber air per degree difference
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+const ELEMENT_TO_CHAMBER_COEFFICIENT: f32 = 0.5;
+/// Fan multiplies the chamber heat loss by this factor
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+const FAN_COOLING_FACTOR: f32 = 3.0;
+
+/// Seed used for the simulated sensor noise when the `deterministic` feature is enabled
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+pub const NOISE_SEED: u32 = 0x1234_5678;
+
+/// Small linear congruential generator for simulated sensor noise
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+pub struct NoiseSource {
+    state: u32,
+}
+
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+impl NoiseSource {
+    pub fn new(seed: u32) -> Self {
+        Self { state: seed }
+    }
+
+    /// Fixed seed with the `deterministic` feature, otherwise seeded from the clock
+    pub fn from_config() -> Self {
+        #[cfg(feature = "deterministic")]
+        {
+            Self::new(NOISE_SEED)
+        }
+
+        #[cfg(not(feature = "deterministic"))]
+        {
+            Self::new(embassy_time::Instant::now().as_ticks() as u32)
+        }
+    }
+
+    /// Next noise sample in the range ±0.1°C
+    pub fn next_noise(&mut self) -> f32 {
+        // Numerical Recipes LCG constants
+        self.state = self.state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        let unit = (self.state >> 8) as f32 / (1u32 << 24) as f32;
+        unit * 0.2 - 0.1
     }
 }
 
-#[cfg(feature = "mock_temperature_sensor")]
-#[embassy_executor::task]
-pub async fn run_temperature_sensor(_i2c_bus: &'static I2c0Bus) -> ! {
-    use crate::HeaterCommand;
-    use crate::HEATER_POWER;
+/// Two-node oven model: the heater warms the element, the element warms the
+/// chamber air, and the air loses heat to ambient. The sensed (air) temperature
+/// therefore lags the element, like a real oven.
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+pub struct ThermalModel {
+    element_temp: f32,
+    chamber_temp: f32,
+    max_heating_rate: f32,
+    thermal_mass: f32,
+    heat_loss_coefficient: f32,
+}
 
-    info!("Starting mock temperature sensor with thermal simulation");
+#[cfg(any(feature = "mock_temperature_sensor", test))]
+impl ThermalModel {
+    pub fn new() -> Self {
+        Self {
+            element_temp: AMBIENT_TEMPERATURE,
+            chamber_temp: AMBIENT_TEMPERATURE,
+            max_heating_rate: MAX_HEATING_RATE,
+            thermal_mass: THERMAL_MASS,
+            heat_loss_coefficient: HEAT_LOSS_COEFFICIENT,
+        }
+    }
 
-    // Thermal simulation parameters - configurable for testing
-    let mut current_temp = 25.0; // Start at room temperature
-    let ambient_temp = 25.0;
-    let max_heating_rate = 3.0; // degrees C/second at 100% power (as requested)
-    let thermal_mass = 0.3; // Factor affecting heat retention (0-1)
-    let heat_loss_coefficient = 0.1; // Heat loss to ambient per degree difference
-    let update_interval_ms = SYSTEM_TICK_MILLIS * 5;
+    /// Change the oven characteristics while the simulation is running
+    pub fn set_params(
+        &mut self,
+        max_heating_rate: f32,
+        thermal_mass: f32,
+        heat_loss_coefficient: f32,
+    ) {
+        self.max_heating_rate = max_heating_rate;
+        self.thermal_mass = thermal_mass;
+        self.heat_loss_coefficient = heat_loss_coefficient;
+    }
 
-    let time_step = update_interval_ms as f32 / SYSTEM_TICK_MILLIS as f32 / 10.0;
+    /// Return both nodes to ambient temperature
+    pub fn reset(&mut self) {
+        self.element_temp = AMBIENT_TEMPERATURE;
+        self.chamber_temp = AMBIENT_TEMPERATURE;
+    }
 
-    info!(
-        "Thermal parameters: max_rate={}°C/s, mass={}, loss={}",
-        max_heating_rate, thermal_mass, heat_loss_coefficient
-    );
+    pub fn element_temperature(&self) -> f32 {
+        self.element_temp
+    }
 
-    let mut fan_enabled = false;
+    pub fn chamber_temperature(&self) -> f32 {
+        self.chamber_temp
+    }
 
-    let heater_receiver = HEATER_POWER.receiver();
-    let mut current_heater_power: u32 = 0;
-    loop {
-        // Check for heater power updates
-        let new_command = heater_receiver.receive().await;
-        match new_command {
-            HeaterCommand::SetPower(p) => current_heater_power = p as u32,
-            HeaterCommand::SetFan(on) => fan_enabled = on,
+    /// Advance the model by `time_step` and return the new chamber temperature.
+    /// `fan_speed` is 0-100.
+    pub fn step(&mut self, heater_power: u32, fan_speed: u8, time_step: f32) -> f32 {
+        let power_fraction = heater_power as f32 / 10.0;
+
+        // Heat input from heater into the element (degrees per second)
+        let heat_input = self.max_heating_rate * power_fraction;
+
+        // Heat flowing from the element into the chamber air
+        let transfer = ELEMENT_TO_CHAMBER_COEFFICIENT * (self.element_temp - self.chamber_temp);
+
+        // Heat loss from the chamber to ambient (Newton's law of cooling)
+        let mut heat_loss =
+            self.heat_loss_coefficient * (self.chamber_temp - AMBIENT_TEMPERATURE);
+
+        // Fan increases heat loss significantly, in proportion to its speed
+        let fan_fraction = fan_speed.min(100) as f32 / 100.0;
+        heat_loss *= 1.0 + (FAN_COOLING_FACTOR - 1.0) * fan_fraction;
+
+        self.element_temp += (heat_input - transfer) * time_step;
+        self.chamber_temp += (transfer - heat_loss) * self.thermal_mass * time_step;
+
+        // Ensure temperatures don't go below ambient
+        self.element_temp = self.element_temp.max(AMBIENT_TEMPERATURE);
+        self.chamber_temp = self.chamber_temp.max(AMBIENT_TEMPERATURE);
+
+        self.chamber_temp
+    }
+}
+
+/// Faults the mock sensor can be told to produce, to exercise the controller's
+/// fault handling end to end
+#[cfg(feature = "test_faults")]
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum InjectedFault {
+    /// Report readings normally again
+    None,
+    /// Stop publishing readings
+    Freeze,
+    /// Keep publishing the same value
+    Stuck(f32),
+    /// Publish a reading far beyond anything the oven can reach
+    OutOfRange,
+}
+
+#[cfg(feature = "test_faults")]
+pub static FAULT_INJECT: Signal<CriticalSectionRawMutex, InjectedFault> = Signal::new();
+
+#[cfg(feature = "test_faults")]
+const OUT_OF_RANGE_TEMPERATURE: f32 = 1000.0;
+
+/// Wraps a source and replaces its readings with the fault set through `FAULT_INJECT`.
+/// The wrapped source is still read, so a simulation keeps running underneath.
+#[cfg(feature = "test_faults")]
+pub struct FaultInjectingSource<S> {
+    inner: S,
+    fault: InjectedFault,
+}
+
+#[cfg(feature = "test_faults")]
+impl<S: TemperatureSource> FaultInjectingSource<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            fault: InjectedFault::None,
+        }
+    }
+}
+
+#[cfg(feature = "test_faults")]
+impl<S: TemperatureSource> TemperatureSource for FaultInjectingSource<S> {
+    async fn read(&mut self) -> Result<f32, SensorError> {
+        let reading = self.inner.read().await;
+        if let Some(new_fault) = FAULT_INJECT.try_take() {
+            warn!("Injecting sensor fault: {}", new_fault);
+            self.fault = new_fault;
+        }
+        match self.fault {
+            InjectedFault::None => reading,
+            InjectedFault::Freeze => Err(SensorError::Unavailable),
+            InjectedFault::Stuck(value) => Ok(value),
+            InjectedFault::OutOfRange => Ok(OUT_OF_RANGE_TEMPERATURE),
+        }
+    }
+
+    fn i2c_errors(&self) -> u32 {
+        self.inner.i2c_errors()
+    }
+}
+
+/// The simulated oven. Each read waits for the next heater command, applies it and
+/// advances the thermal model by one sensor interval.
+#[cfg(all(feature = "mock_temperature_sensor", not(feature = "trace_playback")))]
+pub struct SimulatedSource {
+    model: ThermalModel,
+    noise_source: NoiseSource,
+    heater_power: u32,
+    fan_speed: u8,
+    time_step: f32,
+}
+
+#[cfg(all(feature = "mock_temperature_sensor", not(feature = "trace_playback")))]
+impl SimulatedSource {
+    pub fn new() -> Self {
+        Self {
+            model: ThermalModel::new(),
+            noise_source: NoiseSource::from_config(),
+            heater_power: 0,
+            fan_speed: 0,
+            time_step: (SYSTEM_TICK_MILLIS * 5) as f32 / SYSTEM_TICK_MILLIS as f32 / 10.0,
+        }
+    }
+
+    fn handle_command(&mut self, command: crate::HeaterCommand) {
+        use crate::HeaterCommand;
+
+        match command {
+            HeaterCommand::SetPower(p) | HeaterCommand::SetPwmPower(p) => {
+                self.heater_power = p as u32
+            }
+            HeaterCommand::SetFan(on) => self.fan_speed = if on { 100 } else { 0 },
+            HeaterCommand::SetFanSpeed(speed) => self.fan_speed = speed,
             HeaterCommand::SimulationReset => {
                 info!("Resetting thermal simulation to initial state");
-                current_temp = 25.0; // Reset to room temperature
-                fan_enabled = false;
-                current_heater_power = 0;
+                self.model.reset();
+                self.fan_speed = 0;
+                self.heater_power = 0;
             }
             HeaterCommand::UpdatePidParameters {
                 kp: _,
@@ -91,40 +430,245 @@ pub async fn run_temperature_sensor(_i2c_bus: &'static I2c0Bus) -> ! {
             } => {
                 // Ignore for simulation
             }
-        };
+            HeaterCommand::SelfTest => {
+                // There is no relay board to check in the simulation
+                crate::heater::RELAY_SELF_TEST_RESULT.signal(true);
+            }
+            HeaterCommand::SetCoolingInterlock(_) | HeaterCommand::TestRelay { .. } => {
+                // Heater power is already zero while cooling in the simulation,
+                // and there are no relays to test
+            }
+            HeaterCommand::SetThermalParams {
+                max_rate,
+                thermal_mass,
+                loss,
+            } => {
+                info!(
+                    "Thermal parameters updated: max_rate={}°C/s, mass={}, loss={}",
+                    max_rate, thermal_mass, loss
+                );
+                self.model.set_params(max_rate, thermal_mass, loss);
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "mock_temperature_sensor", not(feature = "trace_playback")))]
+impl TemperatureSource for SimulatedSource {
+    async fn read(&mut self) -> Result<f32, SensorError> {
+        let command = crate::HEATER_POWER.receiver().receive().await;
+        self.handle_command(command);
+
+        let current_temp = self.model.step(self.heater_power, self.fan_speed, self.time_step);
+
+        // Add small amount of realistic noise (±0.1°C)
+        Ok(current_temp + self.noise_source.next_noise())
+    }
+}
+
+#[cfg(all(
+    feature = "rp2040",
+    feature = "mock_temperature_sensor",
+    not(feature = "trace_playback")
+))]
+#[embassy_executor::task]
+pub async fn run_temperature_sensor(_i2c_bus: &'static I2c0Bus) -> ! {
+    info!("Starting mock temperature sensor with thermal simulation");
+    info!(
+        "Thermal parameters: max_rate={}°C/s, mass={}, loss={}, coupling={}",
+        MAX_HEATING_RATE, THERMAL_MASS, HEAT_LOSS_COEFFICIENT, ELEMENT_TO_CHAMBER_COEFFICIENT
+    );
 
-        // Calculate thermal dynamics
-        let power_fraction = current_heater_power as f32 / 10.0;
+    #[cfg(feature = "test_faults")]
+    let mut source = FaultInjectingSource::new(SimulatedSource::new());
+    #[cfg(not(feature = "test_faults"))]
+    let mut source = SimulatedSource::new();
 
-        // Heat input from heater (degrees per second)
-        let heat_input = max_heating_rate * power_fraction;
+    run_source(&mut source, &CURRENT_TEMPERATURE, BusDevice::AirSensor, true).await
+}
+
+/// Recorded trace replayed by the `trace_playback` feature
+#[cfg(all(feature = "rp2040", feature = "trace_playback"))]
+static PLAYBACK_TRACE: &str = include_str!("../example_traces/recorded_reflow.csv");
+
+/// Replays a recorded temperature trace instead of simulating thermal dynamics.
+/// Heater commands are drained but have no effect on the reported temperature.
+#[cfg(feature = "trace_playback")]
+pub struct TraceSource {
+    trace: crate::temperature_trace::TemperatureTrace,
+    start: Instant,
+}
+
+#[cfg(feature = "trace_playback")]
+impl TraceSource {
+    pub fn new(trace: crate::temperature_trace::TemperatureTrace) -> Self {
+        Self {
+            trace,
+            start: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "trace_playback")]
+impl TemperatureSource for TraceSource {
+    async fn read(&mut self) -> Result<f32, SensorError> {
+        use crate::{HeaterCommand, HEATER_POWER};
+
+        while let Ok(command) = HEATER_POWER.receiver().try_receive() {
+            if command == HeaterCommand::SelfTest {
+                crate::heater::RELAY_SELF_TEST_RESULT.signal(true);
+            }
+        }
+
+        let elapsed_s = crate::scaled_elapsed(self.start).as_millis() as f32 / 1000.0;
+        Ok(self.trace.temperature_at(elapsed_s))
+    }
+}
+
+#[cfg(all(feature = "rp2040", feature = "trace_playback"))]
+#[embassy_executor::task]
+pub async fn run_temperature_sensor(_i2c_bus: &'static I2c0Bus) -> ! {
+    use crate::temperature_trace::TemperatureTrace;
+
+    let trace = match TemperatureTrace::parse(PLAYBACK_TRACE) {
+        Ok(trace) => trace,
+        Err(err) => defmt::panic!("Invalid playback trace: {}", err),
+    };
+
+    info!("Replaying recorded temperature trace ({}s)", trace.duration());
+
+    #[cfg(feature = "test_faults")]
+    let mut source = FaultInjectingSource::new(TraceSource::new(trace));
+    #[cfg(not(feature = "test_faults"))]
+    let mut source = TraceSource::new(trace);
 
-        // Heat loss to ambient (Newton's law of cooling)
-        let temp_diff = current_temp - ambient_temp;
-        let mut heat_loss = heat_loss_coefficient * temp_diff;
+    run_source(&mut source, &CURRENT_TEMPERATURE, BusDevice::AirSensor, true).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // Over the ones glob imported from defmt
+    use core::{assert, assert_eq};
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select, Either};
+
+    /// Chamber temperatures over `steps` half-second steps at full power
+    fn step_response(model: &mut ThermalModel, steps: usize) -> heapless::Vec<f32, 64> {
+        (0..steps).map(|_| model.step(100, 0, 0.5)).collect()
+    }
+
+    #[test]
+    fn chamber_lags_and_smooths_a_heater_step() {
+        let mut model = ThermalModel::new();
+        let chamber = step_response(&mut model, 40);
+
+        // The element takes the heat first, the air hasn't moved after one step
+        assert_eq!(chamber[0], AMBIENT_TEMPERATURE);
+        assert!(model.element_temperature() > model.chamber_temperature());
 
-        // Fan increases heat loss significantly when enabled
-        if fan_enabled {
-            heat_loss *= 3.0; // Fan triples cooling efficiency
+        // Then the air rises without overshooting, slowly at first
+        let rises: heapless::Vec<f32, 64> =
+            chamber.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert!(rises.iter().all(|rise| *rise >= 0.0));
+        assert!(rises[0] < rises[3]);
+    }
+
+    #[test]
+    fn thermal_params_change_the_trajectory() {
+        let mut default = ThermalModel::new();
+        let mut fast = ThermalModel::new();
+        fast.set_params(MAX_HEATING_RATE * 2.0, THERMAL_MASS, HEAT_LOSS_COEFFICIENT);
+        let mut leaky = ThermalModel::new();
+        leaky.set_params(MAX_HEATING_RATE, THERMAL_MASS, HEAT_LOSS_COEFFICIENT * 4.0);
+
+        let default = step_response(&mut default, 20)[19];
+        assert!(step_response(&mut fast, 20)[19] > default);
+        assert!(step_response(&mut leaky, 20)[19] < default);
+
+        fast.reset();
+        assert_eq!(fast.chamber_temperature(), AMBIENT_TEMPERATURE);
+        assert_eq!(fast.element_temperature(), AMBIENT_TEMPERATURE);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_noise() {
+        let mut first = NoiseSource::new(NOISE_SEED);
+        let mut second = NoiseSource::new(NOISE_SEED);
+        let mut other = NoiseSource::new(NOISE_SEED + 1);
+        let mut differs = false;
+        for _ in 0..100 {
+            let noise = first.next_noise();
+            assert_eq!(noise, second.next_noise());
+            assert!((-0.1..=0.1).contains(&noise));
+            differs |= noise != other.next_noise();
         }
+        assert!(differs);
+    }
 
-        // Net temperature change considering thermal mass
-        let net_heat_rate = (heat_input - heat_loss) * thermal_mass;
-        let temp_change = net_heat_rate * time_step;
+    /// Replays `readings`, then never answers again
+    struct ScriptedSource {
+        readings: &'static [Result<f32, SensorError>],
+        next: usize,
+        i2c_errors: u32,
+    }
 
-        // Update temperature
-        current_temp += temp_change;
+    impl TemperatureSource for ScriptedSource {
+        async fn read(&mut self) -> Result<f32, SensorError> {
+            let Some(reading) = self.readings.get(self.next) else {
+                return core::future::pending().await;
+            };
+            self.next += 1;
+            if *reading == Err(SensorError::Bus) {
+                self.i2c_errors += 1;
+            }
+            *reading
+        }
 
-        // Ensure temperature doesn't go below ambient
-        if current_temp < ambient_temp {
-            current_temp = ambient_temp;
+        fn i2c_errors(&self) -> u32 {
+            self.i2c_errors
         }
+    }
 
-        // Add small amount of realistic noise (±0.1°C)
-        let noise = (embassy_time::Instant::now().as_millis() % 200) as f32 / 1000.0 - 0.1;
-        let reported_temp = current_temp + noise;
+    #[test]
+    fn run_source_publishes_readings_and_reports_a_fault_once() {
+        let _statics = crate::lock_statics();
+        while INPUT_EVENT_CHANNEL.try_receive().is_ok() {}
+        while I2C_ERROR_CHANNEL.try_receive().is_ok() {}
+
+        let mut source = ScriptedSource {
+            readings: &[
+                Ok(20.0),
+                Err(SensorError::Thermocouple { open: true }),
+                Err(SensorError::Thermocouple { open: true }),
+                Err(SensorError::Bus),
+                Ok(30.0),
+            ],
+            next: 0,
+            i2c_errors: 0,
+        };
+        let output: Signal<CriticalSectionRawMutex, TemperatureReading> = Signal::new();
+        let published = async { (output.wait().await.temp, output.wait().await.temp) };
+        let readings = match block_on(select(
+            run_source(&mut source, &output, BusDevice::AirSensor, true),
+            published,
+        )) {
+            Either::First(never) => match never {},
+            Either::Second(readings) => readings,
+        };
 
-        CURRENT_TEMPERATURE.signal(reported_temp);
-        Timer::after_millis(update_interval_ms.into()).await;
+        assert_eq!(readings, (20.0, 30.0));
+        assert_eq!(
+            INPUT_EVENT_CHANNEL.try_receive(),
+            Ok(Event::ThermocoupleFault(true))
+        );
+        assert!(INPUT_EVENT_CHANNEL.try_receive().is_err());
+        assert_eq!(
+            I2C_ERROR_CHANNEL.try_receive(),
+            Ok(I2cErrorReport {
+                device: BusDevice::AirSensor,
+                count: 1
+            })
+        );
     }
 }
@@ -0,0 +1,168 @@
+use defmt::warn;
+use heapless::Vec;
+
+/// Maximum number of samples a trace can hold
+pub const MAX_TRACE_SAMPLES: usize = 512;
+
+#[derive(Debug, defmt::Format)]
+pub enum TraceError {
+    ParseError,
+    TooManySamples,
+    Empty,
+    NotIncreasing,
+}
+
+/// A recorded temperature trace of `(time_s, temp_c)` samples
+pub struct TemperatureTrace {
+    samples: Vec<(f32, f32), MAX_TRACE_SAMPLES>,
+}
+
+impl TemperatureTrace {
+    /// Parse a CSV trace with one `time_s,temp_c` sample per line.
+    /// Blank lines, `#` comments and a non-numeric header line are skipped.
+    /// Sample times must be strictly increasing.
+    pub fn parse(content: &str) -> Result<Self, TraceError> {
+        let mut samples = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split(',');
+            let (time, temp) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(time), Some(temp), None) => (time.trim(), temp.trim()),
+                _ => {
+                    warn!("Invalid trace line: {}", line);
+                    return Err(TraceError::ParseError);
+                }
+            };
+
+            let (time, temp) = match (time.parse::<f32>(), temp.parse::<f32>()) {
+                (Ok(time), Ok(temp)) => (time, temp),
+                // Allow a `time_s,temp_c` header before the first sample
+                _ if samples.is_empty() => continue,
+                _ => {
+                    warn!("Invalid trace sample: {}", line);
+                    return Err(TraceError::ParseError);
+                }
+            };
+
+            if let Some(&(last_time, _)) = samples.last() {
+                if time <= last_time {
+                    return Err(TraceError::NotIncreasing);
+                }
+            }
+
+            samples
+                .push((time, temp))
+                .map_err(|_| TraceError::TooManySamples)?;
+        }
+
+        if samples.is_empty() {
+            return Err(TraceError::Empty);
+        }
+
+        Ok(Self { samples })
+    }
+
+    /// Time of the last sample in seconds
+    pub fn duration(&self) -> f32 {
+        self.samples[self.samples.len() - 1].0
+    }
+
+    /// Temperature at `time_s`, linearly interpolated between samples.
+    /// Times before the first or after the last sample clamp to that sample.
+    pub fn temperature_at(&self, time_s: f32) -> f32 {
+        let first = self.samples[0];
+        if time_s <= first.0 {
+            return first.1;
+        }
+
+        for window in self.samples.windows(2) {
+            let (t0, v0) = window[0];
+            let (t1, v1) = window[1];
+            if time_s <= t1 {
+                let fraction = (time_s - t0) / (t1 - t0);
+                return v0 + (v1 - v0) * fraction;
+            }
+        }
+
+        self.samples[self.samples.len() - 1].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn parses_the_recorded_trace() {
+        let trace = TemperatureTrace::parse(include_str!("../example_traces/recorded_reflow.csv"))
+            .unwrap();
+        assert!(trace.duration() > 0.0);
+        assert_eq!(trace.temperature_at(0.0), 25.0);
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let trace = TemperatureTrace::parse("time_s,temp_c\n0,20\n10,120\n20,100\n").unwrap();
+        assert_eq!(trace.duration(), 20.0);
+        assert_eq!(trace.temperature_at(5.0), 70.0);
+        assert_eq!(trace.temperature_at(10.0), 120.0);
+        assert_eq!(trace.temperature_at(15.0), 110.0);
+    }
+
+    #[test]
+    fn playback_clamps_outside_the_trace() {
+        // The trace doesn't wrap: before the start and after the end it holds the
+        // first and last sample
+        let trace = TemperatureTrace::parse("0,20\n10,120\n").unwrap();
+        assert_eq!(trace.temperature_at(-5.0), 20.0);
+        assert_eq!(trace.temperature_at(10.5), 120.0);
+        assert_eq!(trace.temperature_at(1000.0), 120.0);
+    }
+
+    #[test]
+    fn sample_times_must_increase() {
+        assert!(matches!(
+            TemperatureTrace::parse("0,20\n10,30\n10,40\n"),
+            Err(TraceError::NotIncreasing)
+        ));
+        assert!(matches!(
+            TemperatureTrace::parse("0,20\n10,30\n5,40\n"),
+            Err(TraceError::NotIncreasing)
+        ));
+    }
+
+    #[test]
+    fn rejects_more_samples_than_fit() {
+        let mut content: heapless::String<8192> = heapless::String::new();
+        for time in 0..=MAX_TRACE_SAMPLES {
+            writeln!(content, "{},25", time).unwrap();
+        }
+        assert!(matches!(
+            TemperatureTrace::parse(&content),
+            Err(TraceError::TooManySamples)
+        ));
+    }
+
+    #[test]
+    fn rejects_empty_and_malformed_traces() {
+        assert!(matches!(
+            TemperatureTrace::parse("# comment\ntime_s,temp_c\n"),
+            Err(TraceError::Empty)
+        ));
+        assert!(matches!(
+            TemperatureTrace::parse("0,20\n5,hot\n"),
+            Err(TraceError::ParseError)
+        ));
+        assert!(matches!(
+            TemperatureTrace::parse("0,20,1\n"),
+            Err(TraceError::ParseError)
+        ));
+    }
+}
@@ -1,100 +1,136 @@
-use embassy_rp::bind_interrupts;
-use embassy_rp::peripherals::USB;
-use embassy_rp::rom_data::reset_to_usb_boot;
-
-use embassy_rp::usb::{Driver, InterruptHandler};
-use embassy_usb_logger::ReceiverHandler;
-use heapless::String;
-use serde::{Serialize, Deserialize};
-
-use crate::{Event, USBResources};
-use crate::{ReflowControllerState, CURRENT_STATE, INPUT_EVENT_CHANNEL, PROFILE_LIST_CHANNEL, ACTIVE_PROFILE_CHANNEL, SYSTEM_TICK_MILLIS};
-use crate::profile::Profile;
-use core::str;
-use defmt::unwrap;
-use embassy_executor::Spawner;
-use embassy_time::Timer;
+// Only usb_task spawns the message tasks, host builds keep them for the tests
+#![cfg_attr(not(feature = "rp2040"), allow(dead_code))]
+
+#[cfg(feature = "rp2040")]
+use {
+    crate::protocol::parse_command,
+    crate::{state_receiver, USBResources, INPUT_EVENT_CHANNEL, SYSTEM_TICK_MILLIS},
+    core::str,
+    defmt::unwrap,
+    embassy_executor::Spawner,
+    embassy_rp::bind_interrupts,
+    embassy_rp::peripherals::USB,
+    embassy_rp::rom_data::reset_to_usb_boot,
+    embassy_rp::usb::{Driver, InterruptHandler},
+    embassy_time::Timer,
+    embassy_usb_logger::ReceiverHandler,
+};
+use serde::Serialize;
+
+use crate::{CommandAck, ReflowControllerState, COMMAND_ACK_CHANNEL, EVENT_LOG_CHANNEL, PROFILE_LIST_CHANNEL, ACTIVE_PROFILE_CHANNEL, PROFILE_PREVIEW_CHANNEL, RUN_SCHEDULE_CHANNEL, SCHEMA_VERSION, SETPOINT_PREVIEW_CHANNEL, SYSTEM_EVENT_CHANNEL, SystemEvent};
+use crate::heater::{RelayDutyCounters, RELAY_DUTY_CHANNEL};
+use crate::reflow_controller::{EventLogEntry, EVENT_LOG_CAPACITY};
+use crate::profile::{Profile, PREVIEW_MAX_POINTS};
+use crate::protocol::DEFAULT_PREVIEW_INTERVAL_S;
+use embassy_time::{Duration, Instant, Ticker};
+#[cfg(feature = "rp2040")]
 use {defmt_rtt as _, panic_probe as _};
 
+/// Interval between heartbeat lines, sent whether or not the state has changed
+const HEARTBEAT_INTERVAL_MILLIS: u64 = 5000;
+
+/// JSON buffer for a state line. A state with every string full of characters that
+/// need escaping comes to about 1.7 KB.
+const STATE_MESSAGE_SIZE: usize = 2048;
+
 // —— USB interrupt binding ——
+#[cfg(feature = "rp2040")]
 bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => InterruptHandler<USB>;
 });
 
 use serde_json_core::ser::to_string;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 struct ProfileListResponse {
+    schema_version: u8,
     profiles: heapless::Vec<heapless::String<64>, 16>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 struct ActiveProfileResponse {
+    schema_version: u8,
+    checksum: u32,
+    total_duration_s: u32,
     active_profile: Profile,
 }
 
-pub fn to_json_heapless(msg: &ReflowControllerState) -> String<1024> {
-    // Writes JSON into your buffer; returns (&str, usize)
-    let out = to_string(msg).unwrap();
-    out
+/// A profile read for `GET_PROFILE`, which doesn't change the active one
+#[derive(Serialize)]
+struct ProfilePreviewResponse {
+    schema_version: u8,
+    checksum: u32,
+    total_duration_s: u32,
+    profile: Profile,
 }
 
+/// Everything written to the serial output. Each line is a JSON object tagged with
+/// `"type"` followed by the fields of the payload, so a host can dispatch on the tag
+/// without knowing which task produced the line. Every payload carries
+/// `schema_version` except acks and events, which are too small to change shape.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutboundMessage<'a> {
+    State(&'a ReflowControllerState),
+    Profiles(&'a ProfileListResponse),
+    ActiveProfile(&'a ActiveProfileResponse),
+    Profile(&'a ProfilePreviewResponse),
+    SetpointPreview(&'a SetpointPreviewResponse),
+    RelayDuty(&'a RelayDutyResponse),
+    Ack(&'a CommandAckResponse),
+    Nak(&'a CommandAckResponse),
+    Event(&'a SystemEvent),
+    Schedule(&'a RunScheduleResponse),
+    EventLog(&'a EventLogResponse),
+    Heartbeat(&'a HeartbeatResponse),
+}
+
+/// Serialize a message with its tag and write it as one line. `N` is the size of the
+/// JSON buffer, a message that doesn't fit is dropped with a warning.
+fn write_message<const N: usize>(message: &OutboundMessage) {
+    match to_string::<_, N>(message) {
+        Ok(json) => write_line(&json),
+        Err(_) => defmt::warn!("Outbound message larger than {} bytes, dropped", N),
+    }
+}
+
+/// Write one JSON line to the serial output. With the `crc_framing` feature each
+/// line is suffixed with `*XXXX`, the CRC16 of the payload (see `protocol::crc16`).
+fn write_line(json: &str) {
+    #[cfg(feature = "crc_framing")]
+    log::info!("{}*{:04X}", json, crate::protocol::crc16(json.as_bytes()));
+
+    #[cfg(not(feature = "crc_framing"))]
+    log::info!("{}", json);
+}
+
+#[cfg(feature = "rp2040")]
 struct Handler;
 
+#[cfg(feature = "rp2040")]
 impl ReceiverHandler for Handler {
     async fn handle_data(&self, data: &[u8]) {
         if let Ok(data) = str::from_utf8(data) {
             let data = data.trim();
-            match data {
-                "q" => {
-                    reset_to_usb_boot(0, 0);
-                }
-                "START" => {
-                    INPUT_EVENT_CHANNEL
-                        .sender()
-                        .try_send(Event::StartCommand)
-                        .unwrap();
-                }
-                // Add more commands here
-                "STOP" => {
-                    INPUT_EVENT_CHANNEL
-                        .sender()
-                        .try_send(Event::StopCommand)
-                        .unwrap();
-                }
-                "RESET" => {
-                    INPUT_EVENT_CHANNEL
-                        .sender()
-                        .try_send(Event::ResetCommand)
-                        .unwrap();
-                }
-                "LIST_PROFILES" => {
-                    INPUT_EVENT_CHANNEL
-                        .sender()
-                        .try_send(Event::ListProfilesRequest)
-                        .unwrap();
+            if data == "q" {
+                reset_to_usb_boot(0, 0);
+                return;
+            }
+            #[cfg(feature = "test_faults")]
+            if let Some(argument) = data.strip_prefix("INJECT_FAULT ") {
+                match crate::protocol::parse_fault(argument) {
+                    Some(fault) => crate::temperature_sensor::FAULT_INJECT.signal(fault),
+                    None => defmt::warn!("Unknown fault: {}", argument),
                 }
-                _ => {
-                    // Check for SET_PROFILE command with parameter
-                    if data.starts_with("SET_PROFILE ") {
-                        let profile_name = &data[12..]; // Skip "SET_PROFILE "
-                        if !profile_name.is_empty() {
-                            let mut profile_string = heapless::String::<64>::new();
-                            if profile_string.push_str(profile_name).is_ok() {
-                                INPUT_EVENT_CHANNEL
-                                    .sender()
-                                    .try_send(Event::LoadProfile(profile_string))
-                                    .unwrap();
-                            } else {
-                                defmt::warn!("Profile name too long: {}", profile_name);
-                            }
-                        } else {
-                            defmt::warn!("SET_PROFILE command requires a profile name");
-                        }
-                    } else {
-                        defmt::warn!("Unknown command: {}", data);
+                return;
+            }
+            match parse_command(data) {
+                Some(event) => {
+                    if INPUT_EVENT_CHANNEL.sender().try_send(event).is_err() {
+                        defmt::warn!("Event queue full, dropping command: {}", data);
                     }
                 }
+                None => defmt::warn!("Unknown command: {}", data),
             }
         }
     }
@@ -104,6 +140,7 @@ impl ReceiverHandler for Handler {
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 async fn logger_task(driver: Driver<'static, USB>) {
     embassy_usb_logger::run!(1024, log::LevelFilter::Info, driver, Handler);
@@ -114,9 +151,11 @@ async fn profile_list_task() {
     let receiver = PROFILE_LIST_CHANNEL.receiver();
     loop {
         let profiles = receiver.receive().await;
-        let response = ProfileListResponse { profiles };
-        let json: heapless::String<1024> = to_string(&response).unwrap();
-        log::info!("{}", json);
+        let response = ProfileListResponse {
+            schema_version: SCHEMA_VERSION,
+            profiles,
+        };
+        write_message::<1024>(&OutboundMessage::Profiles(&response));
     }
 }
 
@@ -125,25 +164,399 @@ async fn active_profile_task() {
     let receiver = ACTIVE_PROFILE_CHANNEL.receiver();
     loop {
         let profile = receiver.receive().await;
-        let response = ActiveProfileResponse { active_profile: profile };
-        let json: heapless::String<2048> = to_string(&response).unwrap();
-        log::info!("{}", json);
+        let response = ActiveProfileResponse {
+            schema_version: SCHEMA_VERSION,
+            checksum: profile.checksum(),
+            total_duration_s: profile.total_duration_s(),
+            active_profile: profile,
+        };
+        write_message::<2048>(&OutboundMessage::ActiveProfile(&response));
+    }
+}
+
+#[embassy_executor::task]
+async fn profile_preview_task() {
+    let receiver = PROFILE_PREVIEW_CHANNEL.receiver();
+    loop {
+        let profile = receiver.receive().await;
+        let response = ProfilePreviewResponse {
+            schema_version: SCHEMA_VERSION,
+            checksum: profile.checksum(),
+            total_duration_s: profile.total_duration_s(),
+            profile,
+        };
+        write_message::<2048>(&OutboundMessage::Profile(&response));
+    }
+}
+
+#[derive(Serialize)]
+struct SetpointPreviewResponse {
+    schema_version: u8,
+    samples: heapless::Vec<(u32, f32), PREVIEW_MAX_POINTS>,
+}
+
+/// Everything a host needs to plot a run as soon as it starts
+#[derive(Serialize)]
+struct RunScheduleResponse {
+    schema_version: u8,
+    checksum: u32,
+    total_duration_s: u32,
+    profile: Profile,
+    samples: heapless::Vec<(u32, f32), PREVIEW_MAX_POINTS>,
+}
+
+#[derive(Serialize)]
+struct EventLogResponse {
+    schema_version: u8,
+    entries: heapless::Vec<EventLogEntry, EVENT_LOG_CAPACITY>,
+}
+
+#[derive(Serialize)]
+struct HeartbeatResponse {
+    schema_version: u8,
+    uptime_s: u32,
+}
+
+#[derive(Serialize)]
+struct RelayDutyResponse {
+    schema_version: u8,
+    relay_duty: RelayDutyCounters,
+}
+
+#[embassy_executor::task]
+async fn setpoint_preview_task() {
+    let receiver = SETPOINT_PREVIEW_CHANNEL.receiver();
+    loop {
+        let samples = receiver.receive().await;
+        let response = SetpointPreviewResponse {
+            schema_version: SCHEMA_VERSION,
+            samples,
+        };
+        write_message::<2048>(&OutboundMessage::SetpointPreview(&response));
+    }
+}
+
+#[embassy_executor::task]
+async fn run_schedule_task() {
+    let receiver = RUN_SCHEDULE_CHANNEL.receiver();
+    loop {
+        let profile = receiver.receive().await;
+        let response = RunScheduleResponse {
+            schema_version: SCHEMA_VERSION,
+            checksum: profile.checksum(),
+            total_duration_s: profile.total_duration_s(),
+            samples: profile.preview(DEFAULT_PREVIEW_INTERVAL_S),
+            profile,
+        };
+        write_message::<4096>(&OutboundMessage::Schedule(&response));
+    }
+}
+
+#[embassy_executor::task]
+async fn event_log_task() {
+    let receiver = EVENT_LOG_CHANNEL.receiver();
+    loop {
+        let entries = receiver.receive().await;
+        let response = EventLogResponse {
+            schema_version: SCHEMA_VERSION,
+            entries,
+        };
+        write_message::<2048>(&OutboundMessage::EventLog(&response));
     }
 }
 
+#[embassy_executor::task]
+async fn relay_duty_task() {
+    let receiver = RELAY_DUTY_CHANNEL.receiver();
+    loop {
+        let relay_duty = receiver.receive().await;
+        let response = RelayDutyResponse {
+            schema_version: SCHEMA_VERSION,
+            relay_duty,
+        };
+        write_message::<256>(&OutboundMessage::RelayDuty(&response));
+    }
+}
+
+#[derive(Serialize)]
+struct CommandAckResponse {
+    command: &'static str,
+    reason: &'static str,
+}
+
+#[embassy_executor::task]
+async fn command_ack_task() {
+    let receiver = COMMAND_ACK_CHANNEL.receiver();
+    loop {
+        let ack: CommandAck = receiver.receive().await;
+        let response = CommandAckResponse {
+            command: ack.command,
+            reason: ack.reason,
+        };
+        let message = if ack.accepted {
+            OutboundMessage::Ack(&response)
+        } else {
+            OutboundMessage::Nak(&response)
+        };
+        write_message::<256>(&message);
+    }
+}
+
+#[embassy_executor::task]
+async fn system_event_task() {
+    let receiver = SYSTEM_EVENT_CHANNEL.receiver();
+    loop {
+        let event = receiver.receive().await;
+        write_message::<256>(&OutboundMessage::Event(&event));
+    }
+}
+
+/// Keepalive on a fixed cadence so a host can tell a hung controller or a stalled
+/// link from a state that simply isn't changing.
+#[embassy_executor::task]
+async fn heartbeat_task() {
+    let interval = Duration::from_millis(HEARTBEAT_INTERVAL_MILLIS);
+    run_heartbeat(interval, |response| {
+        write_message::<64>(&OutboundMessage::Heartbeat(response));
+    })
+    .await;
+}
+
+/// Hand a heartbeat to `send` every `interval`, starting one interval from now
+async fn run_heartbeat(interval: Duration, mut send: impl FnMut(&HeartbeatResponse)) -> ! {
+    let mut ticker = Ticker::every(interval);
+    loop {
+        ticker.next().await;
+        let response = HeartbeatResponse {
+            schema_version: SCHEMA_VERSION,
+            uptime_s: Instant::now().as_secs() as u32,
+        };
+        send(&response);
+    }
+}
+
+#[cfg(feature = "rp2040")]
 #[embassy_executor::task]
 pub async fn usb_task(spawner: Spawner, r: USBResources) {
     let driver = Driver::new(r.usb, Irqs);
     spawner.spawn(unwrap!(logger_task(driver)));
     spawner.spawn(unwrap!(profile_list_task()));
     spawner.spawn(unwrap!(active_profile_task()));
+    spawner.spawn(unwrap!(profile_preview_task()));
+    spawner.spawn(unwrap!(system_event_task()));
+    spawner.spawn(unwrap!(relay_duty_task()));
+    spawner.spawn(unwrap!(setpoint_preview_task()));
+    spawner.spawn(unwrap!(command_ack_task()));
+    spawner.spawn(unwrap!(run_schedule_task()));
+    spawner.spawn(unwrap!(event_log_task()));
+    spawner.spawn(unwrap!(heartbeat_task()));
 
-    let mut receiver = CURRENT_STATE.receiver().unwrap();
+    // The other message tasks keep running even if state can't be subscribed to
+    let Some(mut receiver) = state_receiver("usb_task") else {
+        return;
+    };
 
     loop {
         let new_state = receiver.get().await;
-        let json = to_json_heapless(&new_state);
-        log::info!("{}", json);
+        write_message::<STATE_MESSAGE_SIZE>(&OutboundMessage::State(&new_state));
         Timer::after_millis(SYSTEM_TICK_MILLIS.into()).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temperature_sensor::ControlSensor;
+    use crate::{FinishReason, Status};
+
+    fn full_string<const N: usize>(c: char) -> heapless::String<N> {
+        let mut string = heapless::String::new();
+        while string.push(c).is_ok() {}
+        string
+    }
+
+    /// Every string at capacity and escaped, every number at its longest
+    fn largest_state() -> ReflowControllerState {
+        ReflowControllerState {
+            schema_version: SCHEMA_VERSION,
+            status: Status::Initializing,
+            target_temperature: f32::MIN,
+            current_temperature: f32::MIN,
+            board_temperature: f32::MIN,
+            control_sensor: ControlSensor::Board,
+            temperature_rate: f32::MIN,
+            rate_warning: false,
+            door_closed: false,
+            fan: false,
+            fan_speed: u8::MAX,
+            light: false,
+            heater_power: u8::MAX,
+            pid_output_raw: u8::MAX,
+            timer: u32::MAX,
+            current_step: "Reflow Ramp",
+            current_step_index: u8::MAX,
+            total_steps: u8::MAX,
+            step_temp_reached: false,
+            step_time_met: false,
+            step_time_remaining: u32::MAX,
+            step_temp_delta: f32::MIN,
+            total_progress_pct: u8::MAX,
+            profile_duration_s: u32::MAX,
+            bake_remaining_s: u32::MAX,
+            loop_time_ms: u32::MAX,
+            uptime_s: u32::MAX,
+            i2c_errors: u32::MAX,
+            kp: f32::MIN,
+            ki: f32::MIN,
+            kd: f32::MIN,
+            cycles_completed: u32::MAX,
+            finish_reason: Some(FinishReason::StoppedEarly),
+            current_profile: full_string('"'),
+            active_profile_checksum: u32::MAX,
+            error_message: full_string('"'),
+            fault_latched: false,
+            safe_to_open: false,
+            notice: full_string('"'),
+        }
+    }
+
+    #[test]
+    fn largest_state_fits_the_state_buffer() {
+        let state = largest_state();
+        let json = to_string::<_, STATE_MESSAGE_SIZE>(&OutboundMessage::State(&state)).unwrap();
+        assert!(json.contains(r#""error_message":"\"\""#));
+    }
+
+    /// The serialized line for `message`, as the tasks write it
+    fn line(message: &OutboundMessage) -> heapless::String<STATE_MESSAGE_SIZE> {
+        to_string::<_, STATE_MESSAGE_SIZE>(message).unwrap()
+    }
+
+    fn schema_version_field() -> std::string::String {
+        std::format!(r#""schema_version":{}"#, SCHEMA_VERSION)
+    }
+
+    #[test]
+    fn state_has_the_state_tag_and_schema_version() {
+        let state = largest_state();
+        let json = line(&OutboundMessage::State(&state));
+        assert!(json.starts_with(r#"{"type":"state","#));
+        assert!(json.contains(&schema_version_field()));
+    }
+
+    #[test]
+    fn responses_are_tagged_and_carry_the_schema_version() {
+        let profile = crate::profile::create_default_profile();
+        let mut names = heapless::Vec::new();
+        names
+            .push(heapless::String::try_from("leaded.txt").unwrap())
+            .unwrap();
+        let profiles = ProfileListResponse {
+            schema_version: SCHEMA_VERSION,
+            profiles: names,
+        };
+        let active = ActiveProfileResponse {
+            schema_version: SCHEMA_VERSION,
+            checksum: profile.checksum(),
+            total_duration_s: profile.total_duration_s(),
+            active_profile: profile.clone(),
+        };
+        let preview = ProfilePreviewResponse {
+            schema_version: SCHEMA_VERSION,
+            checksum: profile.checksum(),
+            total_duration_s: profile.total_duration_s(),
+            profile: profile.clone(),
+        };
+        let setpoints = SetpointPreviewResponse {
+            schema_version: SCHEMA_VERSION,
+            samples: profile.preview(DEFAULT_PREVIEW_INTERVAL_S),
+        };
+        let schedule = RunScheduleResponse {
+            schema_version: SCHEMA_VERSION,
+            checksum: profile.checksum(),
+            total_duration_s: profile.total_duration_s(),
+            samples: profile.preview(DEFAULT_PREVIEW_INTERVAL_S),
+            profile,
+        };
+        let mut entries = heapless::Vec::new();
+        let entry = EventLogEntry {
+            at_ms: 1500,
+            event: crate::Event::StartCommand,
+        };
+        entries.push(entry).unwrap();
+        let event_log = EventLogResponse {
+            schema_version: SCHEMA_VERSION,
+            entries,
+        };
+        let heartbeat = HeartbeatResponse {
+            schema_version: SCHEMA_VERSION,
+            uptime_s: 12,
+        };
+        let relay_duty = RelayDutyResponse {
+            schema_version: SCHEMA_VERSION,
+            relay_duty: RelayDutyCounters::new(),
+        };
+
+        let messages = [
+            ("profiles", OutboundMessage::Profiles(&profiles)),
+            ("active_profile", OutboundMessage::ActiveProfile(&active)),
+            ("profile", OutboundMessage::Profile(&preview)),
+            (
+                "setpoint_preview",
+                OutboundMessage::SetpointPreview(&setpoints),
+            ),
+            ("schedule", OutboundMessage::Schedule(&schedule)),
+            ("event_log", OutboundMessage::EventLog(&event_log)),
+            ("heartbeat", OutboundMessage::Heartbeat(&heartbeat)),
+            ("relay_duty", OutboundMessage::RelayDuty(&relay_duty)),
+        ];
+        for (tag, message) in messages {
+            let json = line(&message);
+            let prefix = std::format!(r#"{{"type":"{}","#, tag);
+            assert!(json.starts_with(&prefix), "{}", json);
+            assert!(json.contains(&schema_version_field()), "{}", json);
+        }
+    }
+
+    #[test]
+    fn acks_and_events_are_tagged() {
+        let response = CommandAckResponse {
+            command: "START",
+            reason: "Door open",
+        };
+        assert_eq!(
+            line(&OutboundMessage::Ack(&response)).as_str(),
+            r#"{"type":"ack","command":"START","reason":"Door open"}"#
+        );
+        assert_eq!(
+            line(&OutboundMessage::Nak(&response)).as_str(),
+            r#"{"type":"nak","command":"START","reason":"Door open"}"#
+        );
+        assert_eq!(
+            line(&OutboundMessage::Event(&SystemEvent::SafeToOpen)).as_str(),
+            r#"{"type":"event","event":"safe_to_open"}"#
+        );
+    }
+
+    #[test]
+    fn largest_heartbeat_fits_its_buffer() {
+        let heartbeat = HeartbeatResponse {
+            schema_version: u8::MAX,
+            uptime_s: u32::MAX,
+        };
+        assert!(to_string::<_, 64>(&OutboundMessage::Heartbeat(&heartbeat)).is_ok());
+    }
+
+    #[test]
+    fn heartbeats_follow_the_interval() {
+        let mut uptimes = std::vec::Vec::new();
+        embassy_futures::block_on(embassy_futures::select::select(
+            run_heartbeat(Duration::from_millis(100), |response| {
+                uptimes.push(response.uptime_s)
+            }),
+            embassy_time::Timer::after_millis(450),
+        ));
+        // One every 100ms, the first one interval in
+        assert_eq!(uptimes.len(), 4);
+    }
+}